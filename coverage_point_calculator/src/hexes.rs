@@ -3,7 +3,7 @@ use hex_assignments::assignment::HexAssignments;
 use rust_decimal::Decimal;
 use rust_decimal_macros::dec;
 
-use crate::{BoostedHexStatus, RadioType, Result};
+use crate::{BoostedHexStatus, RadioType, Result, RuleSet};
 
 /// Breakdown of points for a hex.
 ///
@@ -57,6 +57,7 @@ pub struct CoveredHex {
 }
 
 pub(crate) fn clean_covered_hexes(
+    rule_set: &RuleSet,
     radio_type: RadioType,
     boosted_hex_status: BoostedHexStatus,
     ranked_coverage: Vec<RankedCoverage>,
@@ -76,11 +77,12 @@ pub(crate) fn clean_covered_hexes(
 
             // hip-103: if a hex is boosted by a service provider >=1x, the oracle
             // multiplier will automatically be 1x, regardless of boosted_hex_status.
-            let assignment_multiplier = if ranked.boosted.is_some() {
-                dec!(1)
-            } else {
-                ranked.assignments.boosting_multiplier()
-            };
+            let assignment_multiplier =
+                if rule_set.provider_boost_overrides_assignment && ranked.boosted.is_some() {
+                    dec!(1)
+                } else {
+                    ranked.assignments.boosting_multiplier()
+                };
 
             let base_coverage_points =
                 modeled_coverage_points * assignment_multiplier * rank_multiplier;
@@ -159,6 +161,7 @@ mod tests {
         };
 
         let covered_hexes = clean_covered_hexes(
+            &crate::rule_set::V1,
             RadioType::IndoorWifi,
             boost_status,
             vec![unboosted_coverage, boosted_coverage],
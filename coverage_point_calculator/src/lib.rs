@@ -48,6 +48,13 @@
 //!   - Radio must pass at least 1mb of data from 3 unique phones [HIP-84][provider-boosting]
 //!   - Service Provider can invalidate boosted rewards of a hotspot [HIP-125][provider-banning]
 //!
+//! ## Rule Set Versions
+//! The boosted hex eligibility rules and the HIP-103 provider boost override
+//! are versioned as a [RuleSet] with the time it activates.
+//! [CoveragePoints::new] calculates a reward period with the versions in force
+//! when it starts, see [RULE_SETS]. Every other rule is applied with its
+//! current value whatever the period.
+//!
 //! [modeled-coverage]:        https://github.com/helium/HIP/blob/main/0074-mobile-poc-modeled-coverage-rewards.md#outdoor-radios
 //! [provider-boosting]:       https://github.com/helium/HIP/blob/main/0084-service-provider-hex-boosting.md
 //! [wifi-aps]:                https://github.com/helium/HIP/blob/main/0093-addition-of-wifi-aps-to-mobile-subdao.md
@@ -64,16 +71,19 @@
 pub use crate::{
     hexes::{CoveredHex, HexPoints},
     location::{asserted_distance_to_trust_multiplier, LocationTrust},
+    rule_set::{RuleSet, RULE_SETS},
     service_provider_boosting::SPBoostedRewardEligibility,
     speedtest::{BytesPs, Speedtest, SpeedtestTier},
 };
+use chrono::{DateTime, Utc};
 use coverage_map::SignalLevel;
 use rust_decimal::Decimal;
 use rust_decimal_macros::dec;
-use service_provider_boosting::{MAX_AVERAGE_DISTANCE, MIN_WIFI_TRUST_MULTIPLIER};
+use std::ops::Range;
 
 mod hexes;
 mod location;
+mod rule_set;
 mod service_provider_boosting;
 mod speedtest;
 
@@ -129,6 +139,8 @@ pub struct CoveragePoints {
     ///
     /// Backhaul of a Radio
     pub speedtest_multiplier: Decimal,
+    /// Tier of the average of the speedtests, setting the Speedtest Multiplier
+    pub speedtest_tier: SpeedtestTier,
     /// Input Radio Type
     pub radio_type: RadioType,
     /// Input ServiceProviderBoostedRewardEligibility
@@ -141,10 +153,33 @@ pub struct CoveragePoints {
     pub location_trust_scores: Vec<LocationTrust>,
     /// Covered Hexes used in calculation
     pub covered_hexes: Vec<CoveredHex>,
+    /// Rules used in calculation
+    pub rule_set: RuleSet,
 }
 
 impl CoveragePoints {
+    /// Calculate coverage points with the rules in force for `reward_period`
     pub fn new(
+        reward_period: &Range<DateTime<Utc>>,
+        radio_type: RadioType,
+        service_provider_boosted_reward_eligibility: SPBoostedRewardEligibility,
+        speedtests: Vec<Speedtest>,
+        location_trust_scores: Vec<LocationTrust>,
+        ranked_coverage: Vec<coverage_map::RankedCoverage>,
+    ) -> Result<CoveragePoints> {
+        Self::with_rule_set(
+            RuleSet::for_period(reward_period),
+            radio_type,
+            service_provider_boosted_reward_eligibility,
+            speedtests,
+            location_trust_scores,
+            ranked_coverage,
+        )
+    }
+
+    /// Calculate coverage points with a given version of the rules
+    pub fn with_rule_set(
+        rule_set: RuleSet,
         radio_type: RadioType,
         service_provider_boosted_reward_eligibility: SPBoostedRewardEligibility,
         speedtests: Vec<Speedtest>,
@@ -154,6 +189,7 @@ impl CoveragePoints {
         let location_trust_multiplier = location::multiplier(radio_type, &location_trust_scores);
 
         let boost_eligibility = BoostedHexStatus::new(
+            &rule_set,
             radio_type,
             location_trust_multiplier,
             &location_trust_scores,
@@ -161,22 +197,25 @@ impl CoveragePoints {
         );
 
         let covered_hexes =
-            hexes::clean_covered_hexes(radio_type, boost_eligibility, ranked_coverage)?;
+            hexes::clean_covered_hexes(&rule_set, radio_type, boost_eligibility, ranked_coverage)?;
         let hex_coverage_points = hexes::calculated_coverage_points(&covered_hexes);
 
         let speedtests = speedtest::clean_speedtests(speedtests);
-        let speedtest_multiplier = speedtest::multiplier(&speedtests);
+        let speedtest_tier = speedtest::tier(&speedtests);
+        let speedtest_multiplier = speedtest_tier.multiplier();
 
         Ok(CoveragePoints {
             coverage_points: hex_coverage_points,
             location_trust_multiplier,
             speedtest_multiplier,
+            speedtest_tier,
             radio_type,
             service_provider_boosted_reward_eligibility,
             boosted_hex_eligibility: boost_eligibility,
             speedtests,
             location_trust_scores,
             covered_hexes,
+            rule_set,
         })
     }
 
@@ -237,6 +276,7 @@ pub enum BoostedHexStatus {
 
 impl BoostedHexStatus {
     fn new(
+        rule_set: &RuleSet,
         radio_type: RadioType,
         location_trust_multiplier: Decimal,
         location_trust_scores: &[LocationTrust],
@@ -244,20 +284,28 @@ impl BoostedHexStatus {
     ) -> Self {
         match service_provider_boosted_reward_eligibility {
             // hip-125: if radio has been banned by service provider, no boosting
-            SPBoostedRewardEligibility::ServiceProviderBanned => Self::ServiceProviderBanned,
+            SPBoostedRewardEligibility::ServiceProviderBanned if rule_set.service_provider_bans => {
+                Self::ServiceProviderBanned
+            }
             // hip-84: if radio has not met minimum data and subscriber thresholds, no boosting
-            SPBoostedRewardEligibility::RadioThresholdNotMet => Self::RadioThresholdNotMet,
-            SPBoostedRewardEligibility::Eligible => {
+            SPBoostedRewardEligibility::RadioThresholdNotMet if rule_set.radio_thresholds => {
+                Self::RadioThresholdNotMet
+            }
+            _ => {
                 // hip-93: if radio is wifi & location_trust score multiplier < 0.75, no boosting
-                if radio_type.is_wifi() && location_trust_multiplier < MIN_WIFI_TRUST_MULTIPLIER {
-                    return Self::WifiLocationScoreBelowThreshold(location_trust_multiplier);
+                if let Some(min_trust_multiplier) = rule_set.min_wifi_trust_multiplier {
+                    if radio_type.is_wifi() && location_trust_multiplier < min_trust_multiplier {
+                        return Self::WifiLocationScoreBelowThreshold(location_trust_multiplier);
+                    }
                 }
 
                 // hip-119: if the average distance to asserted is beyond 50m, no boosting
-                let average_distance =
-                    location::average_distance(radio_type, location_trust_scores);
-                if average_distance > MAX_AVERAGE_DISTANCE {
-                    return Self::AverageAssertedDistanceOverLimit(average_distance);
+                if let Some(max_distance) = rule_set.max_boosted_average_distance {
+                    let average_distance =
+                        location::average_distance(radio_type, location_trust_scores);
+                    if average_distance > max_distance {
+                        return Self::AverageAssertedDistanceOverLimit(average_distance);
+                    }
                 }
 
                 Self::Eligible
@@ -357,6 +405,7 @@ mod tests {
         #[case] expected_points: Decimal,
     ) {
         let wifi = CoveragePoints::new(
+            &reward_period(),
             RadioType::IndoorWifi,
             SPBoostedRewardEligibility::Eligible,
             speedtest_maximum(),
@@ -378,10 +427,46 @@ mod tests {
         assert_eq!(expected_points, wifi.coverage_points_v1());
     }
 
+    #[test]
+    fn rule_set_without_hip_103_keeps_oracle_boost_for_provider_boosted_hexes() {
+        let calculate_wifi = |rule_set: RuleSet| {
+            CoveragePoints::with_rule_set(
+                rule_set,
+                RadioType::IndoorWifi,
+                SPBoostedRewardEligibility::Eligible,
+                speedtest_maximum(),
+                location_trust_maximum(),
+                vec![RankedCoverage {
+                    hotspot_key: pubkey(),
+                    cbsd_id: None,
+                    hex: hex_location(),
+                    rank: 1,
+                    signal_level: SignalLevel::High,
+                    assignments: assignments_from(Assignment::C),
+                    boosted: NonZeroU32::new(5),
+                }],
+            )
+            .unwrap()
+        };
+
+        let with_hip_103 = calculate_wifi(rule_set::V1);
+        let without_hip_103 = calculate_wifi(RuleSet {
+            name: "without-hip-103",
+            provider_boost_overrides_assignment: false,
+            ..rule_set::V1
+        });
+
+        assert_eq!("v1", with_hip_103.rule_set.name);
+        assert_eq!(dec!(2000), with_hip_103.coverage_points_v1());
+        // The worst oracle boosting assignment applies to the boosted hex
+        assert_eq!(dec!(0), without_hip_103.coverage_points_v1());
+    }
+
     #[test]
     fn hip_84_radio_meets_minimum_subscriber_threshold_for_boosted_hexes() {
         let calculate_wifi = |eligibility: SPBoostedRewardEligibility| {
             CoveragePoints::new(
+                &reward_period(),
                 RadioType::IndoorWifi,
                 eligibility,
                 speedtest_maximum(),
@@ -418,6 +503,7 @@ mod tests {
     fn hip_93_wifi_with_low_location_score_receives_no_boosted_hexes() {
         let calculate_wifi = |location_trust_scores: Vec<LocationTrust>| {
             CoveragePoints::new(
+                &reward_period(),
                 RadioType::IndoorWifi,
                 SPBoostedRewardEligibility::Eligible,
                 speedtest_maximum(),
@@ -456,6 +542,7 @@ mod tests {
     fn hip_119_radio_with_past_50m_from_asserted_receives_no_boosted_hexes() {
         let calculate_wifi = |location_trust_scores: Vec<LocationTrust>| {
             CoveragePoints::new(
+                &reward_period(),
                 RadioType::IndoorWifi,
                 SPBoostedRewardEligibility::Eligible,
                 speedtest_maximum(),
@@ -492,6 +579,7 @@ mod tests {
     fn speedtests_effect_reward_shares() {
         let calculate_indoor_cbrs = |speedtests: Vec<Speedtest>| {
             CoveragePoints::new(
+                &reward_period(),
                 RadioType::IndoorCbrs,
                 SPBoostedRewardEligibility::Eligible,
                 speedtests,
@@ -580,6 +668,7 @@ mod tests {
 
         use Assignment::*;
         let indoor_cbrs = CoveragePoints::new(
+            &reward_period(),
             RadioType::IndoorCbrs,
             SPBoostedRewardEligibility::Eligible,
             speedtest_maximum(),
@@ -637,6 +726,7 @@ mod tests {
         #[case] expected_points: Decimal,
     ) {
         let outdoor_wifi = CoveragePoints::new(
+            &reward_period(),
             radio_type,
             SPBoostedRewardEligibility::Eligible,
             speedtest_maximum(),
@@ -666,6 +756,7 @@ mod tests {
         #[case] expected_points: Decimal,
     ) {
         let indoor_wifi = CoveragePoints::new(
+            &reward_period(),
             radio_type,
             SPBoostedRewardEligibility::Eligible,
             speedtest_maximum(),
@@ -709,6 +800,7 @@ mod tests {
     fn location_trust_score_multiplier() {
         // Location scores are averaged together
         let indoor_wifi = CoveragePoints::new(
+            &reward_period(),
             RadioType::IndoorWifi,
             SPBoostedRewardEligibility::Eligible,
             speedtest_maximum(),
@@ -753,6 +845,7 @@ mod tests {
             },
         ];
         let indoor_wifi = CoveragePoints::new(
+            &reward_period(),
             RadioType::IndoorWifi,
             SPBoostedRewardEligibility::Eligible,
             speedtest_maximum(),
@@ -776,6 +869,7 @@ mod tests {
         #[case] expected: Decimal,
     ) {
         let outdoor_cbrs = CoveragePoints::new(
+            &reward_period(),
             RadioType::OutdoorCbrs,
             SPBoostedRewardEligibility::Eligible,
             speedtest_maximum(),
@@ -803,6 +897,7 @@ mod tests {
         #[case] expected: Decimal,
     ) {
         let indoor_cbrs = CoveragePoints::new(
+            &reward_period(),
             RadioType::IndoorCbrs,
             SPBoostedRewardEligibility::Eligible,
             speedtest_maximum(),
@@ -832,6 +927,7 @@ mod tests {
         #[case] expected: Decimal,
     ) {
         let outdoor_wifi = CoveragePoints::new(
+            &reward_period(),
             RadioType::OutdoorWifi,
             SPBoostedRewardEligibility::Eligible,
             speedtest_maximum(),
@@ -859,6 +955,7 @@ mod tests {
         #[case] expected: Decimal,
     ) {
         let indoor_wifi = CoveragePoints::new(
+            &reward_period(),
             RadioType::IndoorWifi,
             SPBoostedRewardEligibility::Eligible,
             speedtest_maximum(),
@@ -887,6 +984,7 @@ mod tests {
 
         let wifi_bad_trust_score = |sp_status: SPBoostedRewardEligibility| {
             BoostedHexStatus::new(
+                &rule_set::V3,
                 RadioType::IndoorWifi,
                 location::multiplier(RadioType::IndoorWifi, &bad_location),
                 &bad_location,
@@ -988,4 +1086,9 @@ mod tests {
     fn pubkey() -> Vec<u8> {
         vec![1]
     }
+
+    fn reward_period() -> Range<DateTime<Utc>> {
+        let now = Utc::now();
+        now - chrono::Duration::days(1)..now
+    }
 }
@@ -0,0 +1,187 @@
+//! Versions of the boosted hex eligibility rules of the rewards algorithm.
+//!
+//! Changes to these rules are released as a new [RuleSet] activating at a
+//! point in time instead of by changing an existing version. A reward period
+//! is calculated with the rules in force when it starts, so recalculating a
+//! past period applies the eligibility rules it was rewarded with.
+//!
+//! To release a change, add a version to [RULE_SETS] with the timestamp the
+//! change activates at, along with a comment giving the source of that
+//! timestamp.
+//!
+//! Only the rules that are [RuleSet] fields are versioned: boosted hex
+//! eligibility and the HIP-103 provider boost override. Rank multipliers,
+//! speedtest tiers, location trust weighting and oracle assignment
+//! multipliers are not, every period is calculated with their current
+//! values, so recalculating a period from before one of them changed does
+//! not reproduce its rewards. A rule has to become a field before it can
+//! change.
+use std::ops::Range;
+
+use chrono::{DateTime, Utc};
+use rust_decimal::Decimal;
+
+use crate::service_provider_boosting::{MAX_AVERAGE_DISTANCE, MIN_WIFI_TRUST_MULTIPLIER};
+
+/// A version of the rules, in force from its activation until the next
+/// version activates
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RuleSet {
+    /// Name of the version
+    pub name: &'static str,
+    /// Unix timestamp in seconds from which reward periods are calculated
+    /// with this version
+    pub activation_timestamp: i64,
+    /// Radios not meeting the minimum data and subscriber thresholds receive
+    /// no boosted rewards [HIP-84][provider-boosting]
+    ///
+    /// [provider-boosting]: https://github.com/helium/HIP/blob/main/0084-service-provider-hex-boosting.md
+    pub radio_thresholds: bool,
+    /// Wifi radios with a lower location trust multiplier receive no boosted
+    /// rewards [HIP-93][wifi-aps]
+    ///
+    /// [wifi-aps]: https://github.com/helium/HIP/blob/main/0093-addition-of-wifi-aps-to-mobile-subdao.md
+    pub min_wifi_trust_multiplier: Option<Decimal>,
+    /// Provider boosted hexes receive an oracle assignment multiplier of 1x
+    /// [HIP-103][oracle-boosting]
+    ///
+    /// [oracle-boosting]: https://github.com/helium/HIP/blob/main/0103-oracle-hex-boosting.md
+    pub provider_boost_overrides_assignment: bool,
+    /// Radios with a larger average distance to their asserted location in
+    /// meters receive no boosted rewards [HIP-119][location-gaming]
+    ///
+    /// [location-gaming]: https://github.com/helium/HIP/blob/main/0119-closing-gaming-loopholes-within-the-mobile-network.md
+    pub max_boosted_average_distance: Option<Decimal>,
+    /// Service providers can ban radios from boosted rewards
+    /// [HIP-125][provider-banning]
+    ///
+    /// [provider-banning]: https://github.com/helium/HIP/blob/main/0125-temporary-anti-gaming-measures-for-boosted-hexes.md
+    pub service_provider_bans: bool,
+}
+
+/// Boosted hexes restricted by radio thresholds and wifi location trust,
+/// provider boosts override oracle assignments.
+///
+/// The baseline versioning started from rather than a release: it applies
+/// from timestamp 0, so every period before [V2] is calculated with it,
+/// including periods that predate HIP-103 and the rules it enables.
+pub const V1: RuleSet = RuleSet {
+    name: "v1",
+    activation_timestamp: 0,
+    radio_thresholds: true,
+    min_wifi_trust_multiplier: Some(MIN_WIFI_TRUST_MULTIPLIER),
+    provider_boost_overrides_assignment: true,
+    max_boosted_average_distance: None,
+    service_provider_bans: false,
+};
+
+/// HIP-119, boosted hexes restricted by the average distance to the
+/// asserted location
+pub const V2: RuleSet = RuleSet {
+    name: "v2",
+    // 2024-07-01 00:00 UTC, the start of the first reward period the
+    // asserted distance limit applies to. Source: HIP-119, linked from
+    // `RuleSet::max_boosted_average_distance`.
+    activation_timestamp: 1_719_792_000,
+    max_boosted_average_distance: Some(MAX_AVERAGE_DISTANCE),
+    ..V1
+};
+
+/// HIP-125, service providers can ban radios from boosted rewards
+pub const V3: RuleSet = RuleSet {
+    name: "v3",
+    // 2024-09-01 00:00 UTC, the start of the first reward period service
+    // provider bans apply to. Source: HIP-125, linked from
+    // `RuleSet::service_provider_bans`.
+    activation_timestamp: 1_725_148_800,
+    service_provider_bans: true,
+    ..V2
+};
+
+/// Every version by activation, oldest first
+pub const RULE_SETS: &[RuleSet] = &[V1, V2, V3];
+
+impl RuleSet {
+    /// The rules in force for `reward_period`
+    pub fn for_period(reward_period: &Range<DateTime<Utc>>) -> Self {
+        Self::at(reward_period.start)
+    }
+
+    /// The rules in force at `timestamp`
+    pub fn at(timestamp: DateTime<Utc>) -> Self {
+        select(RULE_SETS, timestamp)
+    }
+
+    /// The version named `name`, to recalculate with specific rules
+    pub fn by_name(name: &str) -> Option<Self> {
+        RULE_SETS
+            .iter()
+            .find(|rule_set| rule_set.name == name)
+            .copied()
+    }
+}
+
+fn select(rule_sets: &[RuleSet], timestamp: DateTime<Utc>) -> RuleSet {
+    rule_sets
+        .iter()
+        .rev()
+        .find(|rule_set| rule_set.activation_timestamp <= timestamp.timestamp())
+        .copied()
+        // periods before the first activation use the oldest rules
+        .unwrap_or(rule_sets[0])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn versions_are_ordered_by_activation() {
+        assert!(RULE_SETS
+            .windows(2)
+            .all(|pair| pair[0].activation_timestamp < pair[1].activation_timestamp));
+    }
+
+    #[test]
+    fn selects_rules_in_force_at_timestamp() {
+        let before_hip_119 = Utc.with_ymd_and_hms(2024, 6, 30, 23, 59, 59).unwrap();
+        let hip_119 = Utc.with_ymd_and_hms(2024, 7, 1, 0, 0, 0).unwrap();
+        let hip_125 = Utc.with_ymd_and_hms(2024, 9, 1, 0, 0, 0).unwrap();
+
+        assert_eq!(V1, RuleSet::at(DateTime::UNIX_EPOCH));
+        assert_eq!(V1, RuleSet::at(before_hip_119));
+        assert_eq!(V2, RuleSet::at(hip_119));
+        assert_eq!(V3, RuleSet::at(hip_125));
+        assert_eq!(V3, RuleSet::at(Utc::now()));
+
+        let period = before_hip_119 - chrono::Duration::days(1)..before_hip_119;
+        assert_eq!(
+            None,
+            RuleSet::for_period(&period).max_boosted_average_distance
+        );
+        assert!(!RuleSet::for_period(&period).service_provider_bans);
+    }
+
+    #[test]
+    fn selects_oldest_rules_before_first_activation() {
+        let rule_sets = [
+            RuleSet {
+                activation_timestamp: 50,
+                ..V1
+            },
+            RuleSet {
+                name: "v2",
+                activation_timestamp: 100,
+                ..V1
+            },
+        ];
+        assert_eq!("v1", select(&rule_sets, DateTime::UNIX_EPOCH).name);
+    }
+
+    #[test]
+    fn finds_rules_by_name() {
+        assert_eq!(Some(V2), RuleSet::by_name("v2"));
+        assert_eq!(None, RuleSet::by_name("v0"));
+    }
+}
@@ -39,13 +39,13 @@ pub(crate) fn clean_speedtests(speedtests: Vec<Speedtest>) -> Vec<Speedtest> {
     cleaned
 }
 
-pub(crate) fn multiplier(speedtests: &[Speedtest]) -> Decimal {
+pub(crate) fn tier(speedtests: &[Speedtest]) -> SpeedtestTier {
     if speedtests.len() < MIN_REQUIRED_SPEEDTEST_SAMPLES {
-        return dec!(0);
+        return SpeedtestTier::Fail;
     }
 
     let avg = Speedtest::avg(speedtests);
-    avg.multiplier()
+    avg.tier()
 }
 
 #[derive(Debug, Clone, Copy, PartialEq)]
@@ -86,12 +86,15 @@ impl Speedtest {
     }
 
     pub fn multiplier(&self) -> Decimal {
+        self.tier().multiplier()
+    }
+
+    pub fn tier(&self) -> SpeedtestTier {
         let upload = SpeedtestTier::from_upload(self.upload_speed);
         let download = SpeedtestTier::from_download(self.download_speed);
         let latency = SpeedtestTier::from_latency(self.latency_millis);
 
-        let tier = upload.min(download).min(latency);
-        tier.multiplier()
+        upload.min(download).min(latency)
     }
 
     pub fn avg(speedtests: &[Self]) -> Self {
@@ -207,11 +210,11 @@ mod tests {
 
         assert_eq!(
             dec!(0),
-            multiplier(&speedtests(MIN_REQUIRED_SPEEDTEST_SAMPLES - 1))
+            tier(&speedtests(MIN_REQUIRED_SPEEDTEST_SAMPLES - 1)).multiplier()
         );
         assert_eq!(
             dec!(1),
-            multiplier(&speedtests(MIN_REQUIRED_SPEEDTEST_SAMPLES))
+            tier(&speedtests(MIN_REQUIRED_SPEEDTEST_SAMPLES)).multiplier()
         );
     }
 
@@ -262,7 +265,7 @@ mod tests {
         ]);
 
         // Old speedtests should be unused
-        assert_eq!(dec!(1), multiplier(&speedtests));
+        assert_eq!(dec!(1), tier(&speedtests).multiplier());
     }
 
     #[test]
@@ -51,6 +51,7 @@ fn base_radio_coverage_points() {
         (RadioType::OutdoorCbrs, dec!(4)),
     ] {
         let coverage_points = CoveragePoints::new(
+            &reward_period(),
             radio_type,
             SPBoostedRewardEligibility::Eligible,
             speedtests.clone(),
@@ -112,6 +113,7 @@ fn radios_with_coverage() {
         (RadioType::OutdoorCbrs, 100),
     ] {
         let coverage_points = CoveragePoints::new(
+            &reward_period(),
             radio_type,
             SPBoostedRewardEligibility::Eligible,
             default_speedtests.clone(),
@@ -239,6 +241,7 @@ fn cbrs_outdoor_with_mixed_signal_level_coverage() -> Result {
     // Outdoor Cbrs with mixed signal level coverage
 
     let radio = CoveragePoints::new(
+        &reward_period(),
         RadioType::OutdoorCbrs,
         SPBoostedRewardEligibility::Eligible,
         Speedtest::mock(SpeedtestTier::Good),
@@ -371,6 +374,7 @@ fn indoor_cbrs_radio(
     coverage: &[RankedCoverage],
 ) -> Result<CoveragePoints> {
     CoveragePoints::new(
+        &reward_period(),
         RadioType::IndoorCbrs,
         SPBoostedRewardEligibility::Eligible,
         Speedtest::mock(speedtest_tier),
@@ -384,6 +388,7 @@ fn outdoor_cbrs_radio(
     coverage: &[RankedCoverage],
 ) -> Result<CoveragePoints> {
     CoveragePoints::new(
+        &reward_period(),
         RadioType::OutdoorCbrs,
         SPBoostedRewardEligibility::Eligible,
         Speedtest::mock(speedtest_tier),
@@ -392,6 +397,11 @@ fn outdoor_cbrs_radio(
     )
 }
 
+fn reward_period() -> std::ops::Range<chrono::DateTime<Utc>> {
+    let now = Utc::now();
+    now - chrono::Duration::days(1)..now
+}
+
 struct NoBoostedHexes;
 impl BoostedHexMap for NoBoostedHexes {
     fn get_current_multiplier(
@@ -5,6 +5,7 @@ mod metric_tracker;
 mod settings;
 
 pub use error::{Error, Result};
+pub use metric_tracker::health_check;
 pub use settings::Settings;
 
 pub mod meta;
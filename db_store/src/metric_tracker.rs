@@ -1,6 +1,12 @@
 use std::time::Duration;
+use task_manager::health::CheckStatus;
+use tokio::sync::watch;
 
 const DURATION: Duration = Duration::from_secs(300);
+/// How often the health check probes the database
+const CHECK_INTERVAL: Duration = Duration::from_secs(30);
+/// How long a probe may take before the database is deemed unhealthy
+const CHECK_TIMEOUT: Duration = Duration::from_secs(5);
 
 pub async fn start(app_name: &str, pool: sqlx::Pool<sqlx::Postgres>) {
     let pool_size_name = format!("{app_name}_db_pool_size");
@@ -18,3 +24,34 @@ async fn run(size_name: String, idle_name: String, pool: sqlx::Pool<sqlx::Postgr
         metrics::gauge!(idle_name.clone()).set(pool.num_idle() as f64);
     }
 }
+
+/// Health check reporting whether the database answers queries. The pool is
+/// probed with a `SELECT 1` every `CHECK_INTERVAL` in the background and the
+/// check reports the outcome of the latest probe.
+pub fn health_check(
+    pool: sqlx::Pool<sqlx::Postgres>,
+) -> impl Fn() -> CheckStatus + Send + Sync + 'static {
+    let (status_tx, status_rx) = watch::channel(CheckStatus::unhealthy("database not checked yet"));
+    tokio::spawn(async move {
+        let mut trigger = tokio::time::interval(CHECK_INTERVAL);
+        loop {
+            trigger.tick().await;
+            let status = probe(&pool).await;
+            // Stop probing once the check is dropped
+            if status_tx.send(status).is_err() {
+                break;
+            }
+        }
+    });
+
+    move || status_rx.borrow().clone()
+}
+
+async fn probe(pool: &sqlx::Pool<sqlx::Postgres>) -> CheckStatus {
+    let detail = format!("{} connections, {} idle", pool.size(), pool.num_idle());
+    match tokio::time::timeout(CHECK_TIMEOUT, sqlx::query("SELECT 1").execute(pool)).await {
+        Ok(Ok(_)) => CheckStatus::healthy(detail),
+        Ok(Err(err)) => CheckStatus::unhealthy(format!("query failed: {err}, {detail}")),
+        Err(_) => CheckStatus::unhealthy(format!("query timed out, {detail}")),
+    }
+}
@@ -0,0 +1,203 @@
+use crate::{Error, Result};
+use sha2::{Digest, Sha256};
+use std::{
+    fmt, io,
+    path::{Path, PathBuf},
+    pin::Pin,
+    task::{Context, Poll},
+};
+use tokio::{
+    fs::File,
+    io::{AsyncReadExt, AsyncWrite},
+};
+
+/// Object metadata entry holding the checksum of a stored file
+pub const METADATA_KEY: &str = "sha256";
+/// Extension of the optional sidecar file holding the checksum of a file
+pub const SIDECAR_EXTENSION: &str = ".sha256";
+
+/// Hex encoded SHA-256 of the stored (compressed) content of a file
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Checksum(String);
+
+impl Checksum {
+    pub fn compute(data: &[u8]) -> Self {
+        Self::from_hasher(Sha256::new_with_prefix(data))
+    }
+
+    pub async fn of_file(path: &Path) -> Result<Self> {
+        let mut file = File::open(path).await?;
+        let mut hasher = Sha256::new();
+        let mut buf = vec![0; 64 * 1024];
+        loop {
+            let read = file.read(&mut buf).await?;
+            if read == 0 {
+                break;
+            }
+            hasher.update(&buf[..read]);
+        }
+        Ok(Self::from_hasher(hasher))
+    }
+
+    pub fn from_hex(hex: &str) -> Self {
+        Self(hex.trim().to_lowercase())
+    }
+
+    fn from_hasher(hasher: Sha256) -> Self {
+        Self(format!("{:x}", hasher.finalize()))
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    /// Check that `data`, the content of the file `key`, matches
+    pub fn verify(&self, key: &str, data: &[u8]) -> Result {
+        self.check(key, Self::compute(data))
+    }
+
+    /// Verify the content of the file `key` as it is read
+    pub fn verifier(&self, key: impl Into<String>) -> Verifier {
+        Verifier {
+            key: key.into(),
+            expected: self.clone(),
+            hasher: Sha256::new(),
+        }
+    }
+
+    fn check(&self, key: &str, actual: Self) -> Result {
+        if &actual == self {
+            Ok(())
+        } else {
+            Err(Error::ChecksumMismatch(
+                key.to_string(),
+                self.0.clone(),
+                actual.0,
+            ))
+        }
+    }
+}
+
+impl fmt::Display for Checksum {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+pub fn sidecar_key(key: &str) -> String {
+    format!("{key}{SIDECAR_EXTENSION}")
+}
+
+pub fn sidecar_path(path: &Path) -> PathBuf {
+    let mut sidecar = path.as_os_str().to_owned();
+    sidecar.push(SIDECAR_EXTENSION);
+    PathBuf::from(sidecar)
+}
+
+/// Sidecar keys share the prefix of the file they belong to and have to be
+/// left out of listings
+pub fn is_sidecar(key: &str) -> bool {
+    key.ends_with(SIDECAR_EXTENSION)
+}
+
+/// Hashes the content of a file as it is read, checking it against the
+/// stored checksum once the file has been read in full
+#[derive(Debug)]
+pub struct Verifier {
+    key: String,
+    expected: Checksum,
+    hasher: Sha256,
+}
+
+impl Verifier {
+    pub fn update(&mut self, data: &[u8]) {
+        self.hasher.update(data);
+    }
+
+    /// Check everything read so far is the content of the file
+    pub fn finish(self) -> Result {
+        self.expected
+            .check(&self.key, Checksum::from_hasher(self.hasher))
+    }
+}
+
+/// Writer hashing everything written through it
+#[derive(Debug)]
+pub struct HashingWriter<W> {
+    inner: W,
+    hasher: Sha256,
+}
+
+impl<W> HashingWriter<W> {
+    pub fn new(inner: W) -> Self {
+        Self {
+            inner,
+            hasher: Sha256::new(),
+        }
+    }
+
+    /// Checksum of everything written so far
+    pub fn checksum(&self) -> Checksum {
+        Checksum::from_hasher(self.hasher.clone())
+    }
+
+    pub fn into_inner(self) -> W {
+        self.inner
+    }
+}
+
+impl<W> AsyncWrite for HashingWriter<W>
+where
+    W: AsyncWrite + Unpin,
+{
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        let this = self.get_mut();
+        let written = futures::ready!(Pin::new(&mut this.inner).poll_write(cx, buf))?;
+        this.hasher.update(&buf[..written]);
+        Poll::Ready(Ok(written))
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_flush(cx)
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.get_mut().inner).poll_shutdown(cx)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::AsyncWriteExt;
+
+    #[tokio::test]
+    async fn hashing_writer_matches_content_checksum() {
+        let mut writer = HashingWriter::new(Vec::new());
+        writer.write_all(b"hello ").await.unwrap();
+        writer.write_all(b"world").await.unwrap();
+        let checksum = writer.checksum();
+        let content = writer.into_inner();
+
+        assert_eq!(Checksum::compute(&content), checksum);
+        assert_eq!(
+            "b94d27b9934d3e08a52e52d7da7dabfac484efe37a5380ee9088f7ace2efcde9",
+            checksum.as_str()
+        );
+        assert!(checksum.verify("key", b"hello world").is_ok());
+        assert!(checksum.verify("key", b"hello").is_err());
+
+        let mut verifier = checksum.verifier("key");
+        verifier.update(b"hello ");
+        verifier.update(b"world");
+        assert!(verifier.finish().is_ok());
+
+        let mut verifier = checksum.verifier("key");
+        verifier.update(b"hello");
+        assert!(verifier.finish().is_err());
+    }
+}
@@ -1,24 +1,32 @@
 use crate::{
+    checksum::{self, Checksum},
+    cli::{predicate::Predicate, print_json},
     heartbeat::{cli::ValidatedHeartbeat, CbrsHeartbeat},
     iot_beacon_report::IotBeaconIngestReport,
     iot_valid_poc::IotPoc,
     iot_witness_report::IotWitnessIngestReport,
+    local_store::LocalStore,
     mobile_radio_invalidated_threshold::VerifiedInvalidatedRadioThresholdIngestReport,
     mobile_radio_threshold::VerifiedRadioThresholdIngestReport,
+    proto_json,
     speedtest::{cli::SpeedtestAverage, CellSpeedtest},
     traits::MsgDecode,
-    Error, FileInfoStream, FileStore, FileType, Result, Settings,
+    Error, FileInfo, FileInfoStream, FileStore, FileType, Result, Settings,
 };
 use chrono::{NaiveDateTime, TimeZone, Utc};
 use futures::{stream::TryStreamExt, StreamExt, TryFutureExt};
 use helium_crypto::PublicKey;
 use serde::{ser::SerializeSeq, Serializer};
 use std::{
+    collections::HashMap,
     io,
     path::{Path, PathBuf},
     str::FromStr,
 };
-use tokio::fs;
+use tokio::{
+    fs,
+    io::{AsyncReadExt, AsyncWriteExt},
+};
 
 /// Commands on remote buckets
 #[derive(Debug, clap::Args)]
@@ -34,6 +42,9 @@ pub enum BucketCmd {
     Put(Put),
     Get(Get),
     Locate(Locate),
+    Mirror(Mirror),
+    Grep(Grep),
+    Verify(Verify),
 }
 
 impl Cmd {
@@ -50,6 +61,9 @@ impl BucketCmd {
             Self::Put(cmd) => cmd.run(settings).await,
             Self::Get(cmd) => cmd.run(settings).await,
             Self::Locate(cmd) => cmd.run(settings).await,
+            Self::Mirror(cmd) => cmd.run(settings).await,
+            Self::Grep(cmd) => cmd.run(settings).await,
+            Self::Verify(cmd) => cmd.run(settings).await,
         }
     }
 }
@@ -175,6 +189,122 @@ impl Get {
     }
 }
 
+/// Mirror the files of a time range to another bucket or a local directory.
+/// Files already at the destination with the expected size are skipped, so
+/// an interrupted mirror can be resumed by running it again.
+#[derive(Debug, clap::Args)]
+pub struct Mirror {
+    #[clap(flatten)]
+    filter: FileFilter,
+    /// Configuration file for the destination bucket. Defaults to the source
+    /// configuration
+    #[clap(long)]
+    dest_config: Option<PathBuf>,
+    /// Destination bucket, overriding the one in the destination
+    /// configuration
+    #[clap(long)]
+    dest_bucket: Option<String>,
+    /// Local directory to mirror files to instead of a bucket
+    #[clap(
+        long,
+        conflicts_with_all = ["dest_config", "dest_bucket"],
+        required_unless_present_any = ["dest_config", "dest_bucket"]
+    )]
+    dest_dir: Option<PathBuf>,
+    /// Directory downloaded files are staged in before being stored.
+    /// Defaults to a directory in the system temp directory
+    #[clap(long)]
+    staging_dir: Option<PathBuf>,
+    /// Number of files to transfer concurrently
+    #[clap(long, default_value_t = 5)]
+    concurrency: usize,
+}
+
+impl Mirror {
+    pub async fn run(&self, settings: &Settings) -> Result {
+        let source = FileStore::from_settings(settings).await?;
+        let dest = self.dest_store(settings).await?;
+        let staging_dir = self
+            .staging_dir
+            .clone()
+            .unwrap_or_else(|| std::env::temp_dir().join("file-store-mirror"));
+        fs::create_dir_all(&staging_dir).await?;
+
+        let mirrored: HashMap<String, usize> = self
+            .filter
+            .list(&dest)
+            .map_ok(|info| (info.key, info.size))
+            .try_collect()
+            .await?;
+
+        let mut copied = 0;
+        let mut skipped = 0;
+        let mut transfers = self
+            .filter
+            .list(&source)
+            .try_filter(|info| {
+                let done = mirrored.get(&info.key) == Some(&info.size);
+                if done {
+                    skipped += 1;
+                }
+                futures::future::ready(!done)
+            })
+            .map_ok(|info| mirror_file(&source, &dest, &staging_dir, info))
+            .try_buffer_unordered(self.concurrency);
+        while let Some(key) = transfers.try_next().await? {
+            tracing::info!("mirrored {key}");
+            copied += 1;
+        }
+
+        print_json(&serde_json::json!({
+            "copied": copied,
+            "skipped": skipped,
+        }))
+    }
+
+    async fn dest_store(&self, settings: &Settings) -> Result<FileStore> {
+        if let Some(dir) = &self.dest_dir {
+            return Ok(FileStore::with_backend(
+                settings.bucket.clone(),
+                LocalStore::new(dir),
+            ));
+        }
+        let mut dest_settings = match &self.dest_config {
+            Some(path) => Settings::new(path)?,
+            None => settings.clone(),
+        };
+        if let Some(bucket) = &self.dest_bucket {
+            dest_settings.bucket = bucket.clone();
+        }
+        FileStore::from_settings(&dest_settings).await
+    }
+}
+
+/// Copy a file to the destination store through the staging directory,
+/// verifying the downloaded size against the listed one
+async fn mirror_file(
+    source: &FileStore,
+    dest: &FileStore,
+    staging_dir: &Path,
+    info: FileInfo,
+) -> Result<String> {
+    let path = staging_dir.join(&info.key);
+    let mut file = fs::File::create(&path).await?;
+    let stream = source.get_raw(info.key.clone()).await?;
+    let mut reader = tokio_util::io::StreamReader::new(stream);
+    let size = tokio::io::copy(&mut reader, &mut file).await? as usize;
+    file.flush().await?;
+    drop(file);
+
+    if size != info.size {
+        fs::remove_file(&path).await?;
+        return Err(Error::SizeMismatch(info.key, info.size, size));
+    }
+    dest.put(&path).await?;
+    fs::remove_file(&path).await?;
+    Ok(info.key)
+}
+
 /// Locate specific records in a time range
 #[derive(Debug, clap::Args)]
 pub struct Locate {
@@ -208,6 +338,104 @@ impl Locate {
     }
 }
 
+/// Print the records in a time range that match a predicate as
+/// newline delimited JSON
+#[derive(Debug, clap::Args)]
+pub struct Grep {
+    /// Expression records have to match, for example
+    /// `cbsd_id == "P27-SCE4255W" && signal_level >= 2`. Fields are matched
+    /// at any depth of the record, public keys are compared as b58 and
+    /// timestamps as RFC3339.
+    predicate: Predicate,
+
+    #[clap(flatten)]
+    filter: FileFilter,
+}
+
+impl Grep {
+    pub async fn run(&self, settings: &Settings) -> Result {
+        let store = FileStore::from_settings(settings).await?;
+        let file_type = FileType::from_str(&self.filter.prefix)?;
+        let mut records = store.source(self.filter.list(&store));
+        while let Some(buf) = records.try_next().await? {
+            let record = proto_json::decode(file_type, &buf)?;
+            if self.predicate.matches(&record) {
+                println!("{}", serde_json::to_string(&record)?);
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Verify the files in a time range against the checksums they were stored
+/// with, printing the result for each file as newline delimited JSON. Files
+/// without checksum metadata are checked against their sidecar object if
+/// there is one.
+#[derive(Debug, clap::Args)]
+pub struct Verify {
+    #[clap(flatten)]
+    filter: FileFilter,
+}
+
+impl Verify {
+    pub async fn run(&self, settings: &Settings) -> Result {
+        let store = FileStore::from_settings(settings).await?;
+        let mut file_infos = self.filter.list(&store);
+        let mut mismatches = 0;
+        while let Some(info) = file_infos.try_next().await? {
+            let (stream, checksum) = store.get_checksummed(info.key.clone()).await?;
+            let expected = match checksum {
+                Some(checksum) => Some(checksum),
+                None => get_sidecar(&store, &info.key).await?,
+            };
+            let actual = Checksum::compute(&read_all(stream).await?);
+            let status = match &expected {
+                Some(expected) if expected == &actual => "ok",
+                Some(_) => {
+                    mismatches += 1;
+                    "mismatch"
+                }
+                None => "missing",
+            };
+            println!(
+                "{}",
+                serde_json::json!({
+                    "key": info.key,
+                    "status": status,
+                    "expected": expected.as_ref().map(Checksum::as_str),
+                    "actual": actual.as_str(),
+                })
+            );
+        }
+        if mismatches > 0 {
+            return Err(Error::from(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{mismatches} files failed checksum verification"),
+            )));
+        }
+        Ok(())
+    }
+}
+
+async fn get_sidecar(store: &FileStore, key: &str) -> Result<Option<Checksum>> {
+    match store.get_raw(checksum::sidecar_key(key)).await {
+        Ok(stream) => {
+            let hex = read_all(stream).await?;
+            Ok(Some(Checksum::from_hex(&String::from_utf8_lossy(&hex))))
+        }
+        Err(Error::NotFound(_) | Error::Aws(aws_sdk_s3::Error::NoSuchKey(_))) => Ok(None),
+        Err(err) => Err(err),
+    }
+}
+
+async fn read_all(stream: aws_sdk_s3::types::ByteStream) -> Result<Vec<u8>> {
+    let mut data = vec![];
+    tokio_util::io::StreamReader::new(stream)
+        .read_to_end(&mut data)
+        .await?;
+    Ok(data)
+}
+
 fn locate(prefix: &str, gateway: &PublicKey, buf: &[u8]) -> Result<Option<serde_json::Value>> {
     let pub_key = gateway.to_vec();
     match FileType::from_str(prefix)? {
@@ -329,3 +557,63 @@ impl Gateway for VerifiedInvalidatedRadioThresholdIngestReport {
         self.report.report.hotspot_pubkey.as_ref() == pub_key
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::StoreBackend;
+    use tempfile::TempDir;
+
+    #[tokio::test]
+    async fn mirror_copies_missing_and_incomplete_files() {
+        let src_dir = TempDir::new().expect("Unable to create temp dir");
+        let root_dir = TempDir::new().expect("Unable to create temp dir");
+        let dest_dir = TempDir::new().expect("Unable to create temp dir");
+        let settings = Settings {
+            bucket: "source".to_string(),
+            endpoint: None,
+            region: "us-east-1".to_string(),
+            access_key_id: None,
+            secret_access_key: None,
+            backend: StoreBackend::Local,
+            local_root: Some(root_dir.path().to_path_buf()),
+            checksum_sidecar: false,
+            compression: Default::default(),
+        };
+        let source = FileStore::from_settings(&settings).await.unwrap();
+
+        let now = Utc::now();
+        let infos = [
+            FileInfo::from((FileType::EntropyReport, now - chrono::Duration::hours(2))),
+            FileInfo::from((FileType::EntropyReport, now - chrono::Duration::hours(1))),
+        ];
+        for info in &infos {
+            let path = src_dir.path().join(&info.key);
+            fs::write(&path, b"entropy").await.unwrap();
+            source.put(&path).await.unwrap();
+        }
+        // An interrupted earlier mirror left a truncated copy of the first file
+        fs::write(dest_dir.path().join(&infos[0].key), b"ent")
+            .await
+            .unwrap();
+
+        let mirror = Mirror {
+            filter: FileFilter {
+                after: None,
+                before: None,
+                prefix: FileType::EntropyReport.to_string(),
+            },
+            dest_config: None,
+            dest_bucket: None,
+            dest_dir: Some(dest_dir.path().to_path_buf()),
+            staging_dir: Some(src_dir.path().join("staging")),
+            concurrency: 2,
+        };
+        mirror.run(&settings).await.expect("mirror files");
+
+        for info in &infos {
+            let contents = fs::read(dest_dir.path().join(&info.key)).await.unwrap();
+            assert_eq!(b"entropy".to_vec(), contents);
+        }
+    }
+}
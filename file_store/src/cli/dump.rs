@@ -1,390 +1,130 @@
-use crate::{
-    cli::print_json,
-    coverage::CoverageObject,
-    file_source,
-    heartbeat::{CbrsHeartbeat, CbrsHeartbeatIngestReport},
-    iot_packet::IotValidPacket,
-    mobile_radio_invalidated_threshold::VerifiedInvalidatedRadioThresholdIngestReport,
-    mobile_radio_threshold::VerifiedRadioThresholdIngestReport,
-    mobile_session::{DataTransferSessionIngestReport, InvalidDataTransferIngestReport},
-    mobile_subscriber::{SubscriberLocationIngestReport, VerifiedSubscriberLocationIngestReport},
-    speedtest::{CellSpeedtest, CellSpeedtestIngestReport},
-    traits::{MsgDecode, TimestampDecode},
-    wifi_heartbeat::WifiHeartbeatIngestReport,
-    FileType, Result, Settings,
-};
+use crate::{cli::print_json, file_source, proto_json, Error, FileType, Result, Settings};
 use base64::Engine;
-use csv::Writer;
 use futures::stream::StreamExt;
-use helium_crypto::PublicKey;
-use helium_proto::{
-    services::{
-        packet_verifier::ValidDataTransferSession as ValidDataTransferSessionProto,
-        poc_lora::{
-            LoraBeaconIngestReportV1, LoraInvalidWitnessReportV1, LoraPocV1,
-            LoraWitnessIngestReportV1,
-        },
-        poc_mobile::{
-            mobile_reward_share::Reward, CellHeartbeatIngestReportV1, CellHeartbeatReqV1,
-            CoverageObjectV1, Heartbeat, InvalidDataTransferIngestReportV1, MobileRewardShare,
-            OracleBoostingReportV1, RadioRewardShare, SpeedtestAvg, SpeedtestIngestReportV1,
-            SpeedtestReqV1, VerifiedInvalidatedRadioThresholdIngestReportV1,
-            VerifiedRadioThresholdIngestReportV1,
-        },
-        router::PacketRouterPacketReportV1,
-    },
-    BlockchainTxn, BoostedHexUpdateV1 as BoostedHexUpdateProto, Message, PriceReportV1,
-    RewardManifest, SubnetworkRewards,
-};
-use serde_json::json;
-use std::io;
-use std::path::PathBuf;
+use serde_json::{Map, Value};
+use std::{io, path::PathBuf};
 
-/// Print information about a given store file.
+/// Output format of dumped messages
+#[derive(Debug, Clone, Copy, Default, clap::ValueEnum)]
+pub enum Format {
+    /// Pretty printed JSON object per message
+    #[default]
+    Json,
+    /// Single line JSON object per message
+    Ndjson,
+    /// CSV row per message with nested fields flattened into dotted column
+    /// names. Columns are the union of the fields of all messages, so rows
+    /// are written once the whole file was read.
+    Csv,
+    /// Base64 encoded bytes of each message, undecoded, one per line. Useful
+    /// to submit dumped transactions such as signed poc receipt txns on
+    /// chain.
+    Raw,
+}
+
+/// Print the messages in a given store file.
 #[derive(Debug, clap::Args)]
 pub struct Cmd {
     /// Type of file to be dump
     file_type: FileType,
     /// Path to file
     in_path: PathBuf,
+    /// Output format
+    #[clap(long, value_enum, default_value_t = Format::Json)]
+    format: Format,
 }
 
 impl Cmd {
     pub async fn run(&self, _settings: &Settings) -> Result {
         let mut file_stream = file_source::source([&self.in_path]);
-
-        let mut wtr = Writer::from_writer(io::stdout());
+        let mut writer = Writer::new(self.format);
         while let Some(result) = file_stream.next().await {
             let msg = result?;
-            match self.file_type {
-                FileType::VerifiedRadioThresholdIngestReport => {
-                    let dec_msg = VerifiedRadioThresholdIngestReportV1::decode(msg)?;
-                    let report = VerifiedRadioThresholdIngestReport::try_from(dec_msg)?;
-                    print_json(&report)?;
-                }
-                FileType::VerifiedInvalidatedRadioThresholdIngestReport => {
-                    let dec_msg = VerifiedInvalidatedRadioThresholdIngestReportV1::decode(msg)?;
-                    let report = VerifiedInvalidatedRadioThresholdIngestReport::try_from(dec_msg)?;
-                    print_json(&report)?;
-                }
-                FileType::BoostedHexUpdate => {
-                    let dec_msg = BoostedHexUpdateProto::decode(msg)?;
-                    let update = dec_msg.update.unwrap();
-                    let json = json!({
-                        "last_update": dec_msg.timestamp,
-                        "location":  update.location,
-                        "start_ts":  update.start_ts,
-                        "end_ts":  update.end_ts,
-                        "period_length":  update.period_length,
-                        "multipliers":  update.multipliers,
-                        "boosted_hex_pubkey":  update.boosted_hex_pubkey,
-                        "boost_config_pubkey":  update.boost_config_pubkey,
-                    });
-                    print_json(&json)?;
-                }
-                FileType::CbrsHeartbeat => {
-                    let dec_msg = CellHeartbeatReqV1::decode(msg)?;
-                    wtr.serialize(CbrsHeartbeat::try_from(dec_msg)?)?;
-                }
-                FileType::WifiHeartbeatIngestReport => {
-                    let msg = WifiHeartbeatIngestReport::decode(msg)?;
-                    let json = json!({
-                        "received_timestamp": msg.received_timestamp,
-                        "pubkey": msg.report.pubkey,
-                        "operation_mode": msg.report.operation_mode,
-                        "location_validation_timestamp": msg.report.location_validation_timestamp,
-                    });
-                    // print_json(&msg)?;
-                    print_json(&json)?;
-                }
-                FileType::CellSpeedtest => {
-                    let dec_msg = SpeedtestReqV1::decode(msg)?;
-                    wtr.serialize(CellSpeedtest::try_from(dec_msg)?)?;
-                }
-                FileType::CbrsHeartbeatIngestReport => {
-                    let dec_msg = CellHeartbeatIngestReportV1::decode(msg)?;
-                    let ingest_report = CbrsHeartbeatIngestReport::try_from(dec_msg)?;
-                    print_json(&ingest_report)?;
-                }
-                FileType::CellSpeedtestIngestReport => {
-                    let dec_msg = SpeedtestIngestReportV1::decode(msg)?;
-                    let ingest_report = CellSpeedtestIngestReport::try_from(dec_msg)?;
-                    print_json(&ingest_report)?;
-                }
-                FileType::DataTransferSessionIngestReport => {
-                    let dtr = DataTransferSessionIngestReport::decode(msg)?;
-                    print_json(&json!({
-                        "received_timestamp": dtr.received_timestamp,
-                        "rewardable_bytes": dtr.report.rewardable_bytes,
-                        "pub_key": dtr.report.data_transfer_usage.pub_key,
-                        "upload_bytes": dtr.report.data_transfer_usage.upload_bytes,
-                        "download_bytes": dtr.report.data_transfer_usage.download_bytes,
-                        "radio_access_technology": dtr.report.data_transfer_usage.radio_access_technology,
-                        "event_id": dtr.report.data_transfer_usage.event_id,
-                        "payer": dtr.report.data_transfer_usage.payer,
-                        "timestamp": dtr.report.data_transfer_usage.timestamp,
-                    }))?;
-                }
-                FileType::InvalidDataTransferSessionIngestReport => {
-                    let msg: InvalidDataTransferIngestReport =
-                        InvalidDataTransferIngestReportV1::decode(msg)?.try_into()?;
-                    print_json(&json!({
-                        "invalid_reason": msg.reason,
-                        "invalid_timestamp": msg.timestamp,
-                        "received_timestamp": msg.report.received_timestamp,
-                        "rewardable_bytes": msg.report.report.rewardable_bytes,
-                        "hotspot_key": PublicKey::try_from(msg.report.report.data_transfer_usage.pub_key)?,
-                        "upload_bytes": msg.report.report.data_transfer_usage.upload_bytes,
-                        "download_bytes": msg.report.report.data_transfer_usage.download_bytes,
-                        "radio_access_technology": msg.report.report.data_transfer_usage.radio_access_technology,
-                        "event_id": msg.report.report.data_transfer_usage.event_id,
-                        "payer":  PublicKey::try_from(msg.report.report.data_transfer_usage.payer)?,
-                        "event_timestamp": msg.report.report.data_transfer_usage.timestamp,
-                    }))?;
-                }
-                FileType::ValidDataTransferSession => {
-                    let msg = ValidDataTransferSessionProto::decode(msg)?;
-                    print_json(&json!({
-                        "pub_key": PublicKey::try_from(msg.pub_key)?,
-                        "upload_bytes": msg.upload_bytes,
-                        "download_bytes": msg.download_bytes,
-                        "num_dcs": msg.num_dcs,
-                        "upload_bytes": msg.upload_bytes,
-                        "payer": PublicKey::try_from(msg.payer)?,
-                        "first_timestamp": msg.first_timestamp,
-                        "last_timestamp": msg.last_timestamp,
-                    }))?;
+            match self.format {
+                Format::Raw => {
+                    println!("{}", base64::engine::general_purpose::STANDARD.encode(&msg))
                 }
-                FileType::IotBeaconIngestReport => {
-                    let dec_msg = LoraBeaconIngestReportV1::decode(msg)?;
-                    let json = json!({
-                        "received_timestamp": dec_msg.received_timestamp,
-                        "report":  dec_msg.report,
-                    });
-                    // TODO: tmp dump out as json
-                    // printing to json here as csv serializing failing due on header generation from struct
-                    print_json(&json)?;
-                    // wtr.serialize(IotBeaconIngestReport::try_from(dec_msg)?)?;
-                }
-                FileType::IotWitnessIngestReport => {
-                    let dec_msg = LoraWitnessIngestReportV1::decode(msg)?;
-                    let json = json!({
-                        "received_timestamp": dec_msg.received_timestamp,
-                        "report":  dec_msg.report,
-                    });
-                    // TODO: tmp dump out as json
-                    // printing to json here as csv serializing failing due on header generation from struct
-                    print_json(&json)?;
-                    // wtr.serialize(IotWitnessIngestReport::try_from(dec_msg)?)?;
-                }
-                FileType::IotInvalidWitnessReport => {
-                    let dec_msg = LoraInvalidWitnessReportV1::decode(msg)?;
-                    let json = json!({
-                        "received_timestamp": dec_msg.received_timestamp,
-                        "reason":  dec_msg.reason
-                    });
-                    // TODO: tmp dump out as json
-                    // printing to json here as csv serializing failing due on header generation from struct
-                    print_json(&json)?;
-                    // wtr.serialize(IotWitnessIngestReport::try_from(dec_msg)?)?;
-                }
-                FileType::IotPoc => {
-                    let dec_msg = LoraPocV1::decode(msg)?;
-                    let json = json!({
-                        "poc_id": dec_msg.poc_id,
-                        "beacon_report":  dec_msg.beacon_report,
-                        "selected_witnesses": dec_msg.selected_witnesses,
-                        "unselected_witnesses": dec_msg.unselected_witnesses,
-                    });
-                    // TODO: tmp dump out as json
-                    // printing to json here as csv serializing failing due on header generation from struct
-                    print_json(&json)?;
-                    // wtr.serialize(IotValidPoc::try_from(dec_msg)?)?;
-                }
-                FileType::SubnetworkRewards => {
-                    let proto_rewards = SubnetworkRewards::decode(msg)?.rewards;
-                    let total_rewards = proto_rewards
-                        .iter()
-                        .fold(0, |acc, reward| acc + reward.amount);
+                _ => writer.write(proto_json::decode(self.file_type, &msg)?)?,
+            }
+        }
+        writer.flush()
+    }
+}
 
-                    let rewards: Vec<(PublicKey, u64)> = proto_rewards
-                        .iter()
-                        .map(|r| {
-                            (
-                                PublicKey::try_from(r.account.as_slice())
-                                    .expect("unable to get public key"),
-                                r.amount,
-                            )
-                        })
-                        .collect();
-                    print_json(&json!({ "rewards": rewards, "total_rewards": total_rewards }))?;
-                }
-                FileType::SpeedtestAvg => {
-                    let speedtest_avg = SpeedtestAvg::decode(msg)?;
-                    print_json(&json!({
-                        "pub_key": PublicKey::try_from(speedtest_avg.pub_key)?,
-                        "upload_speed_avg_bps": speedtest_avg.upload_speed_avg_bps,
-                        "download_speed_avg_bps": speedtest_avg.download_speed_avg_bps,
-                        "latency_avg_ms": speedtest_avg.latency_avg_ms,
-                        "validity": speedtest_avg.validity,
-                        "number_of_speedtests": speedtest_avg.speedtests.len(),
-                        "reward_multiplier": speedtest_avg.reward_multiplier,
-                    }))?;
-                }
-                FileType::ValidatedHeartbeat => {
-                    let heartbeat = Heartbeat::decode(msg)?;
-                    print_json(&json!({
-                        "cbsd_id": heartbeat.cbsd_id,
-                        "pub_key": PublicKey::try_from(heartbeat.pub_key)?,
-                        "timestamp": heartbeat.timestamp,
-                        "cell_type": heartbeat.cell_type,
-                        "validity": heartbeat.validity,
-                    }))?;
-                }
-                FileType::MobileRewardShare => {
-                    let reward = MobileRewardShare::decode(msg)?;
-                    match reward.reward {
-                        Some(Reward::GatewayReward(reward)) => print_json(&json!({
-                            "hotspot_key": PublicKey::try_from(reward.hotspot_key)?,
-                            "dc_transfer_reward": reward.dc_transfer_reward,
-                        }))?,
-                        Some(Reward::RadioReward(reward)) => print_json(&json!({
-                            "hotspot_key":  PublicKey::try_from(reward.hotspot_key)?,
-                            "cbsd_id": reward.cbsd_id,
-                            "poc_reward": reward.poc_reward,
-                            "boosted_hexes": reward.boosted_hexes,
-                        }))?,
-                        Some(Reward::SubscriberReward(reward)) => print_json(&json!({
-                            "subscriber_id": reward.subscriber_id,
-                            "discovery_location_amount": reward.discovery_location_amount,
-                        }))?,
-                        Some(Reward::ServiceProviderReward(reward)) => print_json(&json!({
-                            "service_provider": reward.service_provider_id,
-                            "amount": reward.amount,
-                        }))?,
-                        Some(Reward::UnallocatedReward(reward)) => print_json(&json!({
-                            "unallocated_reward_type": reward.reward_type,
-                            "amount": reward.amount,
-                        }))?,
-                        _ => (),
-                    }
-                }
-                FileType::RadioRewardShare => {
-                    let reward = RadioRewardShare::decode(msg)?;
-                    print_json(&json!({
-                        "owner_key": PublicKey::try_from(reward.owner_key)?,
-                        "hotpost_key": PublicKey::try_from(reward.hotspot_key)?,
-                        "cbsd_id": reward.cbsd_id,
-                        "amount": reward.amount,
-                        "start_epoch": reward.start_epoch,
-                        "end_epoch": reward.end_epoch,
-                    }))?;
-                }
-                FileType::RewardManifest => {
-                    let manifest = RewardManifest::decode(msg)?;
-                    print_json(&json!({
-                        "written_files": manifest.written_files,
-                        "start_timestamp": manifest.start_timestamp,
-                        "end_timestamp": manifest.end_timestamp,
-                    }))?;
-                }
-                FileType::SignedPocReceiptTxn => {
-                    // This just outputs a binary of the txns instead of the typical decode.
-                    // This is to make ingesting the output of these transactions simpler on chain.
-                    let wrapped_txn = BlockchainTxn::decode(msg)?;
-                    println!("{:?}", wrapped_txn.encode_to_vec());
-                }
-                FileType::IotPacketReport => {
-                    let packet_report = PacketRouterPacketReportV1::decode(msg)?;
-                    print_json(&json!({
-                        "oui": packet_report.oui,
-                        "timestamp": packet_report.gateway_tmst}))?;
-                }
-                FileType::PriceReport => {
-                    let manifest = PriceReportV1::decode(msg)?;
-                    print_json(&json!({
-                        "price": manifest.price,
-                        "timestamp": manifest.timestamp,
-                        "token_type": manifest.token_type(),
-                    }))?;
-                }
-                FileType::IotValidPacket => {
-                    let manifest = IotValidPacket::decode(msg)?;
-                    print_json(&json!({
-                        "payload_size": manifest.payload_size,
-                        "gateway": PublicKey::try_from(manifest.gateway)?,
-                        "payload_hash": base64::engine::general_purpose::STANDARD.encode(manifest.payload_hash),
-                        "num_dcs": manifest.num_dcs,
-                        "packet_timestamp": manifest.packet_timestamp,
-                    }))?;
-                }
-                FileType::SubscriberLocationIngestReport => {
-                    let report = SubscriberLocationIngestReport::decode(msg)?;
-                    print_json(&json!({
-                        "subscriber_id": report.report.subscriber_id,
-                        "carrier_pub_key": report.report.carrier_pub_key,
-                        "recv_timestamp": report.received_timestamp}))?;
-                }
-                FileType::VerifiedSubscriberLocationIngestReport => {
-                    let report = VerifiedSubscriberLocationIngestReport::decode(msg)?;
-                    print_json(&json!({
-                        "subscriber_id": report.report.report.subscriber_id,
-                        "carrier_pub_key": report.report.report.carrier_pub_key,
-                        "status": report.status,
-                        "recv_timestamp": report.report.received_timestamp}))?;
-                }
-                FileType::OracleBoostingReport => {
-                    #[derive(serde::Serialize)]
-                    enum Assignment {
-                        A,
-                        B,
-                        C,
-                    }
+/// Writes decoded messages to stdout in one of the supported formats
+pub struct Writer {
+    format: Format,
+    csv: csv::Writer<io::Stdout>,
+    columns: Vec<String>,
+    rows: Vec<Map<String, Value>>,
+}
 
-                    #[derive(serde::Serialize)]
-                    struct OracleBoostingHexAssignment {
-                        location: String,
-                        assignment_multiplier: u32,
-                        urbanized: Assignment,
-                    }
+impl Writer {
+    pub fn new(format: Format) -> Self {
+        Self {
+            format,
+            csv: csv::Writer::from_writer(io::stdout()),
+            columns: vec![],
+            rows: vec![],
+        }
+    }
 
-                    let report = OracleBoostingReportV1::decode(msg)?;
-                    let assignments: Vec<_> = report
-                        .assignments
-                        .into_iter()
-                        .map(|assignment| OracleBoostingHexAssignment {
-                            location: assignment.location,
-                            assignment_multiplier: assignment.assignment_multiplier,
-                            urbanized: match assignment.urbanized {
-                                0 => Assignment::A,
-                                1 => Assignment::B,
-                                _ => Assignment::C,
-                            },
-                        })
-                        .collect();
+    pub fn write(&mut self, value: Value) -> Result {
+        match self.format {
+            // Raw messages are printed before being decoded
+            Format::Json | Format::Raw => print_json(&value),
+            Format::Ndjson => {
+                println!("{}", serde_json::to_string(&value)?);
+                Ok(())
+            }
+            Format::Csv => self.write_csv(value),
+        }
+    }
 
-                    print_json(&json!({
-                    "coverage_object": uuid::Uuid::from_slice(report.coverage_object.as_slice()).unwrap(),
-                    "assignments": assignments,
-                    "timestamp": report.timestamp.to_timestamp()?,
-                    }))?
-                }
-                FileType::CoverageObject => {
-                    let coverage = CoverageObjectV1::decode(msg)?;
-                    let coverage = CoverageObject::try_from(coverage.coverage_object.unwrap())?;
-                    print_json(&json!({
-                        "pub_key": coverage.pub_key,
-                        "uuid": coverage.uuid,
-                        "coverage_claim_time": coverage.coverage_claim_time,
-                        "coverage": coverage.coverage,
-                    }))?;
-                }
-                missing_filetype => println!("No dump for {missing_filetype}"),
+    fn write_csv(&mut self, value: Value) -> Result {
+        let mut row = Map::new();
+        flatten(None, value, &mut row);
+        for column in row.keys() {
+            if !self.columns.contains(column) {
+                self.columns.push(column.clone());
             }
         }
+        self.rows.push(row);
+        Ok(())
+    }
 
-        wtr.flush()?;
+    pub fn flush(&mut self) -> Result {
+        if !self.columns.is_empty() {
+            self.csv.write_record(&self.columns)?;
+        }
+        for row in self.rows.drain(..) {
+            self.csv
+                .write_record(self.columns.iter().map(|column| match row.get(column) {
+                    None | Some(Value::Null) => String::new(),
+                    Some(Value::String(value)) => value.clone(),
+                    Some(value) => value.to_string(),
+                }))?;
+        }
+        self.columns.clear();
+        self.csv.flush().map_err(Error::from)
+    }
+}
 
-        Ok(())
+/// Flatten nested objects into a single level with dotted keys. Arrays are
+/// kept as JSON values.
+fn flatten(prefix: Option<&str>, value: Value, row: &mut Map<String, Value>) {
+    match value {
+        Value::Object(map) => {
+            for (key, value) in map {
+                let key = match prefix {
+                    Some(prefix) => format!("{prefix}.{key}"),
+                    None => key,
+                };
+                flatten(Some(&key), value, row);
+            }
+        }
+        value => {
+            row.insert(prefix.unwrap_or_default().to_string(), value);
+        }
     }
 }
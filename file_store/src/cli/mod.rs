@@ -2,6 +2,7 @@ pub mod bucket;
 pub mod dump;
 pub mod dump_mobile_rewards;
 pub mod info;
+pub mod predicate;
 
 use crate::Result;
 
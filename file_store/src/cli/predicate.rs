@@ -0,0 +1,350 @@
+//! A small expression language for filtering records rendered as JSON.
+//!
+//! ```text
+//! expr       := or
+//! or         := and ("||" and)*
+//! and        := unary ("&&" unary)*
+//! unary      := "!" unary | "(" expr ")" | comparison
+//! comparison := path (op value)?
+//! op         := "==" | "!=" | "<" | "<=" | ">" | ">=" | "~"
+//! path       := field ("." field)*
+//! ```
+//!
+//! A path matches the field at any depth of the record, so `payer` finds
+//! `report.data_transfer_usage.payer`. When a path resolves to several
+//! values, for example inside a repeated field, the comparison holds if any
+//! of them matches. A path on its own checks that the field is set. Values
+//! are bare words or double quoted strings. Numbers compare numerically, h3
+//! cell strings compare against numeric locations and `~` checks that a
+//! value contains a substring.
+
+use crate::{Error, Result};
+use serde_json::Value;
+use std::{cmp::Ordering, str::FromStr};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Predicate {
+    And(Box<Predicate>, Box<Predicate>),
+    Or(Box<Predicate>, Box<Predicate>),
+    Not(Box<Predicate>),
+    Exists(Vec<String>),
+    Compare {
+        path: Vec<String>,
+        op: Op,
+        value: String,
+    },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Op {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Contains,
+}
+
+impl FromStr for Predicate {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let tokens = tokenize(s)?;
+        let mut parser = Parser { tokens, pos: 0 };
+        let predicate = parser.expr()?;
+        match parser.peek() {
+            None => Ok(predicate),
+            Some(token) => Err(invalid(format!("unexpected {token:?}"))),
+        }
+    }
+}
+
+impl Predicate {
+    pub fn matches(&self, record: &Value) -> bool {
+        match self {
+            Self::And(lhs, rhs) => lhs.matches(record) && rhs.matches(record),
+            Self::Or(lhs, rhs) => lhs.matches(record) || rhs.matches(record),
+            Self::Not(inner) => !inner.matches(record),
+            Self::Exists(path) => find(record, path).iter().any(|value| !value.is_null()),
+            Self::Compare {
+                path,
+                op: Op::Ne,
+                value,
+            } => !find(record, path)
+                .iter()
+                .any(|found| compare(found, Op::Eq, value)),
+            Self::Compare { path, op, value } => find(record, path)
+                .iter()
+                .any(|found| compare(found, *op, value)),
+        }
+    }
+}
+
+fn invalid(msg: impl ToString) -> Error {
+    Error::InvalidPredicate(msg.to_string())
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Word(String),
+    Op(Op),
+    And,
+    Or,
+    Not,
+    Open,
+    Close,
+}
+
+const SPECIAL: &[char] = &['(', ')', '!', '<', '>', '=', '~', '&', '|', '"'];
+
+fn tokenize(s: &str) -> Result<Vec<Token>> {
+    let mut tokens = vec![];
+    let mut chars = s.chars().peekable();
+    while let Some(c) = chars.next() {
+        let token = match c {
+            c if c.is_whitespace() => continue,
+            '(' => Token::Open,
+            ')' => Token::Close,
+            '~' => Token::Op(Op::Contains),
+            '&' if chars.next_if_eq(&'&').is_some() => Token::And,
+            '|' if chars.next_if_eq(&'|').is_some() => Token::Or,
+            '=' if chars.next_if_eq(&'=').is_some() => Token::Op(Op::Eq),
+            '!' if chars.next_if_eq(&'=').is_some() => Token::Op(Op::Ne),
+            '!' => Token::Not,
+            '<' if chars.next_if_eq(&'=').is_some() => Token::Op(Op::Le),
+            '<' => Token::Op(Op::Lt),
+            '>' if chars.next_if_eq(&'=').is_some() => Token::Op(Op::Ge),
+            '>' => Token::Op(Op::Gt),
+            '"' => {
+                let mut word = String::new();
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => word.extend(chars.next()),
+                        Some(c) => word.push(c),
+                        None => return Err(invalid("unterminated string")),
+                    }
+                }
+                Token::Word(word)
+            }
+            c if SPECIAL.contains(&c) => return Err(invalid(format!("unexpected '{c}'"))),
+            c => {
+                let mut word = c.to_string();
+                while let Some(c) = chars.next_if(|c| !c.is_whitespace() && !SPECIAL.contains(c)) {
+                    word.push(c);
+                }
+                Token::Word(word)
+            }
+        };
+        tokens.push(token);
+    }
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    fn expr(&mut self) -> Result<Predicate> {
+        let mut lhs = self.and()?;
+        while self.peek() == Some(&Token::Or) {
+            self.pos += 1;
+            lhs = Predicate::Or(Box::new(lhs), Box::new(self.and()?));
+        }
+        Ok(lhs)
+    }
+
+    fn and(&mut self) -> Result<Predicate> {
+        let mut lhs = self.unary()?;
+        while self.peek() == Some(&Token::And) {
+            self.pos += 1;
+            lhs = Predicate::And(Box::new(lhs), Box::new(self.unary()?));
+        }
+        Ok(lhs)
+    }
+
+    fn unary(&mut self) -> Result<Predicate> {
+        match self.next() {
+            Some(Token::Not) => Ok(Predicate::Not(Box::new(self.unary()?))),
+            Some(Token::Open) => {
+                let inner = self.expr()?;
+                match self.next() {
+                    Some(Token::Close) => Ok(inner),
+                    _ => Err(invalid("expected ')'")),
+                }
+            }
+            Some(Token::Word(word)) => {
+                let path = word.split('.').map(str::to_string).collect();
+                match self.peek() {
+                    Some(Token::Op(op)) => {
+                        let op = *op;
+                        self.pos += 1;
+                        match self.next() {
+                            Some(Token::Word(value)) => Ok(Predicate::Compare { path, op, value }),
+                            _ => Err(invalid(format!("expected a value after {word}"))),
+                        }
+                    }
+                    _ => Ok(Predicate::Exists(path)),
+                }
+            }
+            Some(token) => Err(invalid(format!("unexpected {token:?}"))),
+            None => Err(invalid("unexpected end of expression")),
+        }
+    }
+}
+
+/// All values the path resolves to, starting from any depth of the record
+fn find<'a>(record: &'a Value, path: &[String]) -> Vec<&'a Value> {
+    let mut found = vec![];
+    find_into(record, path, &mut found);
+    found
+}
+
+fn find_into<'a>(value: &'a Value, path: &[String], found: &mut Vec<&'a Value>) {
+    resolve(value, path, found);
+    match value {
+        Value::Object(map) => map.values().for_each(|value| find_into(value, path, found)),
+        Value::Array(items) => items.iter().for_each(|item| find_into(item, path, found)),
+        _ => (),
+    }
+}
+
+fn resolve<'a>(value: &'a Value, path: &[String], found: &mut Vec<&'a Value>) {
+    match (path.split_first(), value) {
+        (None, Value::Array(items)) => found.extend(items),
+        (None, value) => found.push(value),
+        (Some((field, rest)), Value::Object(map)) => {
+            if let Some(value) = map.get(field) {
+                resolve(value, rest, found);
+            }
+        }
+        (Some(_), Value::Array(items)) => items.iter().for_each(|item| resolve(item, path, found)),
+        (Some(_), _) => (),
+    }
+}
+
+fn compare(found: &Value, op: Op, value: &str) -> bool {
+    if op == Op::Contains {
+        return match found {
+            Value::String(found) => found.contains(value),
+            Value::Null => false,
+            found => found.to_string().contains(value),
+        };
+    }
+    let ordering = match found {
+        Value::Number(number) => compare_number(number, value),
+        Value::String(found) => match (found.parse::<f64>(), value.parse::<f64>()) {
+            (Ok(found), Ok(value)) => found.partial_cmp(&value),
+            _ => Some(found.as_str().cmp(value)),
+        },
+        Value::Bool(found) => value.parse::<bool>().ok().map(|value| found.cmp(&value)),
+        _ => None,
+    };
+    ordering.is_some_and(|ordering| match op {
+        Op::Eq => ordering == Ordering::Equal,
+        Op::Ne => ordering != Ordering::Equal,
+        Op::Lt => ordering == Ordering::Less,
+        Op::Le => ordering != Ordering::Greater,
+        Op::Gt => ordering == Ordering::Greater,
+        Op::Ge => ordering != Ordering::Less,
+        Op::Contains => unreachable!("handled above"),
+    })
+}
+
+fn compare_number(number: &serde_json::Number, value: &str) -> Option<Ordering> {
+    // Integers such as h3 indexes and timestamps are compared exactly, they
+    // do not all fit in an f64
+    if let (Some(number), Ok(value)) = (number.as_u64(), value.parse::<u64>()) {
+        return Some(number.cmp(&value));
+    }
+    if let (Some(number), Ok(value)) = (number.as_i64(), value.parse::<i64>()) {
+        return Some(number.cmp(&value));
+    }
+    if let Ok(value) = value.parse::<f64>() {
+        return number.as_f64()?.partial_cmp(&value);
+    }
+    // Hex locations are stored as numeric h3 indexes
+    let cell = h3o::CellIndex::from_str(value).ok()?;
+    number.as_u64().map(|number| number.cmp(&u64::from(cell)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn record() -> Value {
+        json!({
+            "received_timestamp": "2024-05-01T12:00:00+00:00",
+            "report": {
+                "cbsd_id": "P27-SCE4255W",
+                "signal_level": 2,
+                "location": 631181359475644927u64,
+                "payer": "1payer",
+                "hexes": [{"location": 1}, {"location": 2}],
+            }
+        })
+    }
+
+    fn matches(expr: &str) -> bool {
+        Predicate::from_str(expr)
+            .expect("valid predicate")
+            .matches(&record())
+    }
+
+    #[test]
+    fn matches_fields_at_any_depth() {
+        assert!(matches(r#"cbsd_id == "P27-SCE4255W""#));
+        assert!(matches("report.payer == 1payer"));
+        assert!(!matches("payer == 1other"));
+        assert!(matches("payer != 1other"));
+    }
+
+    #[test]
+    fn combines_ranges_and_boolean_operators() {
+        assert!(matches("signal_level >= 2 && signal_level < 3"));
+        assert!(!matches("signal_level > 2 || cbsd_id ~ SCE9"));
+        assert!(matches("!(signal_level > 2) && cbsd_id ~ SCE4"));
+        assert!(matches("received_timestamp >= 2024-05-01T00:00:00+00:00"));
+    }
+
+    #[test]
+    fn matches_any_repeated_value_and_h3_cells() {
+        assert!(matches("hexes.location == 2"));
+        assert!(!matches("hexes.location == 3"));
+        assert!(matches("report.location == 8c2681a3064d9ff"));
+        assert!(matches("cbsd_id"));
+        assert!(!matches("missing"));
+    }
+
+    #[test]
+    fn compares_large_integers_exactly() {
+        // Both values round to the same f64
+        assert!(matches("report.location == 631181359475644927"));
+        assert!(!matches("report.location == 631181359475644928"));
+        assert!(matches("report.location < 631181359475644928"));
+        assert!(matches("signal_level > -1"));
+        assert!(matches("signal_level < 2.5"));
+    }
+
+    #[test]
+    fn rejects_invalid_expressions() {
+        assert!(Predicate::from_str("cbsd_id ==").is_err());
+        assert!(Predicate::from_str("(cbsd_id").is_err());
+        assert!(Predicate::from_str(r#"cbsd_id == "abc"#).is_err());
+        assert!(Predicate::from_str("a == 1 b").is_err());
+    }
+}
@@ -0,0 +1,209 @@
+use crate::{BytesMutStream, Error, Result};
+use async_compression::tokio::{
+    bufread::{GzipDecoder, ZstdDecoder},
+    write::{GzipEncoder, ZstdEncoder},
+};
+use futures::{stream, StreamExt, TryStreamExt};
+use serde::{Deserialize, Serialize};
+use std::{
+    io,
+    pin::Pin,
+    task::{Context, Poll},
+};
+use tokio::io::{AsyncBufRead, AsyncBufReadExt, AsyncRead, AsyncWrite};
+use tokio_util::codec::{length_delimited::LengthDelimitedCodec, FramedRead};
+
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+const ZSTD_MAGIC: [u8; 4] = [0x28, 0xb5, 0x2f, 0xfd];
+
+/// Compression applied to the frames of a file. The choice is encoded in the
+/// file key suffix.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum Compression {
+    #[default]
+    Gzip,
+    Zstd,
+    None,
+}
+
+impl Compression {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Self::Gzip => ".gz",
+            Self::Zstd => ".zst",
+            Self::None => "",
+        }
+    }
+
+    pub fn from_key(key: &str) -> Self {
+        if key.ends_with(Self::Gzip.extension()) {
+            Self::Gzip
+        } else if key.ends_with(Self::Zstd.extension()) {
+            Self::Zstd
+        } else {
+            Self::None
+        }
+    }
+
+    /// Detect the compression of a file from its leading bytes. Uncompressed
+    /// files start with a big endian frame length, which can never be large
+    /// enough to be mistaken for either magic number.
+    fn detect(buf: &[u8]) -> Self {
+        if buf.starts_with(&GZIP_MAGIC) {
+            Self::Gzip
+        } else if buf.starts_with(&ZSTD_MAGIC) {
+            Self::Zstd
+        } else {
+            Self::None
+        }
+    }
+
+    pub(crate) fn encoder<W>(&self, writer: W) -> Encoder<W>
+    where
+        W: AsyncWrite + Unpin,
+    {
+        match self {
+            Self::Gzip => Encoder::Gzip(GzipEncoder::new(writer)),
+            Self::Zstd => Encoder::Zstd(ZstdEncoder::new(writer)),
+            Self::None => Encoder::None(writer),
+        }
+    }
+}
+
+/// Writer applying one of the supported compressions
+#[derive(Debug)]
+pub enum Encoder<W> {
+    Gzip(GzipEncoder<W>),
+    Zstd(ZstdEncoder<W>),
+    None(W),
+}
+
+impl<W> Encoder<W> {
+    pub fn get_ref(&self) -> &W {
+        match self {
+            Self::Gzip(writer) => writer.get_ref(),
+            Self::Zstd(writer) => writer.get_ref(),
+            Self::None(writer) => writer,
+        }
+    }
+}
+
+impl<W> AsyncWrite for Encoder<W>
+where
+    W: AsyncWrite + Unpin,
+{
+    fn poll_write(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &[u8],
+    ) -> Poll<io::Result<usize>> {
+        match self.get_mut() {
+            Self::Gzip(writer) => Pin::new(writer).poll_write(cx, buf),
+            Self::Zstd(writer) => Pin::new(writer).poll_write(cx, buf),
+            Self::None(writer) => Pin::new(writer).poll_write(cx, buf),
+        }
+    }
+
+    fn poll_flush(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            Self::Gzip(writer) => Pin::new(writer).poll_flush(cx),
+            Self::Zstd(writer) => Pin::new(writer).poll_flush(cx),
+            Self::None(writer) => Pin::new(writer).poll_flush(cx),
+        }
+    }
+
+    fn poll_shutdown(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<io::Result<()>> {
+        match self.get_mut() {
+            Self::Gzip(writer) => Pin::new(writer).poll_shutdown(cx),
+            Self::Zstd(writer) => Pin::new(writer).poll_shutdown(cx),
+            Self::None(writer) => Pin::new(writer).poll_shutdown(cx),
+        }
+    }
+}
+
+type DecodedReader = Pin<Box<dyn AsyncRead + Send>>;
+
+async fn decoder<R>(mut reader: R) -> Result<DecodedReader>
+where
+    R: AsyncBufRead + Unpin + Send + 'static,
+{
+    let compression = Compression::detect(reader.fill_buf().await?);
+    Ok(match compression {
+        Compression::Gzip => Box::pin(GzipDecoder::new(reader)),
+        Compression::Zstd => Box::pin(ZstdDecoder::new(reader)),
+        Compression::None => Box::pin(reader),
+    })
+}
+
+/// Stream the length delimited frames of a file, decoding whichever
+/// compression it was written with
+pub(crate) fn framed_source<R>(reader: R, codec: LengthDelimitedCodec) -> BytesMutStream
+where
+    R: AsyncBufRead + Unpin + Send + 'static,
+{
+    stream::once(decoder(reader))
+        .map_ok(move |reader| FramedRead::new(reader, codec).map_err(Error::from))
+        .try_flatten()
+        .boxed()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bytes::Bytes;
+    use futures::SinkExt;
+    use tokio::io::AsyncWriteExt;
+    use tokio_util::codec::FramedWrite;
+
+    async fn encode(compression: Compression, frames: &[&'static str]) -> Vec<u8> {
+        let mut transport =
+            FramedWrite::new(compression.encoder(Vec::new()), LengthDelimitedCodec::new());
+        for frame in frames {
+            transport.send(Bytes::from(*frame)).await.unwrap();
+        }
+        let mut encoder = transport.into_inner();
+        encoder.shutdown().await.unwrap();
+        match encoder {
+            Encoder::Gzip(writer) => writer.into_inner(),
+            Encoder::Zstd(writer) => writer.into_inner(),
+            Encoder::None(writer) => writer,
+        }
+    }
+
+    #[tokio::test]
+    async fn decodes_every_compression() {
+        for compression in [Compression::Gzip, Compression::Zstd, Compression::None] {
+            let encoded = encode(compression, &["hello", "world"]).await;
+            let frames: Vec<_> =
+                framed_source(io::Cursor::new(encoded), LengthDelimitedCodec::new())
+                    .try_collect()
+                    .await
+                    .expect("decoded frames");
+            assert_eq!(
+                vec!["hello", "world"],
+                frames
+                    .iter()
+                    .map(|frame| std::str::from_utf8(frame).unwrap())
+                    .collect::<Vec<_>>(),
+                "{compression:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn compression_from_key() {
+        assert_eq!(
+            Compression::Gzip,
+            Compression::from_key("entropy_report.1658832527866.gz")
+        );
+        assert_eq!(
+            Compression::Zstd,
+            Compression::from_key("entropy_report.1658832527866.zst")
+        );
+        assert_eq!(
+            Compression::None,
+            Compression::from_key("entropy_report.1658832527866")
+        );
+    }
+}
@@ -0,0 +1,102 @@
+use crate::{
+    file_sink::{FileSinkBuilder, FileSinkClient},
+    file_upload::FileUpload,
+    BytesMutStream, FileInfo, Result, Stream,
+};
+use bytes::BytesMut;
+use futures::StreamExt;
+use std::path::Path;
+
+/// Prefix of the dead-letter files, followed by the prefix of the files
+/// whose frames they hold
+pub const DEAD_LETTER_PREFIX: &str = "dead_letter_";
+
+/// Length of the header the length delimited codec writes before each frame
+const FRAME_HEADER_LEN: u64 = 4;
+
+/// A frame that could not be decoded, along with where it was read from
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct DeadLetterV1 {
+    /// Key of the file the frame was read from
+    #[prost(string, tag = "1")]
+    pub file_key: String,
+    /// Timestamp of the file the frame was read from, in milliseconds
+    #[prost(uint64, tag = "2")]
+    pub file_timestamp: u64,
+    /// Offset of the frame header in the decompressed file
+    #[prost(uint64, tag = "3")]
+    pub offset: u64,
+    /// Raw bytes of the frame
+    #[prost(bytes = "vec", tag = "4")]
+    pub data: Vec<u8>,
+    /// Reason the frame failed to decode
+    #[prost(string, tag = "5")]
+    pub error: String,
+}
+
+/// Sink persisting the frames of a poller's files that fail to decode
+#[derive(Debug, Clone)]
+pub struct DeadLetterSink {
+    client: FileSinkClient,
+}
+
+impl DeadLetterSink {
+    pub fn new(client: FileSinkClient) -> Self {
+        Self { client }
+    }
+
+    /// Builder for the file sink holding the dead letters of the files with
+    /// the given prefix. The resulting `FileSink` has to be run by the
+    /// caller like any other sink.
+    pub fn sink_builder(
+        prefix: &str,
+        target_path: &Path,
+        file_upload: FileUpload,
+    ) -> FileSinkBuilder {
+        FileSinkBuilder::new(
+            format!("{DEAD_LETTER_PREFIX}{prefix}"),
+            target_path,
+            file_upload,
+            "file-store-dead-letter-sink",
+        )
+    }
+
+    pub async fn write(
+        &self,
+        file_info: &FileInfo,
+        offset: u64,
+        data: &[u8],
+        error: String,
+    ) -> Result {
+        metrics::counter!(
+            "file-store-dead-letter-frames",
+            "file-type" => file_info.prefix.clone(),
+        )
+        .increment(1);
+
+        let dead_letter = DeadLetterV1 {
+            file_key: file_info.key.clone(),
+            file_timestamp: file_info.timestamp.timestamp_millis() as u64,
+            offset,
+            data: data.to_vec(),
+            error,
+        };
+        self.client.write(dead_letter, &[]).await?;
+        Ok(())
+    }
+}
+
+/// Pair each frame of a file with the offset of its header in the
+/// decompressed file
+pub(crate) fn frame_offsets(frames: BytesMutStream) -> Stream<(u64, BytesMut)> {
+    frames
+        .scan(0u64, |next_offset, frame| {
+            let frame = frame.map(|frame| {
+                let offset = *next_offset;
+                *next_offset += FRAME_HEADER_LEN + frame.len() as u64;
+                (offset, frame)
+            });
+            futures::future::ready(Some(frame))
+        })
+        .boxed()
+}
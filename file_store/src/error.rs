@@ -30,8 +30,22 @@ pub enum Error {
     SendTimeout,
     #[error("shutting down")]
     Shutdown,
+    #[error("size mismatch for {0}: expected {1} bytes, got {2}")]
+    SizeMismatch(String, usize, usize),
+    #[error("checksum mismatch for {0}: expected {1}, got {2}")]
+    ChecksumMismatch(String, String, String),
+    #[error("invalid predicate: {0}")]
+    InvalidPredicate(String),
+    #[error("invalid key: {0}")]
+    InvalidKey(String),
+    #[error("streaming file {0} has to be read with into_try_stream")]
+    StreamingFile(String),
     #[error("error building file info poller")]
     FileInfoPollerError(#[from] crate::file_info_poller::FileInfoPollerConfigBuilderError),
+    #[error("error building multi file info poller")]
+    MultiFileInfoPollerError(
+        #[from] crate::multi_file_info_poller::MultiFileInfoPollerConfigBuilderError,
+    ),
     #[cfg(feature = "sqlx-postgres")]
     #[error("db error")]
     DbError(#[from] sqlx::Error),
@@ -1,8 +1,13 @@
-use crate::{file_store, traits::MsgDecode, Error, FileInfo, FileStore, Result};
+use crate::{
+    dead_letter::{self, DeadLetterSink},
+    file_store,
+    traits::MsgDecode,
+    Error, FileInfo, FileStore, Result, Stream,
+};
 use aws_sdk_s3::types::ByteStream;
 use chrono::{DateTime, Utc};
 use derive_builder::Builder;
-use futures::{future::LocalBoxFuture, stream::BoxStream, StreamExt};
+use futures::{future::LocalBoxFuture, stream::BoxStream, StreamExt, TryStreamExt};
 use futures_util::TryFutureExt;
 use retainer::Cache;
 use std::{collections::VecDeque, marker::PhantomData, sync::Arc, time::Duration};
@@ -10,12 +15,12 @@ use task_manager::ManagedTask;
 use tokio::sync::mpsc::{Receiver, Sender};
 
 const DEFAULT_POLL_DURATION_SECS: i64 = 30;
-const DEFAULT_POLL_DURATION: std::time::Duration =
+pub(crate) const DEFAULT_POLL_DURATION: std::time::Duration =
     std::time::Duration::from_secs(DEFAULT_POLL_DURATION_SECS as u64);
-const CLEAN_DURATION: std::time::Duration = std::time::Duration::from_secs(12 * 60 * 60);
+pub(crate) const CLEAN_DURATION: std::time::Duration = std::time::Duration::from_secs(12 * 60 * 60);
 const CACHE_TTL: std::time::Duration = std::time::Duration::from_secs(3 * 60 * 60);
 
-type MemoryFileCache = Arc<Cache<String, bool>>;
+pub(crate) type MemoryFileCache = Arc<Cache<String, bool>>;
 
 #[async_trait::async_trait]
 pub trait FileInfoPollerState: Send + Sync + 'static {
@@ -33,6 +38,50 @@ pub trait FileInfoPollerState: Send + Sync + 'static {
 #[async_trait::async_trait]
 pub trait FileInfoPollerParser<T>: Send + Sync + 'static {
     async fn parse(&self, stream: ByteStream) -> Result<Vec<T>>;
+
+    /// Parse a file, persisting the frames that fail to decode to
+    /// `dead_letter`. Parsers that do not work on frames ignore the sink.
+    async fn parse_with_dead_letter(
+        &self,
+        _file_info: &FileInfo,
+        stream: ByteStream,
+        _dead_letter: &DeadLetterSink,
+    ) -> Result<Vec<T>> {
+        self.parse(stream).await
+    }
+}
+
+/// Parser decoding the items of a file lazily as they are read rather than
+/// collecting the whole file up front
+pub trait FileInfoPollerStreamParser<T>: Send + Sync + 'static {
+    /// Errors reading the file are returned in the stream. Frames that fail
+    /// to decode are logged and skipped.
+    fn parse_stream(&self, stream: ByteStream) -> Stream<T>;
+
+    /// Like [`Self::parse_stream`], persisting the frames that fail to
+    /// decode to `dead_letter`
+    fn parse_stream_with_dead_letter(
+        &self,
+        _file_info: FileInfo,
+        stream: ByteStream,
+        _dead_letter: DeadLetterSink,
+    ) -> Stream<T> {
+        self.parse_stream(stream)
+    }
+}
+
+pub struct StreamParser<T>(Arc<dyn FileInfoPollerStreamParser<T>>);
+
+impl<T> Clone for StreamParser<T> {
+    fn clone(&self) -> Self {
+        Self(self.0.clone())
+    }
+}
+
+impl<T> std::fmt::Debug for StreamParser<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(std::any::type_name::<Self>())
+    }
 }
 
 #[async_trait::async_trait]
@@ -40,10 +89,15 @@ pub trait FileInfoPollerStateRecorder {
     async fn record(self, process_name: &str, file_info: &FileInfo) -> Result;
 }
 
+enum FileInfoData<T> {
+    Parsed(Vec<T>),
+    Streaming(Stream<T>),
+}
+
 pub struct FileInfoStream<T> {
     pub file_info: FileInfo,
     process_name: String,
-    data: Vec<T>,
+    data: FileInfoData<T>,
 }
 
 impl<T> FileInfoStream<T>
@@ -54,14 +108,49 @@ where
         Self {
             file_info,
             process_name,
-            data,
+            data: FileInfoData::Parsed(data),
         }
     }
 
+    /// A file whose items are decoded as the consumer reads them
+    pub fn new_streaming(process_name: String, file_info: FileInfo, data: Stream<T>) -> Self {
+        Self {
+            file_info,
+            process_name,
+            data: FileInfoData::Streaming(data),
+        }
+    }
+
+    /// Stream the items of the file. Only files parsed up front can be read
+    /// this way, a streaming file is returned as an error as reading it has
+    /// to surface its errors through [`Self::into_try_stream`].
     pub async fn into_stream(
         self,
         recorder: impl FileInfoPollerStateRecorder,
     ) -> Result<BoxStream<'static, T>>
+    where
+        T: 'static,
+    {
+        if let FileInfoData::Streaming(_) = self.data {
+            return Err(Error::StreamingFile(self.file_info.key));
+        }
+        Ok(self
+            .into_try_stream(recorder)
+            .await?
+            .filter_map(|item| futures::future::ready(item.ok()))
+            .boxed())
+    }
+
+    /// Stream the items of the file, surfacing errors reading it. The file
+    /// is recorded through `recorder` before any item is read, so only
+    /// transactional recorders such as a database transaction are supported
+    /// for streaming files: the file is marked as processed once the consumer
+    /// has finished with every item and commits, and not at all when it
+    /// drops the transaction on an error.
+    pub async fn into_try_stream(
+        self,
+        recorder: impl FileInfoPollerStateRecorder,
+    ) -> Result<Stream<T>>
     where
         T: 'static,
     {
@@ -72,7 +161,10 @@ where
         ).set(latency.num_seconds() as f64);
 
         recorder.record(&self.process_name, &self.file_info).await?;
-        Ok(futures::stream::iter(self.data.into_iter()).boxed())
+        Ok(match self.data {
+            FileInfoData::Parsed(data) => futures::stream::iter(data.into_iter().map(Ok)).boxed(),
+            FileInfoData::Streaming(data) => data,
+        })
     }
 }
 
@@ -82,6 +174,21 @@ pub enum LookbackBehavior {
     Max(Duration),
 }
 
+impl LookbackBehavior {
+    /// Timestamp to list files after given the latest processed file
+    /// timestamp, moved back by `offset` to pick up late arriving files
+    pub(crate) fn after(&self, latest: Option<DateTime<Utc>>, offset: Duration) -> DateTime<Utc> {
+        let latest_offset = latest.map(|lt| lt - offset);
+        match self {
+            LookbackBehavior::StartAfter(start_after) => latest_offset.unwrap_or(*start_after),
+            LookbackBehavior::Max(max_lookback) => {
+                let max_ts = Utc::now() - *max_lookback;
+                latest_offset.map(|lt| lt.max(max_ts)).unwrap_or(max_ts)
+            }
+        }
+    }
+}
+
 #[derive(Debug, Clone, Builder)]
 #[builder(pattern = "owned")]
 pub struct FileInfoPollerConfig<T, S, P> {
@@ -91,6 +198,14 @@ pub struct FileInfoPollerConfig<T, S, P> {
     store: FileStore,
     prefix: String,
     parser: P,
+    /// When set, files are decoded lazily by this parser as they are
+    /// consumed instead of up front by `parser`
+    #[builder(setter(custom), default)]
+    stream_parser: Option<StreamParser<T>>,
+    /// When set, frames that fail to decode are persisted to this sink
+    /// instead of only being logged
+    #[builder(setter(strip_option), default)]
+    dead_letter: Option<DeadLetterSink>,
     lookback: LookbackBehavior,
     #[builder(default = "Duration::from_secs(10 * 60)")]
     offset: Duration,
@@ -118,6 +233,11 @@ where
     S: FileInfoPollerState,
     P: FileInfoPollerParser<T>,
 {
+    pub fn stream_parser(mut self, parser: impl FileInfoPollerStreamParser<T>) -> Self {
+        self.stream_parser = Some(Some(StreamParser(Arc::new(parser))));
+        self
+    }
+
     pub async fn create(
         self,
     ) -> Result<(FileInfoStreamReceiver<T>, FileInfoPollerServer<T, S, P>)> {
@@ -229,9 +349,7 @@ where
                 _ = cleanup_trigger.tick() => self.clean(&self.cache).await?,
                 result = futures::future::try_join(sender.reserve().map_err(Error::from), self.get_next_file()) => {
                     let (permit, file) = result?;
-                    let byte_stream = self.config.store.get_raw(file.clone()).await?;
-                    let data = self.config.parser.parse(byte_stream).await?;
-                    let file_info_stream = FileInfoStream::new(process_name.clone(), file.clone(), data);
+                    let file_info_stream = self.parse_file(&process_name, &file).await?;
 
                     permit.send(file_info_stream);
                     cache_file(&self.cache, &file).await;
@@ -241,15 +359,38 @@ where
         Ok(())
     }
 
-    fn after(&self, latest: Option<DateTime<Utc>>) -> DateTime<Utc> {
-        let latest_offset = latest.map(|lt| lt - self.config.offset);
-        match self.config.lookback {
-            LookbackBehavior::StartAfter(start_after) => latest_offset.unwrap_or(start_after),
-            LookbackBehavior::Max(max_lookback) => {
-                let max_ts = Utc::now() - max_lookback;
-                latest_offset.map(|lt| lt.max(max_ts)).unwrap_or(max_ts)
+    async fn parse_file(&self, process_name: &str, file: &FileInfo) -> Result<FileInfoStream<T>> {
+        let byte_stream = self.config.store.get_verified(file.clone()).await?;
+        let dead_letter = self.config.dead_letter.as_ref();
+        Ok(match &self.config.stream_parser {
+            Some(StreamParser(parser)) => {
+                let data = match dead_letter {
+                    Some(dead_letter) => parser.parse_stream_with_dead_letter(
+                        file.clone(),
+                        byte_stream,
+                        dead_letter.clone(),
+                    ),
+                    None => parser.parse_stream(byte_stream),
+                };
+                FileInfoStream::new_streaming(process_name.to_string(), file.clone(), data)
             }
-        }
+            None => {
+                let data = match dead_letter {
+                    Some(dead_letter) => {
+                        self.config
+                            .parser
+                            .parse_with_dead_letter(file, byte_stream, dead_letter)
+                            .await?
+                    }
+                    None => self.config.parser.parse(byte_stream).await?,
+                };
+                FileInfoStream::new(process_name.to_string(), file.clone(), data)
+            }
+        })
+    }
+
+    fn after(&self, latest: Option<DateTime<Utc>>) -> DateTime<Utc> {
+        self.config.lookback.after(latest, self.config.offset)
     }
 
     async fn clean(&self, cache: &MemoryFileCache) -> Result {
@@ -285,30 +426,24 @@ where
     T: MsgDecode + TryFrom<T::Msg, Error = Error> + Send + Sync + 'static,
 {
     async fn parse(&self, byte_stream: ByteStream) -> Result<Vec<T>> {
-        Ok(file_store::stream_source(byte_stream)
-            .filter_map(|msg| async {
-                msg.map_err(|err| {
-                    tracing::error!(
-                        "Error streaming entry in file of type {}: {err:?}",
-                        std::any::type_name::<T>()
-                    );
-                    err
-                })
-                .ok()
-            })
-            .filter_map(|msg| async {
-                <T as MsgDecode>::decode(msg)
-                    .map_err(|err| {
-                        tracing::error!(
-                            "Error in decoding message of type {}: {err:?}",
-                            std::any::type_name::<T>()
-                        );
-                        err
-                    })
-                    .ok()
-            })
-            .collect()
-            .await)
+        decode_stream(byte_stream, None, |msg| <T as MsgDecode>::decode(msg))
+            .try_collect()
+            .await
+    }
+
+    async fn parse_with_dead_letter(
+        &self,
+        file_info: &FileInfo,
+        byte_stream: ByteStream,
+        dead_letter: &DeadLetterSink,
+    ) -> Result<Vec<T>> {
+        decode_stream(
+            byte_stream,
+            Some((file_info.clone(), dead_letter.clone())),
+            |msg| <T as MsgDecode>::decode(msg),
+        )
+        .try_collect()
+        .await
     }
 }
 
@@ -317,41 +452,134 @@ pub struct ProstFileInfoPollerParser;
 #[async_trait::async_trait]
 impl<T> FileInfoPollerParser<T> for ProstFileInfoPollerParser
 where
-    T: helium_proto::Message + Default,
+    T: helium_proto::Message + Default + 'static,
 {
     async fn parse(&self, byte_stream: ByteStream) -> Result<Vec<T>> {
-        Ok(file_store::stream_source(byte_stream)
-            .filter_map(|msg| async {
-                msg.map_err(|err| {
-                    tracing::error!(
-                        "Error streaming entry in file of type {}: {err:?}",
-                        std::any::type_name::<T>()
-                    );
-                    err
-                })
-                .ok()
-            })
-            .filter_map(|msg| async {
-                <T as helium_proto::Message>::decode(msg)
-                    .map_err(|err| {
-                        tracing::error!(
-                            "Error in decoding message of type {}: {err:?}",
-                            std::any::type_name::<T>()
-                        );
-                        err
-                    })
-                    .ok()
-            })
-            .collect()
-            .await)
+        decode_stream(byte_stream, None, |msg| {
+            <T as helium_proto::Message>::decode(msg)
+        })
+        .try_collect()
+        .await
+    }
+
+    async fn parse_with_dead_letter(
+        &self,
+        file_info: &FileInfo,
+        byte_stream: ByteStream,
+        dead_letter: &DeadLetterSink,
+    ) -> Result<Vec<T>> {
+        decode_stream(
+            byte_stream,
+            Some((file_info.clone(), dead_letter.clone())),
+            |msg| <T as helium_proto::Message>::decode(msg),
+        )
+        .try_collect()
+        .await
+    }
+}
+
+impl<T> FileInfoPollerStreamParser<T> for MsgDecodeFileInfoPollerParser
+where
+    T: MsgDecode + TryFrom<T::Msg, Error = Error> + Send + Sync + 'static,
+{
+    fn parse_stream(&self, byte_stream: ByteStream) -> Stream<T> {
+        decode_stream(byte_stream, None, |msg| <T as MsgDecode>::decode(msg))
+    }
+
+    fn parse_stream_with_dead_letter(
+        &self,
+        file_info: FileInfo,
+        byte_stream: ByteStream,
+        dead_letter: DeadLetterSink,
+    ) -> Stream<T> {
+        decode_stream(byte_stream, Some((file_info, dead_letter)), |msg| {
+            <T as MsgDecode>::decode(msg)
+        })
+    }
+}
+
+impl<T> FileInfoPollerStreamParser<T> for ProstFileInfoPollerParser
+where
+    T: helium_proto::Message + Default + 'static,
+{
+    fn parse_stream(&self, byte_stream: ByteStream) -> Stream<T> {
+        decode_stream(byte_stream, None, |msg| {
+            <T as helium_proto::Message>::decode(msg)
+        })
+    }
+
+    fn parse_stream_with_dead_letter(
+        &self,
+        file_info: FileInfo,
+        byte_stream: ByteStream,
+        dead_letter: DeadLetterSink,
+    ) -> Stream<T> {
+        decode_stream(byte_stream, Some((file_info, dead_letter)), |msg| {
+            <T as helium_proto::Message>::decode(msg)
+        })
     }
 }
 
-fn create_cache() -> MemoryFileCache {
+/// Decode the frames of a file as they are read. Frames that fail to decode
+/// are logged and, when a dead-letter sink is given, persisted to it along
+/// with the file and offset they were read from. Failures to persist a dead
+/// letter are logged and counted, decoding carries on. Errors reading the
+/// file, such as a checksum mismatch, are returned as is.
+fn decode_stream<T, E, F>(
+    byte_stream: ByteStream,
+    dead_letter: Option<(FileInfo, DeadLetterSink)>,
+    decode: F,
+) -> Stream<T>
+where
+    T: Send + 'static,
+    E: std::fmt::Debug,
+    F: Fn(bytes::Bytes) -> std::result::Result<T, E> + Send + 'static,
+{
+    dead_letter::frame_offsets(file_store::stream_source(byte_stream))
+        .try_filter_map(move |(offset, msg)| {
+            let msg = msg.freeze();
+            let result = decode(msg.clone()).map_err(|err| {
+                tracing::error!(
+                    "Error in decoding message of type {}: {err:?}",
+                    std::any::type_name::<T>()
+                );
+                format!("{err:?}")
+            });
+            let dead_letter = result.is_err().then(|| dead_letter.clone()).flatten();
+            async move {
+                match result {
+                    Ok(item) => Ok(Some(item)),
+                    Err(error) => {
+                        // Failing to keep a dead letter does not fail the
+                        // rest of the file
+                        if let Some((file_info, dead_letter)) = dead_letter {
+                            if let Err(err) =
+                                dead_letter.write(&file_info, offset, &msg, error).await
+                            {
+                                tracing::error!(
+                                    "Error writing dead letter of {} at offset {offset}: {err:?}",
+                                    file_info.key
+                                );
+                                metrics::counter!(
+                                    "file-store-dead-letter-write-errors",
+                                    "file-type" => file_info.prefix.clone(),
+                                )
+                                .increment(1);
+                            }
+                        }
+                        Ok(None)
+                    }
+                }
+            }
+        })
+        .boxed()
+}
+
+pub(crate) fn create_cache() -> MemoryFileCache {
     Arc::new(Cache::new())
 }
 
-async fn cache_file(cache: &MemoryFileCache, file_info: &FileInfo) {
+pub(crate) async fn cache_file(cache: &MemoryFileCache, file_info: &FileInfo) {
     cache.insert(file_info.key.clone(), true, CACHE_TTL).await;
 }
 
@@ -428,3 +656,176 @@ impl FileInfoPollerState for sqlx::Pool<sqlx::Postgres> {
         .map_err(Error::from)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        compression::{Compression, Encoder},
+        dead_letter::DeadLetterV1,
+        file_sink::{self, FileSinkClient},
+        FileType,
+    };
+    use futures::SinkExt;
+    use helium_proto::{EntropyReportV1, Message};
+    use tokio::io::AsyncWriteExt;
+    use tokio_util::codec::{length_delimited::LengthDelimitedCodec, FramedWrite};
+
+    struct NoopRecorder;
+
+    #[async_trait::async_trait]
+    impl FileInfoPollerStateRecorder for NoopRecorder {
+        async fn record(self, _process_name: &str, _file_info: &FileInfo) -> Result {
+            Ok(())
+        }
+    }
+
+    async fn encode_reports(reports: &[EntropyReportV1]) -> Vec<u8> {
+        encode_frames(reports.iter().map(Message::encode_to_vec)).await
+    }
+
+    async fn encode_frames(frames: impl IntoIterator<Item = Vec<u8>>) -> Vec<u8> {
+        let mut transport = FramedWrite::new(
+            Compression::Gzip.encoder(Vec::new()),
+            LengthDelimitedCodec::new(),
+        );
+        for frame in frames {
+            transport.send(bytes::Bytes::from(frame)).await.unwrap();
+        }
+        let mut encoder = transport.into_inner();
+        encoder.shutdown().await.unwrap();
+        let Encoder::Gzip(encoder) = encoder else {
+            panic!("expected gzip encoder");
+        };
+        encoder.into_inner()
+    }
+
+    fn entropy_reports() -> Vec<EntropyReportV1> {
+        (0..3)
+            .map(|timestamp| EntropyReportV1 {
+                timestamp,
+                ..Default::default()
+            })
+            .collect()
+    }
+
+    fn streaming_file(bytes: Vec<u8>) -> FileInfoStream<EntropyReportV1> {
+        let data = ProstFileInfoPollerParser.parse_stream(ByteStream::from(bytes));
+        FileInfoStream::new_streaming(
+            "default".to_string(),
+            FileInfo::from((crate::FileType::EntropyReport, Utc::now())),
+            data,
+        )
+    }
+
+    #[tokio::test]
+    async fn streams_decoded_items_lazily() {
+        let reports = entropy_reports();
+        let file = streaming_file(encode_reports(&reports).await);
+
+        let streamed: Vec<EntropyReportV1> = file
+            .into_try_stream(NoopRecorder)
+            .await
+            .expect("recorded file")
+            .try_collect()
+            .await
+            .expect("streamed reports");
+        assert_eq!(reports, streamed);
+    }
+
+    #[tokio::test]
+    async fn surfaces_errors_reading_a_streaming_file() {
+        let mut bytes = encode_reports(&entropy_reports()).await;
+        bytes.truncate(bytes.len() / 2);
+        let file = streaming_file(bytes);
+
+        let result: Result<Vec<EntropyReportV1>> = file
+            .into_try_stream(NoopRecorder)
+            .await
+            .expect("recorded file")
+            .try_collect()
+            .await;
+        assert!(result.is_err());
+    }
+
+    #[tokio::test]
+    async fn refuses_to_read_streaming_files_dropping_errors() {
+        struct PanicRecorder;
+
+        #[async_trait::async_trait]
+        impl FileInfoPollerStateRecorder for PanicRecorder {
+            async fn record(self, _process_name: &str, _file_info: &FileInfo) -> Result {
+                panic!("recorded a streaming file");
+            }
+        }
+
+        let file = streaming_file(encode_reports(&entropy_reports()).await);
+
+        assert!(matches!(
+            file.into_stream(PanicRecorder).await,
+            Err(Error::StreamingFile(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn writes_undecodable_frames_to_dead_letter_sink() {
+        let report = EntropyReportV1 {
+            timestamp: 1,
+            ..Default::default()
+        };
+        let invalid = vec![0xff, 0xff];
+        let bytes = encode_frames([
+            report.encode_to_vec(),
+            invalid.clone(),
+            report.encode_to_vec(),
+        ])
+        .await;
+
+        let (sender, mut receiver) = tokio::sync::mpsc::channel(5);
+        let dead_letter = DeadLetterSink::new(FileSinkClient::new(sender, "dead_letter"));
+        let file_info = FileInfo::from((FileType::EntropyReport, Utc::now()));
+
+        let parsed: Vec<EntropyReportV1> = ProstFileInfoPollerParser
+            .parse_stream_with_dead_letter(file_info.clone(), ByteStream::from(bytes), dead_letter)
+            .try_collect()
+            .await
+            .expect("parsed reports");
+        assert_eq!(vec![report.clone(), report.clone()], parsed);
+
+        let Some(file_sink::Message::Data(_, data)) = receiver.recv().await else {
+            panic!("expected dead letter");
+        };
+        let dead_letter = DeadLetterV1::decode(data.as_slice()).expect("dead letter");
+        assert_eq!(file_info.key, dead_letter.file_key);
+        assert_eq!(4 + report.encoded_len() as u64, dead_letter.offset);
+        assert_eq!(invalid, dead_letter.data);
+        assert!(receiver.try_recv().is_err());
+    }
+
+    #[tokio::test]
+    async fn keeps_decoding_when_dead_letters_fail_to_write() {
+        let report = EntropyReportV1 {
+            timestamp: 1,
+            ..Default::default()
+        };
+        let bytes = encode_frames([
+            report.encode_to_vec(),
+            vec![0xff, 0xff],
+            report.encode_to_vec(),
+        ])
+        .await;
+
+        // A closed sink fails every dead letter write
+        let (sender, receiver) = tokio::sync::mpsc::channel(5);
+        drop(receiver);
+        let dead_letter = DeadLetterSink::new(FileSinkClient::new(sender, "dead_letter"));
+        let file_info = FileInfo::from((FileType::EntropyReport, Utc::now()));
+
+        let parsed: Vec<EntropyReportV1> = ProstFileInfoPollerParser
+            .parse_stream_with_dead_letter(file_info, ByteStream::from(bytes), dead_letter)
+            .try_collect()
+            .await
+            .expect("parsed reports");
+        assert_eq!(vec![report.clone(), report], parsed);
+    }
+}
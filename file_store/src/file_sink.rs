@@ -1,15 +1,24 @@
-use crate::{file_upload::FileUpload, Error, Result};
-use async_compression::tokio::write::GzipEncoder;
+use crate::{
+    checksum::{Checksum, HashingWriter},
+    compression::{Compression, Encoder},
+    file_upload::FileUpload,
+    manifest_journal::{FileState, JournalStates, ManifestJournal},
+    Error, Result,
+};
 use bytes::Bytes;
 use chrono::{DateTime, Utc};
 use futures::{future::LocalBoxFuture, SinkExt, TryFutureExt};
 use metrics::Label;
 use std::time::Duration;
 use std::{
+    collections::HashMap,
     io, mem,
     path::{Path, PathBuf},
 };
-use task_manager::ManagedTask;
+use task_manager::{
+    health::{Check, CheckStatus},
+    ManagedTask,
+};
 use tokio::{
     fs::{self, File, OpenOptions},
     io::{AsyncWriteExt, BufWriter},
@@ -30,7 +39,7 @@ pub const SINK_CHECK_MILLIS: u64 = 50;
 
 pub const MAX_FRAME_LENGTH: usize = 15_000_000;
 
-type Sink = GzipEncoder<BufWriter<File>>;
+type Sink = Encoder<HashingWriter<BufWriter<File>>>;
 type Transport = FramedWrite<Sink, LengthDelimitedCodec>;
 pub type FileManifest = Vec<String>;
 
@@ -49,6 +58,8 @@ pub enum Message {
     Data(oneshot::Sender<Result>, Vec<u8>),
     Commit(oneshot::Sender<Result<FileManifest>>),
     Rollback(oneshot::Sender<Result<FileManifest>>),
+    Acknowledge(oneshot::Sender<Result>, FileManifest),
+    AwaitCommit(oneshot::Sender<Result>),
 }
 
 pub type MessageSender = mpsc::Sender<Message>;
@@ -66,6 +77,8 @@ pub struct FileSinkBuilder {
     roll_time: Duration,
     file_upload: FileUpload,
     auto_commit: bool,
+    acknowledgements: bool,
+    compression: Compression,
     metric: &'static str,
 }
 
@@ -76,6 +89,7 @@ impl FileSinkBuilder {
         file_upload: FileUpload,
         metric: &'static str,
     ) -> Self {
+        let compression = file_upload.compression;
         Self {
             prefix: prefix.to_string(),
             target_path: target_path.to_path_buf(),
@@ -84,6 +98,8 @@ impl FileSinkBuilder {
             roll_time: Duration::from_secs(DEFAULT_SINK_ROLL_SECS),
             file_upload,
             auto_commit: true,
+            acknowledgements: false,
+            compression,
             metric,
         }
     }
@@ -113,6 +129,22 @@ impl FileSinkBuilder {
         }
     }
 
+    /// Keep uploaded files in the journal until the owner of the sink
+    /// acknowledges them with [`FileSinkClient::acknowledge`]
+    pub fn acknowledgements(self, acknowledgements: bool) -> Self {
+        Self {
+            acknowledgements,
+            ..self
+        }
+    }
+
+    pub fn compression(self, compression: Compression) -> Self {
+        Self {
+            compression,
+            ..self
+        }
+    }
+
     pub fn roll_time(self, duration: Duration) -> Self {
         Self {
             roll_time: duration,
@@ -122,6 +154,7 @@ impl FileSinkBuilder {
 
     pub async fn create(self) -> Result<(FileSinkClient, FileSink)> {
         let (tx, rx) = message_channel(50);
+        let weak_tx = tx.downgrade();
 
         let client = FileSinkClient {
             sender: tx,
@@ -130,6 +163,12 @@ impl FileSinkBuilder {
 
         metrics::counter!(client.metric, vec![OK_LABEL]);
 
+        fs::create_dir_all(&self.target_path).await?;
+        // Uploaded files are only kept in the journal for owners that
+        // acknowledge them, otherwise nothing would ever remove them
+        let (journal, journal_states) =
+            ManifestJournal::open(&self.target_path, &self.prefix, !self.acknowledgements).await?;
+
         let mut sink = FileSink {
             target_path: self.target_path,
             tmp_path: self.tmp_path,
@@ -138,11 +177,16 @@ impl FileSinkBuilder {
             file_upload: self.file_upload,
             roll_time: self.roll_time,
             messages: rx,
+            sender: weak_tx,
             staged_files: Vec::new(),
             auto_commit: self.auto_commit,
+            compression: self.compression,
             active_sink: None,
+            checksums: HashMap::new(),
+            commit_waiters: Vec::new(),
+            journal,
         };
-        sink.init().await?;
+        sink.init(journal_states).await?;
         Ok((client, sink))
     }
 }
@@ -241,6 +285,42 @@ impl FileSinkClient {
             })
             .map(|_| on_rollback_rx)
     }
+
+    /// Wait for every frame written before this call to be committed. The
+    /// returned receiver resolves once the files holding them have been
+    /// handed to the uploader, which for an auto committing sink can take up
+    /// to its roll time.
+    pub async fn await_commit(&self) -> Result<oneshot::Receiver<Result>> {
+        let (on_commit_tx, on_commit_rx) = oneshot::channel();
+        self.sender
+            .send(Message::AwaitCommit(on_commit_tx))
+            .await
+            .map_err(|e| {
+                tracing::error!(
+                    "file_sink failed to await commit for {:?} with {e:?}",
+                    self.metric
+                );
+                Error::channel()
+            })
+            .map(|_| on_commit_rx)
+    }
+
+    /// Acknowledge that the files in a manifest returned by a commit have
+    /// been fully handled by the caller so the sink can stop tracking them
+    pub async fn acknowledge(&self, manifest: FileManifest) -> Result<oneshot::Receiver<Result>> {
+        let (on_ack_tx, on_ack_rx) = oneshot::channel();
+        self.sender
+            .send(Message::Acknowledge(on_ack_tx, manifest))
+            .await
+            .map_err(|e| {
+                tracing::error!(
+                    "file_sink failed to acknowledge for {:?} with {e:?}",
+                    self.metric
+                );
+                Error::channel()
+            })
+            .map(|_| on_ack_rx)
+    }
 }
 
 #[derive(Debug)]
@@ -252,24 +332,36 @@ pub struct FileSink {
     roll_time: Duration,
 
     messages: MessageReceiver,
+    /// Used to report the backlog of queued messages without keeping the
+    /// channel open
+    sender: mpsc::WeakSender<Message>,
     file_upload: FileUpload,
     staged_files: Vec<PathBuf>,
     auto_commit: bool,
+    compression: Compression,
 
     active_sink: Option<ActiveSink>,
+    /// Checksums of the closed sink files, computed while writing them
+    checksums: HashMap<PathBuf, Checksum>,
+    /// Writers waiting for the currently staged frames to be committed
+    commit_waiters: Vec<oneshot::Sender<Result>>,
+    journal: ManifestJournal,
 }
 
 #[derive(Debug)]
 struct ActiveSink {
+    path: PathBuf,
     size: usize,
     time: DateTime<Utc>,
     transport: Transport,
 }
 
 impl ActiveSink {
-    async fn shutdown(&mut self) -> Result {
-        transport_sink(&mut self.transport).shutdown().await?;
-        Ok(())
+    /// Close the sink file, returning the checksum of its content
+    async fn shutdown(&mut self) -> Result<Checksum> {
+        let sink = transport_sink(&mut self.transport);
+        sink.shutdown().await?;
+        Ok(sink.get_ref().checksum())
     }
 }
 
@@ -286,14 +378,36 @@ impl ManagedTask for FileSink {
                 .and_then(|result| async move { result.map_err(anyhow::Error::from) }),
         )
     }
+
+    fn name(&self) -> String {
+        format!("file_sink:{}", self.prefix)
+    }
+
+    fn health_check(&self) -> Option<Check> {
+        let sender = self.sender.clone();
+        Some(Box::new(move || match sender.upgrade() {
+            Some(sender) => {
+                let backlog = sender.max_capacity() - sender.capacity();
+                let detail = format!("{backlog} messages queued");
+                if sender.capacity() == 0 {
+                    CheckStatus::unhealthy(detail)
+                } else {
+                    CheckStatus::healthy(detail)
+                }
+            }
+            None => CheckStatus::healthy("no writers left"),
+        }))
+    }
 }
 
 impl FileSink {
-    async fn init(&mut self) -> Result {
+    async fn init(&mut self, journal_states: JournalStates) -> Result {
         fs::create_dir_all(&self.target_path).await?;
         fs::create_dir_all(&self.tmp_path).await?;
 
-        // Notify all existing completed sinks via file uploads
+        // Notify all existing completed sinks via file uploads unless the
+        // journal shows they were already uploaded. Files acknowledged
+        // before their upload was recorded are uploaded again.
         let mut dir = fs::read_dir(&self.target_path).await?;
         loop {
             match dir.next_entry().await {
@@ -303,7 +417,20 @@ impl FileSink {
                         .to_string_lossy()
                         .starts_with(&self.prefix) =>
                 {
-                    self.file_upload.upload_file(&entry.path()).await?;
+                    let file_name = entry.file_name().to_string_lossy().to_string();
+                    match journal_states.get(&file_name) {
+                        Some(FileState::Uploaded | FileState::Acknowledged) => {
+                            let _ = fs::remove_file(&entry.path()).await;
+                        }
+                        state => {
+                            if state.is_none() {
+                                self.journal.record(&file_name, FileState::Rolled).await?;
+                            }
+                            self.file_upload
+                                .upload_journaled_file(&entry.path(), &self.journal, None)
+                                .await?;
+                        }
+                    }
                 }
                 Ok(None) => break,
                 _ => continue,
@@ -350,31 +477,15 @@ impl FileSink {
                 _ = shutdown.clone() => break,
                 _ = rollover_timer.tick() => self.maybe_roll().await?,
                 msg = self.messages.recv() => match msg {
-                    Some(Message::Data(on_write_tx, bytes)) => {
-                        let res = match self.write(Bytes::from(bytes)).await {
-                            Ok(_) => Ok(()),
-                            Err(err) => {
-                                tracing::error!("failed to store {}: {err:?}", &self.prefix);
-                                Err(err)
-                            }
-                        };
-                        let _ = on_write_tx.send(res);
-                    }
-                    Some(Message::Commit(on_commit_tx)) => {
-                        let res = self.commit().await;
-                        let _ = on_commit_tx.send(res);
-                    }
-                    Some(Message::Rollback(on_rollback_tx)) => {
-                        let res = self.rollback().await;
-                        let _ = on_rollback_tx.send(res);
-                    }
-                    None => {
-                        break
-                    }
+                    Some(msg) => self.handle(msg).await,
+                    None => break,
                 }
             }
         }
         tracing::info!("stopping file sink {}", &self.prefix);
+        if self.auto_commit {
+            self.drain().await;
+        }
         if let Some(active_sink) = self.active_sink.as_mut() {
             let _ = active_sink.shutdown().await;
             self.active_sink = None;
@@ -382,22 +493,84 @@ impl FileSink {
         Ok(())
     }
 
+    async fn handle(&mut self, msg: Message) {
+        match msg {
+            Message::Data(on_write_tx, bytes) => {
+                let res = match self.write(Bytes::from(bytes)).await {
+                    Ok(_) => Ok(()),
+                    Err(err) => {
+                        tracing::error!("failed to store {}: {err:?}", &self.prefix);
+                        Err(err)
+                    }
+                };
+                let _ = on_write_tx.send(res);
+            }
+            Message::Commit(on_commit_tx) => {
+                let res = self.commit().await;
+                let _ = on_commit_tx.send(res);
+            }
+            Message::Rollback(on_rollback_tx) => {
+                let res = self.rollback().await;
+                let _ = on_rollback_tx.send(res);
+            }
+            Message::Acknowledge(on_ack_tx, manifest) => {
+                let res = self.acknowledge(manifest).await;
+                let _ = on_ack_tx.send(res);
+            }
+            Message::AwaitCommit(on_commit_tx) => {
+                if self.active_sink.is_none() && self.staged_files.is_empty() {
+                    let _ = on_commit_tx.send(Ok(()));
+                } else {
+                    self.commit_waiters.push(on_commit_tx);
+                }
+            }
+        }
+    }
+
+    /// Write the messages queued when the sink was stopped and commit them,
+    /// so reports accepted before shutdown are handed to the uploader rather
+    /// than left in the tmp folder until restart. Only done for auto
+    /// committing sinks, as anything else is committed by its owner.
+    async fn drain(&mut self) {
+        self.messages.close();
+        let mut drained = 0;
+        while let Some(msg) = self.messages.recv().await {
+            self.handle(msg).await;
+            drained += 1;
+        }
+        if drained > 0 {
+            tracing::info!("file sink {} drained {drained} messages", &self.prefix);
+        }
+        if let Err(err) = self.commit().await {
+            tracing::error!(
+                "file sink {} failed to commit on shutdown, files left for restart: {err:?}",
+                &self.prefix
+            );
+        }
+    }
+
     async fn new_sink(&mut self) -> Result {
         let sink_time = Utc::now();
-        let filename = format!("{}.{}.gz", self.prefix, sink_time.timestamp_millis());
+        let filename = format!(
+            "{}.{}{}",
+            self.prefix,
+            sink_time.timestamp_millis(),
+            self.compression.extension()
+        );
         let new_path = self.tmp_path.join(filename);
-        let writer = GzipEncoder::new(BufWriter::new(
+        let writer = self.compression.encoder(HashingWriter::new(BufWriter::new(
             OpenOptions::new()
                 .write(true)
                 .create(true)
                 .truncate(true)
                 .open(&new_path)
                 .await?,
-        ));
+        )));
 
-        self.staged_files.push(new_path);
+        self.staged_files.push(new_path.clone());
 
         self.active_sink = Some(ActiveSink {
+            path: new_path,
             size: 0,
             time: sink_time,
             transport: new_transport(writer),
@@ -407,6 +580,21 @@ impl FileSink {
     }
 
     pub async fn commit(&mut self) -> Result<FileManifest> {
+        let result = self.commit_staged().await;
+        let waiter_result = || match &result {
+            Ok(_) => Ok(()),
+            Err(err) => Err(Error::from(io::Error::new(
+                io::ErrorKind::Other,
+                format!("commit failed: {err}"),
+            ))),
+        };
+        for waiter in self.commit_waiters.drain(..) {
+            let _ = waiter.send(waiter_result());
+        }
+        result
+    }
+
+    async fn commit_staged(&mut self) -> Result<FileManifest> {
         self.maybe_close_active_sink().await?;
 
         let mut manifest: FileManifest = Vec::new();
@@ -421,12 +609,19 @@ impl FileSink {
     }
 
     pub async fn rollback(&mut self) -> Result<FileManifest> {
+        for waiter in self.commit_waiters.drain(..) {
+            let _ = waiter.send(Err(Error::from(io::Error::new(
+                io::ErrorKind::Other,
+                "staged files rolled back",
+            ))));
+        }
         self.maybe_close_active_sink().await?;
 
         let mut manifest: FileManifest = Vec::new();
         let staged_files = mem::take(&mut self.staged_files);
 
         for staged_file in staged_files.into_iter() {
+            self.checksums.remove(&staged_file);
             fs::remove_file(&staged_file).await?;
             manifest.push(file_name(&staged_file)?);
         }
@@ -434,6 +629,15 @@ impl FileSink {
         Ok(manifest)
     }
 
+    pub async fn acknowledge(&mut self, manifest: FileManifest) -> Result {
+        for file_name in manifest.iter() {
+            self.journal
+                .record(file_name, FileState::Acknowledged)
+                .await?;
+        }
+        Ok(())
+    }
+
     pub async fn maybe_roll(&mut self) -> Result {
         if let Some(active_sink) = self.active_sink.as_mut() {
             if (active_sink.time + self.roll_time) <= Utc::now() {
@@ -449,7 +653,8 @@ impl FileSink {
 
     async fn maybe_close_active_sink(&mut self) -> Result {
         if let Some(active_sink) = self.active_sink.as_mut() {
-            active_sink.shutdown().await?;
+            let checksum = active_sink.shutdown().await?;
+            self.checksums.insert(active_sink.path.clone(), checksum);
             self.active_sink = None;
         }
 
@@ -468,8 +673,17 @@ impl FileSink {
         })?;
         let target_path = self.target_path.join(target_filename);
 
+        // Partial files recovered on startup have no checksum yet, the
+        // uploader computes it from the file instead
+        let checksum = self.checksums.remove(sink_path);
+
         fs::rename(&sink_path, &target_path).await?;
-        self.file_upload.upload_file(&target_path).await?;
+        self.journal
+            .record(&file_name(&target_path)?, FileState::Rolled)
+            .await?;
+        self.file_upload
+            .upload_journaled_file(&target_path, &self.journal, checksum)
+            .await?;
 
         Ok(())
     }
@@ -483,7 +697,7 @@ impl FileSink {
             // active sink is usable.
             Some(active_sink) => {
                 if active_sink.size + buf_len >= self.max_size {
-                    active_sink.shutdown().await?;
+                    self.maybe_close_active_sink().await?;
                     if self.auto_commit {
                         self.commit().await?;
                     }
@@ -537,6 +751,7 @@ mod tests {
         let (file_upload_tx, _file_upload_rx) = file_upload::message_channel();
         let file_upload = FileUpload {
             sender: file_upload_tx,
+            compression: Compression::default(),
         };
 
         let (file_sink_client, file_sink_server) = FileSinkBuilder::new(
@@ -578,6 +793,60 @@ mod tests {
         assert_eq!("hello", read_file(&entropy_file).await);
     }
 
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn writes_a_framed_zstd_encoded_file() {
+        let tmp_dir = TempDir::new().expect("Unable to create temp dir");
+        let (shutdown_trigger, shutdown_listener) = triggered::trigger();
+        let (file_upload_tx, _file_upload_rx) = file_upload::message_channel();
+        let file_upload = FileUpload {
+            sender: file_upload_tx,
+            compression: Compression::default(),
+        };
+
+        let (file_sink_client, file_sink_server) = FileSinkBuilder::new(
+            FileType::EntropyReport,
+            tmp_dir.path(),
+            file_upload,
+            "fake_metric",
+        )
+        .roll_time(Duration::from_millis(100))
+        .compression(Compression::Zstd)
+        .create()
+        .await
+        .expect("failed to create file sink");
+
+        let sink_thread = tokio::spawn(async move {
+            file_sink_server
+                .run(shutdown_listener.clone())
+                .await
+                .expect("failed to complete file sink");
+        });
+
+        let (on_write_tx, _on_write_rx) = oneshot::channel();
+
+        file_sink_client
+            .sender
+            .try_send(Message::Data(
+                on_write_tx,
+                String::into_bytes("hello".to_string()),
+            ))
+            .expect("failed to send bytes to file sink");
+
+        tokio::time::sleep(time::Duration::from_millis(200)).await;
+
+        shutdown_trigger.trigger();
+        sink_thread.await.expect("file sink did not complete");
+
+        let entropy_file = get_entropy_file(&tmp_dir)
+            .await
+            .expect("no entropy available");
+        assert!(entropy_file
+            .file_name()
+            .to_string_lossy()
+            .ends_with(Compression::Zstd.extension()));
+        assert_eq!("hello", read_file(&entropy_file).await);
+    }
+
     #[tokio::test]
     async fn only_uploads_after_commit_when_auto_commit_is_false() {
         let tmp_dir = TempDir::new().expect("Unable to create temp dir");
@@ -585,6 +854,7 @@ mod tests {
         let (file_upload_tx, mut file_upload_rx) = file_upload::message_channel();
         let file_upload = FileUpload {
             sender: file_upload_tx,
+            compression: Compression::default(),
         };
 
         let (file_sink_client, file_sink_server) = FileSinkBuilder::new(
@@ -626,7 +896,12 @@ mod tests {
         let receiver = file_sink_client.commit().await.expect("commit failed");
         let _ = receiver.await.expect("commit didn't complete completed");
 
-        assert!(file_upload_rx.try_recv().is_ok());
+        // The checksum computed while writing matches the rolled file
+        let request = file_upload_rx.try_recv().expect("no upload requested");
+        assert_eq!(
+            Some(Checksum::of_file(&request.path).await.expect("checksum")),
+            request.checksum
+        );
 
         let entropy_file = get_entropy_file(&tmp_dir)
             .await
@@ -637,6 +912,285 @@ mod tests {
         sink_thread.await.expect("file sink did not complete");
     }
 
+    #[tokio::test]
+    async fn does_not_reupload_journaled_files_on_restart() {
+        let tmp_dir = TempDir::new().expect("Unable to create temp dir");
+        let (shutdown_trigger, shutdown_listener) = triggered::trigger();
+        let (file_upload_tx, mut file_upload_rx) = file_upload::message_channel();
+        let file_upload = FileUpload {
+            sender: file_upload_tx,
+            compression: Compression::default(),
+        };
+
+        let (file_sink_client, file_sink_server) = FileSinkBuilder::new(
+            FileType::EntropyReport,
+            tmp_dir.path(),
+            file_upload.clone(),
+            "fake_metric",
+        )
+        .auto_commit(false)
+        .create()
+        .await
+        .expect("failed to create file sink");
+
+        let sink_thread = tokio::spawn(async move {
+            file_sink_server
+                .run(shutdown_listener.clone())
+                .await
+                .expect("failed to complete file sink");
+        });
+
+        file_sink_client
+            .write(helium_proto::EntropyReportV1::default(), [])
+            .await
+            .expect("failed to write to file sink")
+            .await
+            .expect("write didn't complete")
+            .expect("write failed");
+        let manifest = file_sink_client
+            .commit()
+            .await
+            .expect("commit failed")
+            .await
+            .expect("commit didn't complete")
+            .expect("commit failed");
+        assert_eq!(1, manifest.len());
+
+        // Act as the upload server, but stop before removing the local file
+        let request = file_upload_rx.try_recv().expect("no upload requested");
+        request
+            .journal
+            .expect("upload without journal")
+            .record(&manifest[0], FileState::Uploaded)
+            .await
+            .expect("failed to journal upload");
+
+        shutdown_trigger.trigger();
+        sink_thread.await.expect("file sink did not complete");
+
+        let (_file_sink_client, _file_sink_server) = FileSinkBuilder::new(
+            FileType::EntropyReport,
+            tmp_dir.path(),
+            file_upload,
+            "fake_metric",
+        )
+        .auto_commit(false)
+        .create()
+        .await
+        .expect("failed to create file sink");
+
+        assert_eq!(
+            Err(tokio::sync::mpsc::error::TryRecvError::Empty),
+            file_upload_rx.try_recv()
+        );
+        assert!(get_entropy_file(&tmp_dir).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn reuploads_files_acknowledged_before_upload_on_restart() {
+        let tmp_dir = TempDir::new().expect("Unable to create temp dir");
+        let (shutdown_trigger, shutdown_listener) = triggered::trigger();
+        let (file_upload_tx, mut file_upload_rx) = file_upload::message_channel();
+        let file_upload = FileUpload {
+            sender: file_upload_tx,
+            compression: Compression::default(),
+        };
+
+        let (file_sink_client, file_sink_server) = FileSinkBuilder::new(
+            FileType::EntropyReport,
+            tmp_dir.path(),
+            file_upload.clone(),
+            "fake_metric",
+        )
+        .auto_commit(false)
+        .acknowledgements(true)
+        .create()
+        .await
+        .expect("failed to create file sink");
+
+        let sink_thread = tokio::spawn(async move {
+            file_sink_server
+                .run(shutdown_listener.clone())
+                .await
+                .expect("failed to complete file sink");
+        });
+
+        file_sink_client
+            .write(helium_proto::EntropyReportV1::default(), [])
+            .await
+            .expect("failed to write to file sink")
+            .await
+            .expect("write didn't complete")
+            .expect("write failed");
+        let manifest = file_sink_client
+            .commit()
+            .await
+            .expect("commit failed")
+            .await
+            .expect("commit didn't complete")
+            .expect("commit failed");
+        file_sink_client
+            .acknowledge(manifest.clone())
+            .await
+            .expect("acknowledge failed")
+            .await
+            .expect("acknowledge didn't complete")
+            .expect("acknowledge failed");
+
+        // Crash before the upload server got to the file
+        file_upload_rx.try_recv().expect("no upload requested");
+        shutdown_trigger.trigger();
+        sink_thread.await.expect("file sink did not complete");
+
+        let (_file_sink_client, restarted_sink) = FileSinkBuilder::new(
+            FileType::EntropyReport,
+            tmp_dir.path(),
+            file_upload.clone(),
+            "fake_metric",
+        )
+        .auto_commit(false)
+        .acknowledgements(true)
+        .create()
+        .await
+        .expect("failed to create file sink");
+
+        let request = file_upload_rx.try_recv().expect("no upload requested");
+        assert_eq!(manifest[0], file_name(&request.path).unwrap());
+        assert!(get_entropy_file(&tmp_dir).await.is_ok());
+
+        // Once uploaded, the file is acknowledged and no longer uploaded
+        request
+            .journal
+            .expect("upload without journal")
+            .record(&manifest[0], FileState::Uploaded)
+            .await
+            .expect("failed to journal upload");
+        drop(restarted_sink);
+
+        let (_file_sink_client, _file_sink_server) = FileSinkBuilder::new(
+            FileType::EntropyReport,
+            tmp_dir.path(),
+            file_upload,
+            "fake_metric",
+        )
+        .auto_commit(false)
+        .acknowledgements(true)
+        .create()
+        .await
+        .expect("failed to create file sink");
+
+        assert_eq!(
+            Err(tokio::sync::mpsc::error::TryRecvError::Empty),
+            file_upload_rx.try_recv()
+        );
+        assert!(get_entropy_file(&tmp_dir).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn await_commit_resolves_once_written_frames_are_committed() {
+        let tmp_dir = TempDir::new().expect("Unable to create temp dir");
+        let (shutdown_trigger, shutdown_listener) = triggered::trigger();
+        let (file_upload_tx, mut file_upload_rx) = file_upload::message_channel();
+        let file_upload = FileUpload {
+            sender: file_upload_tx,
+            compression: Compression::default(),
+        };
+
+        let (file_sink_client, file_sink_server) = FileSinkBuilder::new(
+            FileType::EntropyReport,
+            tmp_dir.path(),
+            file_upload,
+            "fake_metric",
+        )
+        .roll_time(Duration::from_millis(100))
+        .create()
+        .await
+        .expect("failed to create file sink");
+
+        let sink_thread = tokio::spawn(async move {
+            file_sink_server
+                .run(shutdown_listener.clone())
+                .await
+                .expect("failed to complete file sink");
+        });
+
+        // Nothing staged, resolves right away
+        file_sink_client
+            .await_commit()
+            .await
+            .expect("await commit failed")
+            .await
+            .expect("await commit didn't complete")
+            .expect("commit failed");
+
+        file_sink_client
+            .write(helium_proto::EntropyReportV1::default(), [])
+            .await
+            .expect("failed to write to file sink")
+            .await
+            .expect("write didn't complete")
+            .expect("write failed");
+        let committed = file_sink_client
+            .await_commit()
+            .await
+            .expect("await commit failed");
+        assert_eq!(
+            Err(tokio::sync::mpsc::error::TryRecvError::Empty),
+            file_upload_rx.try_recv()
+        );
+
+        time::timeout(Duration::from_secs(2), committed)
+            .await
+            .expect("commit timed out")
+            .expect("await commit didn't complete")
+            .expect("commit failed");
+        assert!(file_upload_rx.try_recv().is_ok());
+
+        shutdown_trigger.trigger();
+        sink_thread.await.expect("file sink did not complete");
+    }
+
+    #[tokio::test]
+    async fn drains_and_commits_queued_messages_on_shutdown() {
+        let tmp_dir = TempDir::new().expect("Unable to create temp dir");
+        let (shutdown_trigger, shutdown_listener) = triggered::trigger();
+        let (file_upload_tx, mut file_upload_rx) = file_upload::message_channel();
+        let file_upload = FileUpload {
+            sender: file_upload_tx,
+            compression: Compression::default(),
+        };
+
+        let (file_sink_client, file_sink_server) = FileSinkBuilder::new(
+            FileType::EntropyReport,
+            tmp_dir.path(),
+            file_upload,
+            "fake_metric",
+        )
+        .create()
+        .await
+        .expect("failed to create file sink");
+
+        // Queued before the sink gets to run, which then sees the shutdown
+        // first
+        let written = file_sink_client
+            .write(helium_proto::EntropyReportV1::default(), [])
+            .await
+            .expect("failed to write to file sink");
+        shutdown_trigger.trigger();
+        file_sink_server
+            .run(shutdown_listener)
+            .await
+            .expect("failed to complete file sink");
+
+        written
+            .await
+            .expect("write didn't complete")
+            .expect("write failed");
+        assert!(file_upload_rx.try_recv().is_ok());
+        assert!(get_entropy_file(&tmp_dir).await.is_ok());
+        assert!(file_sink_client.sender.is_closed());
+    }
+
     async fn read_file(entry: &DirEntry) -> bytes::BytesMut {
         file_source::source([entry.path()])
             .next()
@@ -1,15 +1,15 @@
 use crate::{
+    compression,
     file_info_poller::{FileInfoPollerConfigBuilder, MsgDecodeFileInfoPollerParser},
     file_sink, BytesMutStream, Error,
 };
-use async_compression::tokio::bufread::GzipDecoder;
 use futures::{
     stream::{self},
-    StreamExt, TryFutureExt, TryStreamExt,
+    StreamExt, TryFutureExt,
 };
 use std::path::{Path, PathBuf};
 use tokio::{fs::File, io::BufReader};
-use tokio_util::codec::{length_delimited::LengthDelimitedCodec, FramedRead};
+use tokio_util::codec::length_delimited::LengthDelimitedCodec;
 
 pub fn continuous_source<T, S>() -> FileInfoPollerConfigBuilder<T, S, MsgDecodeFileInfoPollerParser>
 where
@@ -38,9 +38,7 @@ where
                     .max_frame_length(file_sink::MAX_FRAME_LENGTH)
                     .new_codec();
 
-                FramedRead::new(GzipDecoder::new(buf_reader), codec)
-                    .map_err(Error::from)
-                    .boxed()
+                compression::framed_source(buf_reader, codec)
             }
             Err(err) => stream::once(async { Err(err) }).boxed(),
         })
@@ -76,6 +74,10 @@ mod test {
             region: "us-east-1".to_string(),
             access_key_id: None,
             secret_access_key: None,
+            backend: Default::default(),
+            local_root: None,
+            checksum_sidecar: false,
+            compression: Default::default(),
         };
 
         let file_store = FileStore::from_settings(&settings)
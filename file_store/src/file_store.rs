@@ -1,6 +1,9 @@
 use crate::{
+    checksum::{self, Checksum},
+    compression,
     error::DecodeError,
-    settings::{self, Settings},
+    local_store::LocalStore,
+    settings::{self, Settings, StoreBackend},
     BytesMutStream, Error, FileInfo, FileInfoStream, Result,
 };
 use aws_config::meta::region::RegionProviderChain;
@@ -9,13 +12,44 @@ use chrono::{DateTime, Utc};
 use futures::FutureExt;
 use futures::{stream, StreamExt, TryFutureExt, TryStreamExt};
 use http::Uri;
-use std::path::Path;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
 use std::str::FromStr;
+use std::sync::Arc;
+use tokio::fs;
+
+/// Storage operations a [`FileStore`] delegates to. Implementations must
+/// honour the `FileInfo` key and timestamp naming so that listings and
+/// time range filters behave the same regardless of where files live.
+#[async_trait::async_trait]
+pub trait FileStoreBackend: std::fmt::Debug + Send + Sync + 'static {
+    /// List files with the given prefix with a timestamp after `after`
+    /// (exclusive) and up to `before` (inclusive), ordered by key.
+    fn list(
+        &self,
+        prefix: &str,
+        after: Option<DateTime<Utc>>,
+        before: Option<DateTime<Utc>>,
+    ) -> FileInfoStream;
+
+    /// Store a file under its file name, along with the checksum of its
+    /// content if given
+    async fn put(&self, file: &Path, checksum: Option<&Checksum>) -> Result;
+
+    async fn remove(&self, key: &str) -> Result;
+
+    async fn get_raw(&self, key: String) -> Result<ByteStream>;
+
+    /// Get a file along with the checksum it was stored with, if any
+    async fn get_checksummed(&self, key: String) -> Result<(ByteStream, Option<Checksum>)> {
+        Ok((self.get_raw(key).await?, None))
+    }
+}
 
 #[derive(Debug, Clone)]
 pub struct FileStore {
     pub(crate) bucket: String,
-    client: Client,
+    backend: Arc<dyn FileStoreBackend>,
 }
 
 pub struct FileData {
@@ -23,7 +57,182 @@ pub struct FileData {
     pub stream: BytesMutStream,
 }
 
+#[derive(Debug, Clone)]
+pub struct S3Store {
+    bucket: String,
+    client: Client,
+}
+
 impl FileStore {
+    pub async fn from_settings(settings: &Settings) -> Result<Self> {
+        match settings.backend {
+            StoreBackend::S3 => S3Store::from_settings(settings)
+                .await
+                .map(|store| Self::with_backend(settings.bucket.clone(), store)),
+            StoreBackend::Local => {
+                let root = settings.local_root.clone().ok_or_else(|| {
+                    config::ConfigError::Message(
+                        "local_root is required for the local backend".to_string(),
+                    )
+                })?;
+                Ok(Self::local(root, settings.bucket.clone()))
+            }
+        }
+    }
+
+    pub async fn new(
+        bucket: String,
+        endpoint: Option<String>,
+        region: Option<String>,
+    ) -> Result<Self> {
+        S3Store::new(bucket.clone(), endpoint, region)
+            .await
+            .map(|store| Self::with_backend(bucket, store))
+    }
+
+    /// Create a store backed by the local directory `<root>/<bucket>`.
+    pub fn local(root: impl Into<PathBuf>, bucket: String) -> Self {
+        let store = LocalStore::new(root.into().join(&bucket));
+        Self::with_backend(bucket, store)
+    }
+
+    pub fn with_backend(bucket: String, backend: impl FileStoreBackend) -> Self {
+        Self {
+            bucket,
+            backend: Arc::new(backend),
+        }
+    }
+
+    pub async fn list_all<A, B>(
+        &self,
+        file_type: &str,
+        after: A,
+        before: B,
+    ) -> Result<Vec<FileInfo>>
+    where
+        A: Into<Option<DateTime<Utc>>> + Copy,
+        B: Into<Option<DateTime<Utc>>> + Copy,
+    {
+        self.list(file_type, after, before).try_collect().await
+    }
+
+    pub fn list<A, B>(&self, prefix: &str, after: A, before: B) -> FileInfoStream
+    where
+        A: Into<Option<DateTime<Utc>>> + Copy,
+        B: Into<Option<DateTime<Utc>>> + Copy,
+    {
+        self.backend.list(prefix, after.into(), before.into())
+    }
+
+    pub async fn put(&self, file: &Path) -> Result {
+        let checksum = Checksum::of_file(file).await?;
+        self.put_with_checksum(file, &checksum).await
+    }
+
+    /// Store a file along with an already computed checksum of its content
+    pub async fn put_with_checksum(&self, file: &Path, checksum: &Checksum) -> Result {
+        poc_metrics::record_duration!(
+            "file_store_put_duration",
+            self.backend.put(file, Some(checksum)).await
+        )
+    }
+
+    /// Store the checksum of a file as a `<key>.sha256` sidecar object for
+    /// consumers that can't read object metadata
+    pub async fn put_sidecar(&self, file: &Path, checksum: &Checksum) -> Result {
+        let file_name = file
+            .file_name()
+            .ok_or_else(|| Error::not_found(format!("could not open {}", file.display())))?;
+        // Staged in a directory of its own, outside of the sink directories,
+        // so concurrent puts of the same file name don't clash and a
+        // leftover sidecar is never mistaken for a sink file
+        let staging = tempfile::tempdir()?;
+        let sidecar = checksum::sidecar_path(&staging.path().join(file_name));
+        fs::write(&sidecar, checksum.as_str()).await?;
+        self.backend.put(&sidecar, None).await
+    }
+
+    pub async fn remove(&self, key: &str) -> Result {
+        poc_metrics::record_duration!("file_store_remove_duration", self.backend.remove(key).await)
+    }
+
+    pub async fn get_raw<K>(&self, key: K) -> Result<ByteStream>
+    where
+        K: Into<String>,
+    {
+        self.backend.get_raw(key.into()).await
+    }
+
+    /// Get a file along with the checksum it was stored with, if any
+    pub async fn get_checksummed<K>(&self, key: K) -> Result<(ByteStream, Option<Checksum>)>
+    where
+        K: Into<String>,
+    {
+        self.backend.get_checksummed(key.into()).await
+    }
+
+    /// Get a file, verifying its content against the checksum it was
+    /// stored with as it is read. A mismatch is returned as an error at the
+    /// end of the stream. Files stored without a checksum are returned as is.
+    pub async fn get_verified<K>(&self, key: K) -> Result<ByteStream>
+    where
+        K: Into<String>,
+    {
+        let key = key.into();
+        let (stream, checksum) = self.get_checksummed(key.clone()).await?;
+        let Some(checksum) = checksum else {
+            metrics::counter!("file-store-checksum-verification", "result" => "missing")
+                .increment(1);
+            return Ok(stream);
+        };
+        Ok(verified_stream(stream, checksum.verifier(key)))
+    }
+
+    pub async fn get<K>(&self, key: K) -> Result<BytesMutStream>
+    where
+        K: Into<String>,
+    {
+        Ok(stream_source(self.get_verified(key).await?))
+    }
+
+    /// Stream a series of ordered items from the store from remote files with
+    /// the given keys.
+    pub fn source(&self, infos: FileInfoStream) -> BytesMutStream {
+        let backend = self.backend.clone();
+        infos
+            .map_ok(move |info| get_byte_stream(backend.clone(), info.key))
+            .try_buffered(2)
+            .flat_map(|stream| match stream {
+                Ok(stream) => stream_source(stream),
+                Err(err) => stream::once(async move { Err(err) }).boxed(),
+            })
+            .fuse()
+            .boxed()
+    }
+
+    /// Stream a series of unordered items from the store from remote files with
+    /// the given keys using a number of workers.  This allows for an unordered
+    /// stream of buffers to be produced as soon as available from up to
+    /// "worker" number of remote files
+    pub fn source_unordered(&self, workers: usize, infos: FileInfoStream) -> BytesMutStream {
+        let backend = self.backend.clone();
+        infos
+            .map_ok(move |info| get_byte_stream(backend.clone(), info.key))
+            .try_buffer_unordered(workers)
+            .flat_map(|stream| match stream {
+                Ok(stream) => stream_source(stream),
+                Err(err) => stream::once(async move { Err(err) }).boxed(),
+            })
+            .fuse()
+            .boxed()
+    }
+
+    pub async fn stream_file(&self, file_info: FileInfo) -> Result<BytesMutStream> {
+        self.get_raw(file_info).await.map(stream_source)
+    }
+}
+
+impl S3Store {
     pub async fn from_settings(settings: &Settings) -> Result<Self> {
         let endpoint: Option<Endpoint> = match &settings.endpoint {
             Some(endpoint) => Uri::from_str(endpoint)
@@ -84,28 +293,17 @@ impl FileStore {
         let client = Client::new(&config);
         Ok(Self { client, bucket })
     }
+}
 
-    pub async fn list_all<A, B>(
+#[async_trait::async_trait]
+impl FileStoreBackend for S3Store {
+    fn list(
         &self,
-        file_type: &str,
-        after: A,
-        before: B,
-    ) -> Result<Vec<FileInfo>>
-    where
-        A: Into<Option<DateTime<Utc>>> + Copy,
-        B: Into<Option<DateTime<Utc>>> + Copy,
-    {
-        self.list(file_type, after, before).try_collect().await
-    }
-
-    pub fn list<A, B>(&self, prefix: &str, after: A, before: B) -> FileInfoStream
-    where
-        A: Into<Option<DateTime<Utc>>> + Copy,
-        B: Into<Option<DateTime<Utc>>> + Copy,
-    {
+        prefix: &str,
+        after: Option<DateTime<Utc>>,
+        before: Option<DateTime<Utc>>,
+    ) -> FileInfoStream {
         let file_type = prefix.to_string();
-        let before = before.into();
-        let after = after.into();
 
         let request = self
             .client
@@ -140,7 +338,8 @@ impl FileStore {
                     .unwrap_or_default()
                     .into_iter()
                     .filter_map(|obj| {
-                        if FileInfo::matches(obj.key().unwrap_or_default()) {
+                        let key = obj.key().unwrap_or_default();
+                        if FileInfo::matches(key) && !checksum::is_sidecar(key) {
                             Some(FileInfo::try_from(&obj).unwrap())
                         } else {
                             None
@@ -156,121 +355,95 @@ impl FileStore {
         .boxed()
     }
 
-    pub async fn put(&self, file: &Path) -> Result {
+    async fn put(&self, file: &Path, checksum: Option<&Checksum>) -> Result {
         let byte_stream = ByteStream::from_path(&file)
             .await
             .map_err(|_| Error::not_found(format!("could not open {}", file.display())))?;
-        poc_metrics::record_duration!(
-            "file_store_put_duration",
-            self.client
-                .put_object()
-                .bucket(&self.bucket)
-                .key(file.file_name().map(|name| name.to_string_lossy()).unwrap())
-                .body(byte_stream)
-                .content_type("application/octet-stream")
-                .send()
-                .map_ok(|_| ())
-                .map_err(Error::s3_error)
-                .await
-        )
-    }
-
-    pub async fn remove(&self, key: &str) -> Result {
-        poc_metrics::record_duration!(
-            "file_store_remove_duration",
-            self.client
-                .delete_object()
-                .bucket(&self.bucket)
-                .key(key)
-                .send()
-                .map_ok(|_| ())
-                .map_err(Error::s3_error)
-                .await
-        )
-    }
-
-    pub async fn get_raw<K>(&self, key: K) -> Result<ByteStream>
-    where
-        K: Into<String>,
-    {
-        get_byte_stream(self.client.clone(), self.bucket.clone(), key).await
+        self.client
+            .put_object()
+            .bucket(&self.bucket)
+            .key(file.file_name().map(|name| name.to_string_lossy()).unwrap())
+            .body(byte_stream)
+            .content_type("application/octet-stream")
+            .set_metadata(checksum.map(|checksum| {
+                HashMap::from([(checksum::METADATA_KEY.to_string(), checksum.to_string())])
+            }))
+            .send()
+            .map_ok(|_| ())
+            .map_err(Error::s3_error)
+            .await
     }
 
-    pub async fn get<K>(&self, key: K) -> Result<BytesMutStream>
-    where
-        K: Into<String>,
-    {
-        Ok(stream_source(self.get_raw(key).await?))
+    async fn remove(&self, key: &str) -> Result {
+        self.client
+            .delete_object()
+            .bucket(&self.bucket)
+            .key(key)
+            .send()
+            .map_ok(|_| ())
+            .map_err(Error::s3_error)
+            .await
     }
 
-    /// Stream a series of ordered items from the store from remote files with
-    /// the given keys.
-    pub fn source(&self, infos: FileInfoStream) -> BytesMutStream {
-        let bucket = self.bucket.clone();
-        let client = self.client.clone();
-        infos
-            .map_ok(move |info| get_byte_stream(client.clone(), bucket.clone(), info.key))
-            .try_buffered(2)
-            .flat_map(|stream| match stream {
-                Ok(stream) => stream_source(stream),
-                Err(err) => stream::once(async move { Err(err) }).boxed(),
-            })
+    async fn get_raw(&self, key: String) -> Result<ByteStream> {
+        self.client
+            .get_object()
+            .bucket(&self.bucket)
+            .key(key)
+            .send()
+            .map_ok(|output| output.body)
+            .map_err(Error::s3_error)
             .fuse()
-            .boxed()
+            .await
     }
 
-    /// Stream a series of unordered items from the store from remote files with
-    /// the given keys using a number of workers.  This allows for an unordered
-    /// stream of buffers to be produced as soon as available from up to
-    /// "worker" number of remote files
-    pub fn source_unordered(&self, workers: usize, infos: FileInfoStream) -> BytesMutStream {
-        let bucket = self.bucket.clone();
-        let client = self.client.clone();
-        infos
-            .map_ok(move |info| get_byte_stream(client.clone(), bucket.clone(), info.key))
-            .try_buffer_unordered(workers)
-            .flat_map(|stream| match stream {
-                Ok(stream) => stream_source(stream),
-                Err(err) => stream::once(async move { Err(err) }).boxed(),
+    async fn get_checksummed(&self, key: String) -> Result<(ByteStream, Option<Checksum>)> {
+        self.client
+            .get_object()
+            .bucket(&self.bucket)
+            .key(key)
+            .send()
+            .map_ok(|output| {
+                let checksum = output
+                    .metadata()
+                    .and_then(|metadata| metadata.get(checksum::METADATA_KEY))
+                    .map(|hex| Checksum::from_hex(hex));
+                (output.body, checksum)
             })
-            .fuse()
-            .boxed()
-    }
-
-    pub async fn stream_file(&self, file_info: FileInfo) -> Result<BytesMutStream> {
-        get_byte_stream(self.client.clone(), self.bucket.clone(), file_info)
+            .map_err(Error::s3_error)
             .await
-            .map(stream_source)
     }
 }
 
 pub fn stream_source(stream: ByteStream) -> BytesMutStream {
-    use async_compression::tokio::bufread::GzipDecoder;
-    use tokio_util::{
-        codec::{length_delimited::LengthDelimitedCodec, FramedRead},
-        io::StreamReader,
-    };
-
-    Box::pin(
-        FramedRead::new(
-            GzipDecoder::new(StreamReader::new(stream)),
-            LengthDelimitedCodec::new(),
-        )
-        .map_err(Error::from),
-    )
+    use tokio_util::{codec::length_delimited::LengthDelimitedCodec, io::StreamReader};
+
+    compression::framed_source(StreamReader::new(stream), LengthDelimitedCodec::new())
+}
+
+/// Pass `stream` through `verifier`, ending it with an error when its
+/// content does not match the checksum
+fn verified_stream(stream: ByteStream, verifier: checksum::Verifier) -> ByteStream {
+    let verified = stream::unfold(Some((Box::pin(stream), verifier)), |state| async move {
+        let (mut stream, mut verifier) = state?;
+        match stream.next().await {
+            Some(Ok(bytes)) => {
+                verifier.update(&bytes);
+                Some((Ok(bytes), Some((stream, verifier))))
+            }
+            Some(Err(err)) => Some((Err(Error::from(std::io::Error::from(err))), None)),
+            None => {
+                let result = verifier.finish();
+                let label = if result.is_ok() { "ok" } else { "mismatch" };
+                metrics::counter!("file-store-checksum-verification", "result" => label)
+                    .increment(1);
+                result.err().map(|err| (Err(err), None))
+            }
+        }
+    });
+    ByteStream::from(hyper::Body::wrap_stream(verified))
 }
 
-async fn get_byte_stream<K>(client: Client, bucket: String, key: K) -> Result<ByteStream>
-where
-    K: Into<String>,
-{
-    client
-        .get_object()
-        .bucket(bucket)
-        .key(key)
-        .send()
-        .map_ok(|output| output.body)
-        .map_err(Error::s3_error)
-        .fuse()
-        .await
+async fn get_byte_stream(backend: Arc<dyn FileStoreBackend>, key: String) -> Result<ByteStream> {
+    backend.get_raw(key).await
 }
@@ -1,32 +1,60 @@
-use crate::{Error, FileStore, Result, Settings};
+use crate::{
+    checksum::Checksum,
+    manifest_journal::{FileState, ManifestJournal},
+    Compression, Error, FileStore, Result, Settings,
+};
 use futures::{future::LocalBoxFuture, StreamExt, TryFutureExt};
 use std::{
+    collections::HashSet,
     path::{Path, PathBuf},
+    pin::pin,
+    sync::Mutex,
     time::Duration,
 };
 use task_manager::ManagedTask;
 use tokio::{fs, sync::mpsc, time};
 use tokio_stream::wrappers::UnboundedReceiverStream;
 
-pub type MessageSender = mpsc::UnboundedSender<PathBuf>;
-pub type MessageReceiver = mpsc::UnboundedReceiver<PathBuf>;
+pub type MessageSender = mpsc::UnboundedSender<UploadRequest>;
+pub type MessageReceiver = mpsc::UnboundedReceiver<UploadRequest>;
+
+/// A local file to upload, with the journal of the sink that rolled it if
+/// any. The upload is recorded in the journal before the local file is
+/// removed. Files without a checksum computed while writing them are
+/// checksummed before upload.
+#[derive(Debug, PartialEq)]
+pub struct UploadRequest {
+    pub path: PathBuf,
+    pub journal: Option<ManifestJournal>,
+    pub checksum: Option<Checksum>,
+}
 
 pub fn message_channel() -> (MessageSender, MessageReceiver) {
     mpsc::unbounded_channel()
 }
 
 pub async fn upload_file(tx: &MessageSender, file: &Path) -> Result {
-    tx.send(file.to_path_buf()).map_err(|_| Error::channel())
+    tx.send(UploadRequest {
+        path: file.to_path_buf(),
+        journal: None,
+        checksum: None,
+    })
+    .map_err(|_| Error::channel())
 }
 
 #[derive(Debug, Clone)]
 pub struct FileUpload {
     pub sender: MessageSender,
+    /// Compression of the files written by sinks using this upload, unless
+    /// they set their own
+    pub compression: Compression,
 }
 
 pub struct FileUploadServer {
-    messages: UnboundedReceiverStream<PathBuf>,
+    messages: UnboundedReceiverStream<UploadRequest>,
     store: FileStore,
+    checksum_sidecar: bool,
+    drain_timeout: Option<Duration>,
 }
 
 impl FileUpload {
@@ -37,23 +65,43 @@ impl FileUpload {
         Ok(FileUploadServer {
             messages: UnboundedReceiverStream::new(messages),
             store: FileStore::from_settings(settings).await?,
+            checksum_sidecar: settings.checksum_sidecar,
+            drain_timeout: None,
         })
     }
 
     pub async fn from_settings_tm(settings: &Settings) -> Result<(Self, FileUploadServer)> {
         let (sender, receiver) = mpsc::unbounded_channel();
         Ok((
-            Self { sender },
+            Self {
+                sender,
+                compression: settings.compression,
+            },
             FileUploadServer {
                 messages: UnboundedReceiverStream::new(receiver),
                 store: FileStore::from_settings(settings).await?,
+                checksum_sidecar: settings.checksum_sidecar,
+                drain_timeout: None,
             },
         ))
     }
 
     pub async fn upload_file(&self, file: &Path) -> Result {
+        upload_file(&self.sender, file).await
+    }
+
+    pub async fn upload_journaled_file(
+        &self,
+        file: &Path,
+        journal: &ManifestJournal,
+        checksum: Option<Checksum>,
+    ) -> Result {
         self.sender
-            .send(file.to_path_buf())
+            .send(UploadRequest {
+                path: file.to_path_buf(),
+                journal: Some(journal.clone()),
+                checksum,
+            })
             .map_err(|_| Error::channel())
     }
 }
@@ -74,59 +122,158 @@ impl ManagedTask for FileUploadServer {
 }
 
 impl FileUploadServer {
+    /// On shutdown, keep uploading the queued files for up to `timeout`
+    /// instead of stopping right away
+    pub fn drain_timeout(self, timeout: Duration) -> Self {
+        Self {
+            drain_timeout: Some(timeout),
+            ..self
+        }
+    }
+
     pub async fn run(self, shutdown: triggered::Listener) -> Result {
         tracing::info!("starting file uploader {}", self.store.bucket);
 
-        let uploads = self
-            .messages
-            .map(|msg| (self.store.clone(), msg))
-            .for_each_concurrent(5, |(store, path)| async move {
-                let path_str = path.display();
-                let bucket = &store.bucket;
-                if !path.exists() {
-                    tracing::warn!("ignoring absent file {path_str}");
-                    return;
-                }
-                if !path.is_file() {
-                    tracing::warn!("ignoring non file {path_str}");
-                    return;
-                }
-                let mut retry = 0;
-                const MAX_RETRIES: u8 = 5;
-                const RETRY_WAIT: Duration = Duration::from_secs(10);
-                while retry <= MAX_RETRIES {
-                    tracing::debug!("storing {path_str} in {bucket} retry {retry}");
-                    match store.put(&path).await {
-                        Ok(()) => {
-                            match fs::remove_file(&path).await {
-                                Ok(()) => {
-                                    tracing::info!("stored {path_str} in {bucket}");
-                                }
-                                Err(err) => {
-                                    tracing::error!(
-                                        "failed to remove uploaded file {path_str}: {err:?}"
-                                    );
-                                }
-                            }
-                            return;
-                        }
-                        Err(err) => {
-                            tracing::error!(
-                                "failed to store {path_str} in {bucket} retry: {retry}: {err:?}"
-                            );
-                            retry += 1;
-                            time::sleep(RETRY_WAIT).await;
-                        }
-                    }
+        let Self {
+            mut messages,
+            store,
+            checksum_sidecar,
+            drain_timeout,
+        } = self;
+        let uploading = Mutex::new(HashSet::new());
+
+        {
+            let uploads = messages.by_ref().for_each_concurrent(5, |request| {
+                let store = store.clone();
+                let uploading = &uploading;
+                async move {
+                    let path = request.path.clone();
+                    uploading
+                        .lock()
+                        .expect("uploads poisoned")
+                        .insert(path.clone());
+                    upload(&store, request, checksum_sidecar).await;
+                    uploading.lock().expect("uploads poisoned").remove(&path);
                 }
             });
+            let mut uploads = pin!(uploads);
 
-        tokio::select! {
-            _ = uploads => (),
-            _ = shutdown.clone() => (),
+            let stopped = tokio::select! {
+                _ = &mut uploads => false,
+                _ = shutdown.clone() => true,
+            };
+            if let Some(drain_timeout) = drain_timeout.filter(|_| stopped) {
+                tracing::info!(
+                    "draining file uploader {} for up to {drain_timeout:?}",
+                    store.bucket
+                );
+                if time::timeout(drain_timeout, uploads).await.is_err() {
+                    tracing::warn!("file uploader {} drain timed out", store.bucket);
+                }
+            }
+        }
+
+        // Anything not uploaded stays on disk and is uploaded again by its
+        // sink on restart
+        let mut receiver = messages.into_inner();
+        receiver.close();
+        let mut left = uploading.into_inner().expect("uploads poisoned");
+        while let Ok(request) = receiver.try_recv() {
+            left.insert(request.path);
+        }
+        if !left.is_empty() {
+            tracing::warn!(
+                "file uploader {} stopping with {} files not uploaded",
+                store.bucket,
+                left.len()
+            );
+            for path in &left {
+                tracing::warn!("file {} not uploaded on shutdown", path.display());
+            }
         }
 
-        tracing::info!("stopping file uploader {}", self.store.bucket);
+        tracing::info!("stopping file uploader {}", store.bucket);
         Ok(())
     }
 }
+
+async fn upload(
+    store: &FileStore,
+    UploadRequest {
+        path,
+        journal,
+        checksum,
+    }: UploadRequest,
+    checksum_sidecar: bool,
+) {
+    let path_str = path.display();
+    let bucket = &store.bucket;
+    if !path.exists() {
+        tracing::warn!("ignoring absent file {path_str}");
+        return;
+    }
+    if !path.is_file() {
+        tracing::warn!("ignoring non file {path_str}");
+        return;
+    }
+    let checksum = match checksum {
+        Some(checksum) => checksum,
+        None => match Checksum::of_file(&path).await {
+            Ok(checksum) => checksum,
+            Err(err) => {
+                tracing::error!("failed to checksum {path_str}: {err:?}");
+                return;
+            }
+        },
+    };
+    let mut retry = 0;
+    const MAX_RETRIES: u8 = 5;
+    const RETRY_WAIT: Duration = Duration::from_secs(10);
+    while retry <= MAX_RETRIES {
+        tracing::debug!("storing {path_str} in {bucket} retry {retry}");
+        match store_file(store, &path, &checksum, checksum_sidecar).await {
+            Ok(()) => {
+                if let Some(journal) = journal.as_ref() {
+                    if let Err(err) = record_uploaded(journal, &path).await {
+                        // Leave the file in place, the sink will
+                        // upload it again on restart
+                        tracing::error!("failed to journal upload of {path_str}: {err:?}");
+                        return;
+                    }
+                }
+                match fs::remove_file(&path).await {
+                    Ok(()) => {
+                        tracing::info!("stored {path_str} in {bucket}");
+                    }
+                    Err(err) => {
+                        tracing::error!("failed to remove uploaded file {path_str}: {err:?}");
+                    }
+                }
+                return;
+            }
+            Err(err) => {
+                tracing::error!("failed to store {path_str} in {bucket} retry: {retry}: {err:?}");
+                retry += 1;
+                time::sleep(RETRY_WAIT).await;
+            }
+        }
+    }
+}
+
+async fn store_file(
+    store: &FileStore,
+    path: &Path,
+    checksum: &Checksum,
+    checksum_sidecar: bool,
+) -> Result {
+    store.put_with_checksum(path, checksum).await?;
+    if checksum_sidecar {
+        store.put_sidecar(path, checksum).await?;
+    }
+    Ok(())
+}
+
+async fn record_uploaded(journal: &ManifestJournal, path: &Path) -> Result {
+    let file_name = crate::file_sink::file_name(path)?;
+    journal.record(&file_name, FileState::Uploaded).await
+}
@@ -1,5 +1,8 @@
+pub mod checksum;
 pub mod cli;
+pub mod compression;
 pub mod coverage;
+pub mod dead_letter;
 pub mod entropy_report;
 mod error;
 mod file_info;
@@ -15,24 +18,29 @@ pub mod iot_invalid_poc;
 pub mod iot_packet;
 pub mod iot_valid_poc;
 pub mod iot_witness_report;
+pub mod local_store;
+pub mod manifest_journal;
 pub mod mobile_radio_invalidated_threshold;
 pub mod mobile_radio_threshold;
 pub mod mobile_session;
 pub mod mobile_subscriber;
 pub mod mobile_transfer;
+pub mod multi_file_info_poller;
+pub mod proto_json;
 pub mod reward_manifest;
 mod settings;
 pub mod speedtest;
 pub mod traits;
 pub mod wifi_heartbeat;
 
-pub use crate::file_store::FileStore;
+pub use crate::file_store::{FileStore, FileStoreBackend};
 pub use cli::bucket::FileFilter;
+pub use compression::Compression;
 pub use error::{Error, Result};
 pub use file_info::{FileInfo, FileType};
 pub use file_sink::{FileSink, FileSinkBuilder};
 pub use iot_valid_poc::SCALING_PRECISION;
-pub use settings::Settings;
+pub use settings::{Settings, StoreBackend};
 
 use bytes::BytesMut;
 use futures::stream::BoxStream;
@@ -0,0 +1,304 @@
+use crate::{
+    checksum::{self, Checksum},
+    file_store::FileStoreBackend,
+    Error, FileInfo, FileInfoStream, Result,
+};
+use aws_sdk_s3::types::ByteStream;
+use chrono::{DateTime, Utc};
+use futures::{stream, StreamExt};
+use std::{
+    io,
+    path::{Component, Path, PathBuf},
+    str::FromStr,
+};
+use tokio::fs;
+
+/// A [`FileStoreBackend`] that keeps files in a local directory instead of a
+/// remote bucket. Files are stored under their `FileInfo` key so listings
+/// behave like they do against S3.
+#[derive(Debug, Clone)]
+pub struct LocalStore {
+    dir: PathBuf,
+}
+
+impl LocalStore {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Path of the file stored under `key`. Keys are plain file names, keys
+    /// that could resolve to a path outside of the store are rejected.
+    fn key_path(&self, key: &str) -> Result<PathBuf> {
+        let mut components = Path::new(key).components();
+        match (components.next(), components.next()) {
+            (Some(Component::Normal(_)), None) if !key.contains('\\') => Ok(self.dir.join(key)),
+            _ => Err(Error::InvalidKey(key.to_string())),
+        }
+    }
+
+    /// Hidden file standing in for the object metadata holding the checksum
+    /// of a file
+    fn checksum_path(&self, key: &str) -> Result<PathBuf> {
+        self.key_path(key)?;
+        Ok(checksum::sidecar_path(&self.dir.join(format!(".{key}"))))
+    }
+}
+
+#[async_trait::async_trait]
+impl FileStoreBackend for LocalStore {
+    fn list(
+        &self,
+        prefix: &str,
+        after: Option<DateTime<Utc>>,
+        before: Option<DateTime<Utc>>,
+    ) -> FileInfoStream {
+        let dir = self.dir.clone();
+        let prefix = prefix.to_string();
+        stream::once(async move { list_dir(&dir, &prefix, after, before).await })
+            .flat_map(|result| match result {
+                Ok(infos) => stream::iter(infos.into_iter().map(Ok)).boxed(),
+                Err(err) => stream::once(async move { Err(err) }).boxed(),
+            })
+            .boxed()
+    }
+
+    async fn put(&self, file: &Path, checksum: Option<&Checksum>) -> Result {
+        let file_name = file
+            .file_name()
+            .ok_or_else(|| Error::not_found(format!("could not open {}", file.display())))?;
+        fs::create_dir_all(&self.dir).await?;
+        // Copy to a hidden temporary name first so that a partially written
+        // file is never picked up by a listing
+        let tmp_path = self
+            .dir
+            .join(format!(".{}.tmp", file_name.to_string_lossy()));
+        fs::copy(file, &tmp_path)
+            .await
+            .map_err(|_| Error::not_found(format!("could not open {}", file.display())))?;
+        let checksum_path = self.checksum_path(&file_name.to_string_lossy())?;
+        match checksum {
+            Some(checksum) => fs::write(&checksum_path, checksum.as_str()).await?,
+            None => remove_if_exists(&checksum_path).await?,
+        }
+        fs::rename(&tmp_path, self.dir.join(file_name)).await?;
+        Ok(())
+    }
+
+    async fn remove(&self, key: &str) -> Result {
+        fs::remove_file(self.key_path(key)?).await?;
+        remove_if_exists(&self.checksum_path(key)?).await
+    }
+
+    async fn get_raw(&self, key: String) -> Result<ByteStream> {
+        ByteStream::from_path(self.key_path(&key)?)
+            .await
+            .map_err(|_| Error::not_found(format!("could not open {key}")))
+    }
+
+    async fn get_checksummed(&self, key: String) -> Result<(ByteStream, Option<Checksum>)> {
+        let checksum = match fs::read_to_string(self.checksum_path(&key)?).await {
+            Ok(hex) => Some(Checksum::from_hex(&hex)),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => None,
+            Err(err) => return Err(Error::from(err)),
+        };
+        Ok((self.get_raw(key).await?, checksum))
+    }
+}
+
+async fn remove_if_exists(path: &Path) -> Result {
+    match fs::remove_file(path).await {
+        Err(err) if err.kind() != io::ErrorKind::NotFound => Err(Error::from(err)),
+        _ => Ok(()),
+    }
+}
+
+async fn list_dir(
+    dir: &Path,
+    prefix: &str,
+    after: Option<DateTime<Utc>>,
+    before: Option<DateTime<Utc>>,
+) -> Result<Vec<FileInfo>> {
+    let mut entries = match fs::read_dir(dir).await {
+        Ok(entries) => entries,
+        // An empty bucket has nothing to list
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(vec![]),
+        Err(err) => return Err(Error::from(err)),
+    };
+
+    let mut infos = vec![];
+    while let Some(entry) = entries.next_entry().await? {
+        let file_name = entry.file_name().to_string_lossy().to_string();
+        if !file_name.starts_with(prefix)
+            || !FileInfo::matches(&file_name)
+            || checksum::is_sidecar(&file_name)
+        {
+            continue;
+        }
+        let metadata = entry.metadata().await?;
+        if !metadata.is_file() {
+            continue;
+        }
+        let mut info = FileInfo::from_str(&file_name)?;
+        info.size = metadata.len() as usize;
+        if after.map_or(true, |v| info.timestamp > v)
+            && before.map_or(true, |v| info.timestamp <= v)
+        {
+            infos.push(info);
+        }
+    }
+    infos.sort_by(|a, b| a.key.cmp(&b.key));
+
+    Ok(infos)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{FileStore, FileType};
+    use chrono::Duration;
+    use futures::TryStreamExt;
+    use tempfile::TempDir;
+
+    async fn put_file(store: &FileStore, src_dir: &Path, info: &FileInfo) {
+        let path = src_dir.join(&info.key);
+        fs::write(&path, b"data").await.expect("write source file");
+        store.put(&path).await.expect("put file");
+    }
+
+    #[tokio::test]
+    async fn lists_files_by_prefix_and_time_range() {
+        let src_dir = TempDir::new().expect("Unable to create temp dir");
+        let root_dir = TempDir::new().expect("Unable to create temp dir");
+        let store = FileStore::local(root_dir.path(), "test-bucket".to_string());
+
+        let now = Utc::now();
+        let first = FileInfo::from((FileType::CbrsHeartbeat, now - Duration::hours(2)));
+        let second = FileInfo::from((FileType::CbrsHeartbeat, now - Duration::hours(1)));
+        let other = FileInfo::from((FileType::WifiHeartbeat, now - Duration::hours(1)));
+        for info in [&second, &first, &other] {
+            put_file(&store, src_dir.path(), info).await;
+        }
+
+        let all = store
+            .list_all(&FileType::CbrsHeartbeat.to_string(), None, None)
+            .await
+            .expect("list files");
+        assert_eq!(
+            vec![first.key.clone(), second.key.clone()],
+            all.iter().map(|info| info.key.clone()).collect::<Vec<_>>()
+        );
+        assert!(all.iter().all(|info| info.size == 4));
+
+        let after = store
+            .list_all(&FileType::CbrsHeartbeat.to_string(), first.timestamp, None)
+            .await
+            .expect("list files");
+        assert_eq!(1, after.len());
+        assert_eq!(second.key, after[0].key);
+    }
+
+    #[tokio::test]
+    async fn get_and_remove_round_trip() {
+        let src_dir = TempDir::new().expect("Unable to create temp dir");
+        let root_dir = TempDir::new().expect("Unable to create temp dir");
+        let store = FileStore::local(root_dir.path(), "test-bucket".to_string());
+
+        let info = FileInfo::from((FileType::EntropyReport, Utc::now()));
+        put_file(&store, src_dir.path(), &info).await;
+
+        let stream = store.get_raw(info.key.clone()).await.expect("get file");
+        let bytes = stream.collect().await.expect("read file").into_bytes();
+        assert_eq!(&b"data"[..], &bytes[..]);
+
+        store.remove(&info.key).await.expect("remove file");
+        let remaining: Vec<FileInfo> = store
+            .list(&FileType::EntropyReport.to_string(), None, None)
+            .try_collect()
+            .await
+            .expect("list files");
+        assert!(remaining.is_empty());
+    }
+
+    #[tokio::test]
+    async fn verifies_files_against_stored_checksum() {
+        let src_dir = TempDir::new().expect("Unable to create temp dir");
+        let root_dir = TempDir::new().expect("Unable to create temp dir");
+        let store = FileStore::local(root_dir.path(), "test-bucket".to_string());
+
+        let info = FileInfo::from((FileType::EntropyReport, Utc::now()));
+        put_file(&store, src_dir.path(), &info).await;
+        let path = src_dir.path().join(&info.key);
+        let checksum = Checksum::of_file(&path).await.expect("checksum");
+        store
+            .put_sidecar(&path, &checksum)
+            .await
+            .expect("put sidecar");
+
+        // Sidecars and checksum metadata are not listed as files
+        let listed = store
+            .list_all(&FileType::EntropyReport.to_string(), None, None)
+            .await
+            .expect("list files");
+        assert_eq!(1, listed.len());
+
+        let (_, stored) = store
+            .get_checksummed(info.key.clone())
+            .await
+            .expect("get file");
+        assert_eq!(Some(checksum), stored);
+        let verified = store
+            .get_verified(info.key.clone())
+            .await
+            .expect("get file");
+        assert!(verified.collect().await.is_ok());
+
+        fs::write(
+            root_dir.path().join("test-bucket").join(&info.key),
+            b"tampered",
+        )
+        .await
+        .expect("tamper with file");
+        // The mismatch fails reading the file once it was read in full
+        let verified = store
+            .get_verified(info.key.clone())
+            .await
+            .expect("get file");
+        assert!(verified.collect().await.is_err());
+    }
+
+    #[tokio::test]
+    async fn rejects_keys_outside_of_the_store() {
+        let root_dir = TempDir::new().expect("Unable to create temp dir");
+        let store = LocalStore::new(root_dir.path().join("test-bucket"));
+        let outside = root_dir.path().join("outside");
+        fs::write(&outside, b"data")
+            .await
+            .expect("write outside file");
+
+        for key in [
+            "../outside",
+            outside.to_str().unwrap(),
+            "nested/file",
+            "nested\\file",
+            "..",
+            "",
+        ] {
+            assert!(
+                matches!(
+                    store.get_raw(key.to_string()).await,
+                    Err(Error::InvalidKey(_))
+                ),
+                "{key}"
+            );
+            assert!(
+                matches!(store.remove(key).await, Err(Error::InvalidKey(_))),
+                "{key}"
+            );
+        }
+        assert!(fs::metadata(&outside).await.is_ok());
+    }
+}
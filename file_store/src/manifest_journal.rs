@@ -0,0 +1,342 @@
+use crate::{Error, Result};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+    sync::Arc,
+};
+use tokio::{
+    fs::{self, File, OpenOptions},
+    io::AsyncWriteExt,
+    sync::Mutex,
+};
+
+/// Lifecycle of a file rolled by a `FileSink`
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "snake_case")]
+pub enum FileState {
+    /// Closed and moved to the sink target directory, awaiting upload
+    Rolled,
+    /// Stored in the bucket and safe to remove locally
+    Uploaded,
+    /// Uploaded and confirmed by the sink owner, no longer tracked
+    Acknowledged,
+    /// Confirmed by the sink owner before its upload was recorded. Becomes
+    /// acknowledged once uploaded.
+    AcknowledgedBeforeUpload,
+}
+
+impl FileState {
+    /// State of a file in `self` once `next` is recorded. A file is only
+    /// acknowledged once it was both uploaded and confirmed, in any order.
+    fn then(self, next: FileState) -> FileState {
+        match (self, next) {
+            (_, FileState::Rolled) => FileState::Rolled,
+            (
+                FileState::AcknowledgedBeforeUpload | FileState::Acknowledged,
+                FileState::Uploaded,
+            ) => FileState::Acknowledged,
+            (FileState::Uploaded | FileState::Acknowledged, FileState::Acknowledged) => {
+                FileState::Acknowledged
+            }
+            (FileState::Rolled | FileState::AcknowledgedBeforeUpload, FileState::Acknowledged) => {
+                FileState::AcknowledgedBeforeUpload
+            }
+            (_, next) => next,
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct JournalEntry {
+    file_name: String,
+    state: FileState,
+}
+
+pub type JournalStates = BTreeMap<String, FileState>;
+
+/// Number of entries appended to the journal after which it is compacted,
+/// unless most of its files are still tracked
+#[cfg(not(test))]
+const COMPACT_AFTER_ENTRIES: usize = 1_000;
+#[cfg(test)]
+const COMPACT_AFTER_ENTRIES: usize = 10;
+
+/// Append only write-ahead journal of the state of every file rolled by a
+/// sink. Each state change is flushed to disk before the action it
+/// describes is considered done, so a restarted sink can resume uploads
+/// without losing or duplicating files.
+#[derive(Debug, Clone)]
+pub struct ManifestJournal {
+    path: PathBuf,
+    prune_uploaded: bool,
+    inner: Arc<Mutex<JournalFile>>,
+}
+
+#[derive(Debug)]
+struct JournalFile {
+    file: File,
+    /// Files still tracked by the journal
+    retained: JournalStates,
+    /// Entries in the journal file
+    entries: usize,
+}
+
+impl PartialEq for ManifestJournal {
+    fn eq(&self, other: &Self) -> bool {
+        self.path == other.path
+    }
+}
+
+impl ManifestJournal {
+    /// Open the journal for `prefix` in `dir`, returning the last known state
+    /// of every tracked file. Acknowledged files and, when `prune_uploaded`
+    /// is set, uploaded ones are no longer tracked. The journal is compacted
+    /// on open and whenever it has grown well past the tracked files. Files
+    /// no longer tracked are still part of the returned states so the caller
+    /// can clean up after them.
+    pub async fn open(
+        dir: &Path,
+        prefix: &str,
+        prune_uploaded: bool,
+    ) -> Result<(Self, JournalStates)> {
+        let path = dir.join(format!(".{prefix}.journal"));
+        let states = read_states(&path).await?;
+        let retained: JournalStates = states
+            .iter()
+            .filter(|(_, state)| is_retained(**state, prune_uploaded))
+            .map(|(file_name, state)| (file_name.clone(), *state))
+            .collect();
+
+        let file = compact(&path, &retained).await?;
+        Ok((
+            Self {
+                path,
+                prune_uploaded,
+                inner: Arc::new(Mutex::new(JournalFile {
+                    file,
+                    entries: retained.len(),
+                    retained,
+                })),
+            },
+            states,
+        ))
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Durably record the new state of the given file. Acknowledging a file
+    /// whose upload was not recorded yet only takes effect once it is.
+    pub async fn record(&self, file_name: &str, state: FileState) -> Result {
+        let mut inner = self.inner.lock().await;
+        // Files no longer retained were already uploaded
+        let state = inner
+            .retained
+            .get(file_name)
+            .map_or(state, |current| current.then(state));
+        let entry = encode_entry(file_name, state)?;
+        inner.file.write_all(&entry).await?;
+        inner.file.sync_data().await?;
+        inner.entries += 1;
+
+        if is_retained(state, self.prune_uploaded) {
+            inner.retained.insert(file_name.to_string(), state);
+        } else {
+            inner.retained.remove(file_name);
+        }
+        if inner.entries > COMPACT_AFTER_ENTRIES.max(2 * inner.retained.len()) {
+            inner.file = compact(&self.path, &inner.retained).await?;
+            inner.entries = inner.retained.len();
+        }
+        Ok(())
+    }
+}
+
+fn is_retained(state: FileState, prune_uploaded: bool) -> bool {
+    match state {
+        FileState::Rolled | FileState::AcknowledgedBeforeUpload => true,
+        FileState::Uploaded => !prune_uploaded,
+        FileState::Acknowledged => false,
+    }
+}
+
+/// Atomically replace the journal at `path` with the `retained` states,
+/// returning the new journal file opened for appending
+async fn compact(path: &Path, retained: &JournalStates) -> Result<File> {
+    let mut tmp_path = path.as_os_str().to_owned();
+    tmp_path.push(".tmp");
+    let tmp_path = PathBuf::from(tmp_path);
+
+    let mut tmp_file = File::create(&tmp_path).await?;
+    for (file_name, state) in retained {
+        tmp_file
+            .write_all(&encode_entry(file_name, *state)?)
+            .await?;
+    }
+    tmp_file.sync_all().await?;
+    fs::rename(&tmp_path, path).await?;
+
+    Ok(OpenOptions::new().append(true).open(path).await?)
+}
+
+fn encode_entry(file_name: &str, state: FileState) -> Result<Vec<u8>> {
+    let mut line = serde_json::to_vec(&JournalEntry {
+        file_name: file_name.to_string(),
+        state,
+    })?;
+    line.push(b'\n');
+    Ok(line)
+}
+
+async fn read_states(path: &Path) -> Result<JournalStates> {
+    let contents = match fs::read_to_string(path).await {
+        Ok(contents) => contents,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => return Ok(JournalStates::new()),
+        Err(err) => return Err(Error::from(err)),
+    };
+
+    let mut states = JournalStates::new();
+    for line in contents.lines() {
+        // A crash during an append can leave a truncated last line. The
+        // action it described was never acted on so it is safe to skip.
+        match serde_json::from_str::<JournalEntry>(line) {
+            Ok(entry) => {
+                states.insert(entry.file_name, entry.state);
+            }
+            Err(err) => tracing::warn!(
+                "ignoring invalid journal entry in {}: {err:?}",
+                path.display()
+            ),
+        }
+    }
+    Ok(states)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::TempDir;
+
+    #[tokio::test]
+    async fn replays_last_state_and_compacts() {
+        let tmp_dir = TempDir::new().expect("Unable to create temp dir");
+        let (journal, states) = ManifestJournal::open(tmp_dir.path(), "entropy_report", false)
+            .await
+            .expect("open journal");
+        assert!(states.is_empty());
+
+        journal.record("a", FileState::Rolled).await.unwrap();
+        journal.record("b", FileState::Rolled).await.unwrap();
+        journal.record("c", FileState::Rolled).await.unwrap();
+        journal.record("a", FileState::Uploaded).await.unwrap();
+        journal.record("b", FileState::Uploaded).await.unwrap();
+        journal.record("b", FileState::Acknowledged).await.unwrap();
+        drop(journal);
+
+        // Simulate a crash in the middle of an append
+        let path = tmp_dir.path().join(".entropy_report.journal");
+        let mut contents = fs::read(&path).await.unwrap();
+        contents.extend_from_slice(br#"{"file_name":"c","sta"#);
+        fs::write(&path, contents).await.unwrap();
+
+        let (_journal, states) = ManifestJournal::open(tmp_dir.path(), "entropy_report", false)
+            .await
+            .expect("reopen journal");
+        assert_eq!(
+            JournalStates::from([
+                ("a".to_string(), FileState::Uploaded),
+                ("b".to_string(), FileState::Acknowledged),
+                ("c".to_string(), FileState::Rolled),
+            ]),
+            states
+        );
+
+        let (_journal, states) = ManifestJournal::open(tmp_dir.path(), "entropy_report", true)
+            .await
+            .expect("reopen journal");
+        assert_eq!(
+            JournalStates::from([
+                ("a".to_string(), FileState::Uploaded),
+                ("c".to_string(), FileState::Rolled),
+            ]),
+            states
+        );
+
+        let (_journal, states) = ManifestJournal::open(tmp_dir.path(), "entropy_report", false)
+            .await
+            .expect("reopen journal");
+        assert_eq!(
+            JournalStates::from([("c".to_string(), FileState::Rolled)]),
+            states
+        );
+    }
+
+    #[tokio::test]
+    async fn journal_of_unacknowledged_sink_stays_bounded() {
+        let tmp_dir = TempDir::new().expect("Unable to create temp dir");
+        let (journal, _) = ManifestJournal::open(tmp_dir.path(), "entropy_report", true)
+            .await
+            .expect("open journal");
+
+        for i in 0..(10 * COMPACT_AFTER_ENTRIES) {
+            let file_name = format!("file-{i}");
+            journal.record(&file_name, FileState::Rolled).await.unwrap();
+            journal
+                .record(&file_name, FileState::Uploaded)
+                .await
+                .unwrap();
+        }
+        journal.record("pending", FileState::Rolled).await.unwrap();
+
+        let contents = fs::read_to_string(journal.path()).await.unwrap();
+        assert!(contents.lines().count() <= COMPACT_AFTER_ENTRIES + 1);
+        drop(journal);
+
+        let (journal, states) = ManifestJournal::open(tmp_dir.path(), "entropy_report", true)
+            .await
+            .expect("reopen journal");
+        assert_eq!(Some(&FileState::Rolled), states.get("pending"));
+        let contents = fs::read_to_string(journal.path()).await.unwrap();
+        assert_eq!(1, contents.lines().count());
+    }
+
+    #[tokio::test]
+    async fn acknowledges_files_once_uploaded() {
+        let tmp_dir = TempDir::new().expect("Unable to create temp dir");
+        let (journal, _) = ManifestJournal::open(tmp_dir.path(), "entropy_report", false)
+            .await
+            .expect("open journal");
+
+        journal.record("a", FileState::Rolled).await.unwrap();
+        journal.record("a", FileState::Acknowledged).await.unwrap();
+        journal.record("b", FileState::Rolled).await.unwrap();
+        journal.record("b", FileState::Uploaded).await.unwrap();
+        journal.record("b", FileState::Acknowledged).await.unwrap();
+        drop(journal);
+
+        // The acknowledgement of a file not uploaded yet survives a restart
+        let (journal, states) = ManifestJournal::open(tmp_dir.path(), "entropy_report", false)
+            .await
+            .expect("reopen journal");
+        assert_eq!(
+            JournalStates::from([
+                ("a".to_string(), FileState::AcknowledgedBeforeUpload),
+                ("b".to_string(), FileState::Acknowledged),
+            ]),
+            states
+        );
+
+        journal.record("a", FileState::Uploaded).await.unwrap();
+        drop(journal);
+
+        let (_journal, states) = ManifestJournal::open(tmp_dir.path(), "entropy_report", false)
+            .await
+            .expect("reopen journal");
+        assert_eq!(
+            JournalStates::from([("a".to_string(), FileState::Acknowledged)]),
+            states
+        );
+    }
+}
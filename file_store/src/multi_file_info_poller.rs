@@ -0,0 +1,556 @@
+use crate::{
+    file_info_poller::{
+        cache_file, create_cache, FileInfoPollerParser, FileInfoPollerState, FileInfoStream,
+        LookbackBehavior, MemoryFileCache, CLEAN_DURATION, DEFAULT_POLL_DURATION,
+    },
+    Error, FileInfo, FileStore, Result,
+};
+use aws_sdk_s3::types::ByteStream;
+use chrono::{DateTime, Utc};
+use derive_builder::Builder;
+use futures::{future::LocalBoxFuture, stream, StreamExt, TryStreamExt};
+use futures_util::TryFutureExt;
+use std::{
+    collections::{HashMap, VecDeque},
+    marker::PhantomData,
+    sync::Arc,
+    time::Duration,
+};
+use task_manager::ManagedTask;
+use tokio::sync::mpsc::{Receiver, Sender};
+
+/// A prefix followed by a [`MultiFileInfoPollerServer`] and the parser for
+/// its files
+pub struct PollerSource<T> {
+    prefix: String,
+    parser: Arc<dyn FileInfoPollerParser<T>>,
+}
+
+impl<T> Clone for PollerSource<T> {
+    fn clone(&self) -> Self {
+        Self {
+            prefix: self.prefix.clone(),
+            parser: self.parser.clone(),
+        }
+    }
+}
+
+/// Adapts a parser of `U` to a parser of any `T: From<U>` so that files of
+/// several types can be merged into one stream
+struct IntoParser<P, U> {
+    parser: P,
+    u: PhantomData<fn() -> U>,
+}
+
+#[async_trait::async_trait]
+impl<T, U, P> FileInfoPollerParser<T> for IntoParser<P, U>
+where
+    T: From<U> + Send + 'static,
+    U: Send + 'static,
+    P: FileInfoPollerParser<U>,
+{
+    async fn parse(&self, stream: ByteStream) -> Result<Vec<T>> {
+        Ok(self
+            .parser
+            .parse(stream)
+            .await?
+            .into_iter()
+            .map(T::from)
+            .collect())
+    }
+}
+
+/// Configuration of a poller following several prefixes of the same store.
+/// Files of all prefixes are emitted as one stream in timestamp order, so
+/// that the relative order of related file types is deterministic. A file is
+/// only emitted once every prefix that has seen a file has seen one at least
+/// as recent, less `lag`, so that an older file of a lagging prefix is not
+/// overtaken by newer files of the others.
+#[derive(Clone, Builder)]
+#[builder(pattern = "owned")]
+pub struct MultiFileInfoPollerConfig<T, S> {
+    #[builder(default = "DEFAULT_POLL_DURATION")]
+    poll_duration: Duration,
+    state: S,
+    store: FileStore,
+    #[builder(setter(custom))]
+    sources: Vec<PollerSource<T>>,
+    lookback: LookbackBehavior,
+    #[builder(default = "Duration::from_secs(10 * 60)")]
+    offset: Duration,
+    /// How far behind the latest file of the slowest prefix files are held
+    /// back, to leave room for files uploaded late
+    #[builder(default = "Duration::ZERO")]
+    lag: Duration,
+    #[builder(default = "5")]
+    queue_size: usize,
+    /// Number of files fetched and parsed concurrently
+    #[builder(default = "2")]
+    workers: usize,
+    #[builder(default = r#""default".to_string()"#)]
+    process_name: String,
+}
+
+pub struct MultiFileInfoPollerServer<T, S> {
+    config: MultiFileInfoPollerConfig<T, S>,
+    sender: Sender<FileInfoStream<T>>,
+    file_queue: VecDeque<(FileInfo, PollerSource<T>)>,
+    latest_file_timestamps: HashMap<String, Option<DateTime<Utc>>>,
+    cache: MemoryFileCache,
+}
+
+type FileInfoStreamReceiver<T> = Receiver<FileInfoStream<T>>;
+
+impl<T, S> MultiFileInfoPollerConfigBuilder<T, S>
+where
+    T: Send + 'static,
+    S: FileInfoPollerState,
+{
+    /// Follow files with the given prefix, parsing them with `parser` and
+    /// converting the parsed items into the merged stream item type.
+    pub fn source<U, P>(mut self, prefix: impl ToString, parser: P) -> Self
+    where
+        T: From<U>,
+        U: Send + 'static,
+        P: FileInfoPollerParser<U>,
+    {
+        self.sources
+            .get_or_insert_with(Vec::new)
+            .push(PollerSource {
+                prefix: prefix.to_string(),
+                parser: Arc::new(IntoParser {
+                    parser,
+                    u: PhantomData,
+                }),
+            });
+        self
+    }
+
+    pub async fn create(
+        self,
+    ) -> Result<(FileInfoStreamReceiver<T>, MultiFileInfoPollerServer<T, S>)> {
+        let config = self.build()?;
+        let (sender, receiver) = tokio::sync::mpsc::channel(config.queue_size);
+
+        let mut latest_file_timestamps = HashMap::new();
+        for source in config.sources.iter() {
+            let latest_file_timestamp = config
+                .state
+                .latest_timestamp(&config.process_name, &source.prefix)
+                .await?;
+            latest_file_timestamps.insert(source.prefix.clone(), latest_file_timestamp);
+        }
+
+        Ok((
+            receiver,
+            MultiFileInfoPollerServer {
+                config,
+                sender,
+                file_queue: VecDeque::new(),
+                latest_file_timestamps,
+                cache: create_cache(),
+            },
+        ))
+    }
+}
+
+impl<T, S> ManagedTask for MultiFileInfoPollerServer<T, S>
+where
+    T: Send + Sync + 'static,
+    S: FileInfoPollerState,
+{
+    fn start_task(
+        self: Box<Self>,
+        shutdown: triggered::Listener,
+    ) -> LocalBoxFuture<'static, anyhow::Result<()>> {
+        let handle = tokio::spawn(self.run(shutdown));
+
+        Box::pin(
+            handle
+                .map_err(anyhow::Error::from)
+                .and_then(|result| async move { result.map_err(anyhow::Error::from) }),
+        )
+    }
+}
+
+impl<T, S> MultiFileInfoPollerServer<T, S>
+where
+    T: Send + Sync + 'static,
+    S: FileInfoPollerState,
+{
+    async fn get_next_files(&mut self) -> Result<Vec<(FileInfo, PollerSource<T>)>> {
+        loop {
+            let before = Utc::now();
+            let store = &self.config.store;
+            let listings = stream::iter(self.config.sources.clone())
+                .map(|source| {
+                    let latest = self
+                        .latest_file_timestamps
+                        .get(&source.prefix)
+                        .copied()
+                        .flatten();
+                    let after = self.config.lookback.after(latest, self.config.offset);
+                    async move {
+                        let files = store.list_all(&source.prefix, after, before).await?;
+                        Ok::<_, Error>((source, files))
+                    }
+                })
+                .buffer_unordered(self.config.workers)
+                .try_collect::<Vec<_>>()
+                .await?;
+
+            for (source, listed) in listings {
+                for file in listed {
+                    if !self.is_already_processed(&file).await? && !self.is_queued(&file) {
+                        let latest = self
+                            .latest_file_timestamps
+                            .entry(source.prefix.clone())
+                            .or_default();
+                        *latest = (*latest).max(Some(file.timestamp));
+                        self.file_queue.push_back((file, source.clone()));
+                    }
+                }
+            }
+            // Ties are broken by key so that the order does not depend on
+            // which listing completed first
+            self.file_queue.make_contiguous().sort_by(|(a, _), (b, _)| {
+                a.timestamp
+                    .cmp(&b.timestamp)
+                    .then_with(|| a.key.cmp(&b.key))
+            });
+
+            let ready = self.take_ready_files();
+            if !ready.is_empty() {
+                return Ok(ready);
+            }
+            tokio::time::sleep(self.config.poll_duration).await;
+        }
+    }
+
+    /// Take the queued files up to the watermark: the latest file timestamp
+    /// of the slowest prefix less the configured lag. Prefixes that have not
+    /// seen a file yet do not hold the others back.
+    fn take_ready_files(&mut self) -> Vec<(FileInfo, PollerSource<T>)> {
+        let Some(watermark) = self.latest_file_timestamps.values().flatten().min() else {
+            return vec![];
+        };
+        let watermark = *watermark - self.config.lag;
+        let ready = self
+            .file_queue
+            .iter()
+            .take_while(|(file, _)| file.timestamp <= watermark)
+            .count();
+        self.file_queue.drain(..ready).collect()
+    }
+
+    fn is_queued(&self, file_info: &FileInfo) -> bool {
+        self.file_queue
+            .iter()
+            .any(|(queued, _)| queued.key == file_info.key)
+    }
+
+    async fn run(mut self, shutdown: triggered::Listener) -> Result {
+        let mut cleanup_trigger = tokio::time::interval(CLEAN_DURATION);
+        let process_name = self.config.process_name.clone();
+        let prefixes = self
+            .config
+            .sources
+            .iter()
+            .map(|source| source.prefix.clone())
+            .collect::<Vec<_>>()
+            .join(",");
+
+        tracing::info!(r#type = prefixes, %process_name, "starting MultiFileInfoPoller");
+
+        let sender = self.sender.clone();
+        'poll: loop {
+            let files = tokio::select! {
+                biased;
+                _ = shutdown.clone() => break,
+                _ = cleanup_trigger.tick() => {
+                    self.clean().await?;
+                    continue;
+                }
+                result = self.get_next_files() => result?,
+            };
+
+            // Files are fetched and parsed concurrently but handed out in
+            // the order they were listed
+            let store = self.config.store.clone();
+            let mut parsed = stream::iter(files)
+                .map(|(file, source)| {
+                    let store = store.clone();
+                    async move {
+                        let byte_stream = store.get_verified(file.clone()).await?;
+                        let data = source.parser.parse(byte_stream).await?;
+                        Ok::<_, Error>((file, data))
+                    }
+                })
+                .buffered(self.config.workers);
+
+            loop {
+                let (permit, parsed_file) = tokio::select! {
+                    biased;
+                    _ = shutdown.clone() => break 'poll,
+                    result = futures::future::try_join(
+                        sender.reserve().map_err(Error::from),
+                        parsed.try_next()
+                    ) => result?,
+                };
+                let Some((file, data)) = parsed_file else {
+                    break;
+                };
+                permit.send(FileInfoStream::new(
+                    process_name.clone(),
+                    file.clone(),
+                    data,
+                ));
+                cache_file(&self.cache, &file).await;
+            }
+        }
+
+        tracing::info!(r#type = prefixes, %process_name, "stopping MultiFileInfoPoller");
+        Ok(())
+    }
+
+    async fn clean(&self) -> Result {
+        self.cache.purge(4, 0.25).await;
+        for source in self.config.sources.iter() {
+            self.config
+                .state
+                .clean(&self.config.process_name, &source.prefix)
+                .await?;
+        }
+        Ok(())
+    }
+
+    async fn is_already_processed(&self, file_info: &FileInfo) -> Result<bool> {
+        if self.cache.get(&file_info.key).await.is_some() {
+            Ok(true)
+        } else {
+            self.config
+                .state
+                .exists(&self.config.process_name, file_info)
+                .await
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{compression::Compression, file_info_poller::ProstFileInfoPollerParser, FileType};
+    use bytes::Bytes;
+    use futures::SinkExt;
+    use helium_proto::{EntropyReportV1, PriceReportV1};
+    use std::path::Path;
+    use tempfile::TempDir;
+    use tokio::io::AsyncWriteExt;
+    use tokio_util::codec::{length_delimited::LengthDelimitedCodec, FramedWrite};
+
+    #[derive(Debug)]
+    enum Report {
+        Entropy(EntropyReportV1),
+        Price(PriceReportV1),
+    }
+
+    impl From<EntropyReportV1> for Report {
+        fn from(report: EntropyReportV1) -> Self {
+            Self::Entropy(report)
+        }
+    }
+
+    impl From<PriceReportV1> for Report {
+        fn from(report: PriceReportV1) -> Self {
+            Self::Price(report)
+        }
+    }
+
+    struct NoState;
+
+    #[async_trait::async_trait]
+    impl FileInfoPollerState for NoState {
+        async fn latest_timestamp(
+            &self,
+            _process_name: &str,
+            _file_type: &str,
+        ) -> Result<Option<DateTime<Utc>>> {
+            Ok(None)
+        }
+
+        async fn exists(&self, _process_name: &str, _file_info: &FileInfo) -> Result<bool> {
+            Ok(false)
+        }
+
+        async fn clean(&self, _process_name: &str, _file_type: &str) -> Result {
+            Ok(())
+        }
+    }
+
+    async fn put_file(store: &FileStore, dir: &Path, info: &FileInfo, msg: impl prost::Message) {
+        let path = dir.join(&info.key);
+        write_file(&path, msg).await;
+        store.put(&path).await.unwrap();
+    }
+
+    async fn write_file(path: &Path, msg: impl prost::Message) {
+        let file = tokio::fs::File::create(path).await.unwrap();
+        let mut transport =
+            FramedWrite::new(Compression::Gzip.encoder(file), LengthDelimitedCodec::new());
+        transport
+            .send(Bytes::from(msg.encode_to_vec()))
+            .await
+            .unwrap();
+        transport.get_mut().shutdown().await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn emits_files_of_all_prefixes_in_timestamp_order() {
+        let src_dir = TempDir::new().expect("Unable to create temp dir");
+        let root_dir = TempDir::new().expect("Unable to create temp dir");
+        let store = FileStore::local(root_dir.path(), "test-bucket".to_string());
+
+        let now = Utc::now();
+        let first = FileInfo::from((FileType::PriceReport, now - chrono::Duration::hours(3)));
+        let second = FileInfo::from((FileType::EntropyReport, now - chrono::Duration::hours(2)));
+        let third = FileInfo::from((FileType::PriceReport, now - chrono::Duration::hours(1)));
+        // Lets the entropy prefix catch up with the last price file
+        let fourth = FileInfo::from((FileType::EntropyReport, now - chrono::Duration::minutes(30)));
+        put_file(&store, src_dir.path(), &fourth, EntropyReportV1::default()).await;
+        put_file(&store, src_dir.path(), &third, PriceReportV1::default()).await;
+        put_file(&store, src_dir.path(), &second, EntropyReportV1::default()).await;
+        put_file(&store, src_dir.path(), &first, PriceReportV1::default()).await;
+
+        let (mut receiver, server) = MultiFileInfoPollerConfigBuilder::<Report, _>::default()
+            .state(NoState)
+            .store(store)
+            .lookback(LookbackBehavior::StartAfter(
+                now - chrono::Duration::days(1),
+            ))
+            .source::<EntropyReportV1, _>(FileType::EntropyReport, ProstFileInfoPollerParser)
+            .source::<PriceReportV1, _>(FileType::PriceReport, ProstFileInfoPollerParser)
+            .create()
+            .await
+            .expect("failed to create poller");
+
+        let (shutdown_trigger, shutdown_listener) = triggered::trigger();
+        let server_thread = tokio::spawn(server.run(shutdown_listener));
+
+        let mut keys = vec![];
+        for _ in 0..3 {
+            let file_info_stream = receiver.recv().await.expect("poller stopped");
+            keys.push(file_info_stream.file_info.key);
+        }
+        assert_eq!(vec![first.key, second.key, third.key], keys);
+
+        shutdown_trigger.trigger();
+        server_thread
+            .await
+            .expect("poller did not complete")
+            .expect("poller failed");
+    }
+
+    #[tokio::test]
+    async fn fails_on_files_not_matching_their_checksum() {
+        let src_dir = TempDir::new().expect("Unable to create temp dir");
+        let root_dir = TempDir::new().expect("Unable to create temp dir");
+        let store = FileStore::local(root_dir.path(), "test-bucket".to_string());
+
+        let now = Utc::now();
+        let info = FileInfo::from((FileType::PriceReport, now - chrono::Duration::hours(1)));
+        put_file(&store, src_dir.path(), &info, PriceReportV1::default()).await;
+        // Replace the stored file with valid content it was not stored with
+        let tampered = PriceReportV1 {
+            price: 1,
+            ..Default::default()
+        };
+        write_file(
+            &root_dir.path().join("test-bucket").join(&info.key),
+            tampered,
+        )
+        .await;
+
+        let (mut receiver, server) = MultiFileInfoPollerConfigBuilder::<Report, _>::default()
+            .state(NoState)
+            .store(store)
+            .lookback(LookbackBehavior::StartAfter(
+                now - chrono::Duration::days(1),
+            ))
+            .source::<PriceReportV1, _>(FileType::PriceReport, ProstFileInfoPollerParser)
+            .create()
+            .await
+            .expect("failed to create poller");
+
+        let (_shutdown_trigger, shutdown_listener) = triggered::trigger();
+        let result = tokio::time::timeout(
+            std::time::Duration::from_secs(10),
+            server.run(shutdown_listener),
+        )
+        .await
+        .expect("poller did not stop");
+        // The mismatch surfaces as an error reading the file
+        assert!(result.is_err());
+        assert!(receiver.try_recv().is_err());
+    }
+
+    async fn next_key(receiver: &mut FileInfoStreamReceiver<Report>) -> String {
+        tokio::time::timeout(std::time::Duration::from_secs(10), receiver.recv())
+            .await
+            .expect("no file emitted")
+            .expect("poller stopped")
+            .file_info
+            .key
+    }
+
+    #[tokio::test]
+    async fn holds_files_back_until_every_prefix_caught_up() {
+        let src_dir = TempDir::new().expect("Unable to create temp dir");
+        let root_dir = TempDir::new().expect("Unable to create temp dir");
+        let store = FileStore::local(root_dir.path(), "test-bucket".to_string());
+
+        let now = Utc::now();
+        let price = |age: chrono::Duration| FileInfo::from((FileType::PriceReport, now - age));
+        let entropy = |age: chrono::Duration| FileInfo::from((FileType::EntropyReport, now - age));
+        let first = price(chrono::Duration::hours(3));
+        let second = entropy(chrono::Duration::hours(2));
+        let third = price(chrono::Duration::hours(1));
+        put_file(&store, src_dir.path(), &first, PriceReportV1::default()).await;
+        put_file(&store, src_dir.path(), &second, EntropyReportV1::default()).await;
+        put_file(&store, src_dir.path(), &third, PriceReportV1::default()).await;
+
+        let (mut receiver, server) = MultiFileInfoPollerConfigBuilder::<Report, _>::default()
+            .state(NoState)
+            .store(store.clone())
+            .poll_duration(std::time::Duration::from_millis(50))
+            .lookback(LookbackBehavior::StartAfter(
+                now - chrono::Duration::days(1),
+            ))
+            .source::<EntropyReportV1, _>(FileType::EntropyReport, ProstFileInfoPollerParser)
+            .source::<PriceReportV1, _>(FileType::PriceReport, ProstFileInfoPollerParser)
+            .create()
+            .await
+            .expect("failed to create poller");
+
+        let (shutdown_trigger, shutdown_listener) = triggered::trigger();
+        let server_thread = tokio::spawn(server.run(shutdown_listener));
+
+        assert_eq!(first.key, next_key(&mut receiver).await);
+        assert_eq!(second.key, next_key(&mut receiver).await);
+
+        // An entropy file uploaded late still comes before the newer price
+        // file that was already listed
+        let late = entropy(chrono::Duration::minutes(90));
+        put_file(&store, src_dir.path(), &late, EntropyReportV1::default()).await;
+        assert_eq!(late.key, next_key(&mut receiver).await);
+
+        let latest = entropy(chrono::Duration::minutes(30));
+        put_file(&store, src_dir.path(), &latest, EntropyReportV1::default()).await;
+        assert_eq!(third.key, next_key(&mut receiver).await);
+
+        shutdown_trigger.trigger();
+        server_thread
+            .await
+            .expect("poller did not complete")
+            .expect("poller failed");
+    }
+}
@@ -0,0 +1,643 @@
+//! Render the protobuf messages stored in files as JSON.
+//!
+//! Every [`FileType`] is mapped to the message it holds along with a table of
+//! the fields of that message that need rendering. Messages are serialized
+//! through their serde implementation and then post-processed so that public
+//! keys are rendered as b58 strings, uuids in their hyphenated form, other
+//! binary fields as base64 and timestamps, in the unit the table gives them,
+//! as RFC3339 strings. Fields missing from the tables are left as serialized.
+
+use crate::{Error, FileType, Result};
+use base64::Engine;
+use chrono::{DateTime, TimeZone, Utc};
+use helium_crypto::PublicKey;
+use helium_proto::{
+    services::{
+        packet_verifier::{InvalidPacket, ValidDataTransferSession, ValidPacket},
+        poc_lora::{
+            IotRewardShare, LoraBeaconIngestReportV1, LoraInvalidBeaconReportV1,
+            LoraInvalidWitnessReportV1, LoraPocV1, LoraWitnessIngestReportV1, NonRewardablePacket,
+        },
+        poc_mobile::{
+            CellHeartbeatIngestReportV1, CellHeartbeatReqV1, CoverageObjectIngestReportV1,
+            CoverageObjectV1, DataTransferSessionIngestReportV1, Heartbeat,
+            InvalidDataTransferIngestReportV1, InvalidatedRadioThresholdIngestReportV1,
+            InvalidatedRadioThresholdReportReqV1, MobileRewardShare, OracleBoostingReportV1,
+            RadioRewardShare, RadioThresholdIngestReportV1, RadioThresholdReportReqV1,
+            SeniorityUpdate, ServiceProviderBoostedRewardsBannedRadioIngestReportV1, SpeedtestAvg,
+            SpeedtestIngestReportV1, SpeedtestReqV1, SubscriberLocationIngestReportV1,
+            SubscriberLocationReqV1, VerifiedInvalidatedRadioThresholdIngestReportV1,
+            VerifiedRadioThresholdIngestReportV1,
+            VerifiedServiceProviderBoostedRewardsBannedRadioIngestReportV1, VerifiedSpeedtest,
+            VerifiedSubscriberLocationIngestReportV1, WifiHeartbeatIngestReportV1,
+            WifiHeartbeatReqV1,
+        },
+        router::PacketRouterPacketReportV1,
+    },
+    BlockchainTxn, BoostedHexUpdateV1, EntropyReportV1, Message, PriceReportV1, RewardManifest,
+    SubnetworkRewards,
+};
+use serde_json::{Map, Value};
+
+/// Maps each file type to the message its frames hold and the fields of
+/// that message to render. The match is exhaustive so a new file type has to
+/// be added here to be dumpable.
+macro_rules! decode_message {
+    ($file_type:expr, $buf:expr,
+     { $($variant:ident => $msg:ty: $fields:expr),* $(,)? },
+     unsupported: [$($unsupported:ident),* $(,)?]) => {
+        match $file_type {
+            $(FileType::$variant => to_json(&<$msg as Message>::decode($buf)?, $fields),)*
+            $(FileType::$unsupported)|* => {
+                Err(Error::not_found(format!("no message for {}", $file_type)))
+            }
+        }
+    };
+}
+
+/// Decode a frame of a file of the given type and render it as JSON
+pub fn decode(file_type: FileType, buf: &[u8]) -> Result<Value> {
+    decode_message!(file_type, buf, {
+        CbrsHeartbeat => CellHeartbeatReqV1: CELL_HEARTBEAT_REQ,
+        CellSpeedtest => SpeedtestReqV1: SPEEDTEST_REQ,
+        Entropy => EntropyReportV1: ENTROPY_REPORT,
+        SubnetworkRewards => SubnetworkRewards: SUBNETWORK_REWARDS,
+        CbrsHeartbeatIngestReport => CellHeartbeatIngestReportV1: CELL_HEARTBEAT_INGEST_REPORT,
+        CellSpeedtestIngestReport => SpeedtestIngestReportV1: SPEEDTEST_INGEST_REPORT,
+        EntropyReport => EntropyReportV1: ENTROPY_REPORT,
+        IotBeaconIngestReport => LoraBeaconIngestReportV1: LORA_BEACON_INGEST_REPORT,
+        IotWitnessIngestReport => LoraWitnessIngestReportV1: LORA_WITNESS_INGEST_REPORT,
+        IotPoc => LoraPocV1: LORA_POC,
+        IotInvalidBeaconReport => LoraInvalidBeaconReportV1: LORA_BEACON_INGEST_REPORT,
+        IotInvalidWitnessReport => LoraInvalidWitnessReportV1: LORA_WITNESS_INGEST_REPORT,
+        SpeedtestAvg => SpeedtestAvg: SPEEDTEST_AVG,
+        ValidatedHeartbeat => Heartbeat: HEARTBEAT,
+        SignedPocReceiptTxn => BlockchainTxn: BLOCKCHAIN_TXN,
+        RadioRewardShare => RadioRewardShare: RADIO_REWARD_SHARE,
+        RewardManifest => RewardManifest: REWARD_MANIFEST,
+        IotPacketReport => PacketRouterPacketReportV1: PACKET_REPORT,
+        IotValidPacket => ValidPacket: VALID_PACKET,
+        InvalidPacket => InvalidPacket: INVALID_PACKET,
+        NonRewardablePacket => NonRewardablePacket: NON_REWARDABLE_PACKET,
+        IotRewardShare => IotRewardShare: IOT_REWARD_SHARE,
+        DataTransferSessionIngestReport => DataTransferSessionIngestReportV1: DATA_TRANSFER_SESSION_INGEST_REPORT,
+        InvalidDataTransferSessionIngestReport => InvalidDataTransferIngestReportV1: INVALID_DATA_TRANSFER_INGEST_REPORT,
+        ValidDataTransferSession => ValidDataTransferSession: VALID_DATA_TRANSFER_SESSION,
+        PriceReport => PriceReportV1: PRICE_REPORT,
+        MobileRewardShare => MobileRewardShare: MOBILE_REWARD_SHARE,
+        SubscriberLocationReq => SubscriberLocationReqV1: SUBSCRIBER_LOCATION_REQ,
+        SubscriberLocationIngestReport => SubscriberLocationIngestReportV1: SUBSCRIBER_LOCATION_INGEST_REPORT,
+        VerifiedSubscriberLocationIngestReport => VerifiedSubscriberLocationIngestReportV1: VERIFIED_SUBSCRIBER_LOCATION_INGEST_REPORT,
+        CoverageObject => CoverageObjectV1: COVERAGE_OBJECT,
+        CoverageObjectIngestReport => CoverageObjectIngestReportV1: COVERAGE_OBJECT_INGEST_REPORT,
+        SeniorityUpdate => SeniorityUpdate: SENIORITY_UPDATE,
+        VerifiedSpeedtest => VerifiedSpeedtest: VERIFIED_SPEEDTEST,
+        WifiHeartbeat => WifiHeartbeatReqV1: WIFI_HEARTBEAT_REQ,
+        WifiHeartbeatIngestReport => WifiHeartbeatIngestReportV1: WIFI_HEARTBEAT_INGEST_REPORT,
+        BoostedHexUpdate => BoostedHexUpdateV1: BOOSTED_HEX_UPDATE,
+        OracleBoostingReport => OracleBoostingReportV1: ORACLE_BOOSTING_REPORT,
+        RadioThresholdReq => RadioThresholdReportReqV1: RADIO_THRESHOLD_REQ,
+        RadioThresholdIngestReport => RadioThresholdIngestReportV1: RADIO_THRESHOLD_INGEST_REPORT,
+        VerifiedRadioThresholdIngestReport => VerifiedRadioThresholdIngestReportV1: VERIFIED_RADIO_THRESHOLD_INGEST_REPORT,
+        InvalidatedRadioThresholdReq => InvalidatedRadioThresholdReportReqV1: INVALIDATED_RADIO_THRESHOLD_REQ,
+        InvalidatedRadioThresholdIngestReport => InvalidatedRadioThresholdIngestReportV1: INVALIDATED_RADIO_THRESHOLD_INGEST_REPORT,
+        VerifiedInvalidatedRadioThresholdIngestReport => VerifiedInvalidatedRadioThresholdIngestReportV1: VERIFIED_INVALIDATED_RADIO_THRESHOLD_INGEST_REPORT,
+        SPBoostedRewardsBannedRadioIngestReport => ServiceProviderBoostedRewardsBannedRadioIngestReportV1: SP_BOOSTED_REWARDS_BANNED_RADIO_INGEST_REPORT,
+        VerifiedSPBoostedRewardsBannedRadioIngestReport => VerifiedServiceProviderBoostedRewardsBannedRadioIngestReportV1: VERIFIED_SP_BOOSTED_REWARDS_BANNED_RADIO_INGEST_REPORT,
+    }, unsupported: [
+        // No protobuf message is stored under these prefixes
+        MapperMsg,
+        UrbanizationDataSet,
+        FootfallDataSet,
+        LandtypeDataSet,
+    ])
+}
+
+/// How a field of a message is rendered
+#[derive(Debug, Clone, Copy)]
+enum Field {
+    Seconds,
+    Millis,
+    Nanos,
+    PubKey,
+    Uuid,
+    /// Any other binary field, rendered as base64
+    Bytes,
+    /// A nested message or oneof, rendered with the table of its own fields
+    Message(Fields),
+}
+
+/// The fields of a message that need rendering. Repeated fields are listed
+/// like single ones and every item is rendered.
+type Fields = &'static [(&'static str, Field)];
+
+const CELL_HEARTBEAT_REQ: Fields = &[
+    ("pub_key", Field::PubKey),
+    ("timestamp", Field::Seconds),
+    ("coverage_object", Field::Uuid),
+    ("signature", Field::Bytes),
+];
+
+const CELL_HEARTBEAT_INGEST_REPORT: Fields = &[
+    ("received_timestamp", Field::Millis),
+    ("report", Field::Message(CELL_HEARTBEAT_REQ)),
+];
+
+const WIFI_HEARTBEAT_REQ: Fields = &[
+    ("pub_key", Field::PubKey),
+    ("timestamp", Field::Seconds),
+    ("location_validation_timestamp", Field::Seconds),
+    ("coverage_object", Field::Uuid),
+    ("signature", Field::Bytes),
+];
+
+const WIFI_HEARTBEAT_INGEST_REPORT: Fields = &[
+    ("received_timestamp", Field::Millis),
+    ("report", Field::Message(WIFI_HEARTBEAT_REQ)),
+];
+
+const HEARTBEAT: Fields = &[
+    ("pub_key", Field::PubKey),
+    ("timestamp", Field::Seconds),
+    ("location_validation_timestamp", Field::Seconds),
+    ("coverage_object", Field::Uuid),
+];
+
+const SPEEDTEST_REQ: Fields = &[
+    ("pub_key", Field::PubKey),
+    ("timestamp", Field::Seconds),
+    ("signature", Field::Bytes),
+];
+
+const SPEEDTEST_INGEST_REPORT: Fields = &[
+    ("received_timestamp", Field::Millis),
+    ("report", Field::Message(SPEEDTEST_REQ)),
+];
+
+const VERIFIED_SPEEDTEST: Fields = &[
+    ("report", Field::Message(SPEEDTEST_INGEST_REPORT)),
+    ("timestamp", Field::Millis),
+];
+
+const SPEEDTEST: Fields = &[("timestamp", Field::Seconds)];
+
+const SPEEDTEST_AVG: Fields = &[
+    ("pub_key", Field::PubKey),
+    ("timestamp", Field::Seconds),
+    ("speedtests", Field::Message(SPEEDTEST)),
+];
+
+const COVERAGE_OBJECT_REQ: Fields = &[
+    ("pub_key", Field::PubKey),
+    ("uuid", Field::Uuid),
+    ("key_type", Field::Message(KEY_TYPE)),
+    ("coverage_claim_time", Field::Seconds),
+    ("signature", Field::Bytes),
+];
+
+const COVERAGE_OBJECT_INGEST_REPORT: Fields = &[
+    ("received_timestamp", Field::Millis),
+    ("report", Field::Message(COVERAGE_OBJECT_REQ)),
+];
+
+const COVERAGE_OBJECT: Fields = &[("coverage_object", Field::Message(COVERAGE_OBJECT_REQ))];
+
+/// Oneof of a cbsd id or a hotspot key identifying a radio
+const KEY_TYPE: Fields = &[("hotspot_key", Field::PubKey)];
+
+const SENIORITY_UPDATE: Fields = &[
+    ("key_type", Field::Message(KEY_TYPE)),
+    ("new_seniority_timestamp", Field::Seconds),
+    ("new_seniority_timestamp_ms", Field::Millis),
+];
+
+const DATA_TRANSFER_EVENT: Fields = &[
+    ("pub_key", Field::PubKey),
+    ("payer", Field::PubKey),
+    ("timestamp", Field::Seconds),
+    ("signature", Field::Bytes),
+];
+
+const DATA_TRANSFER_SESSION_REQ: Fields = &[
+    ("data_transfer_usage", Field::Message(DATA_TRANSFER_EVENT)),
+    ("pub_key", Field::PubKey),
+    ("signature", Field::Bytes),
+];
+
+const DATA_TRANSFER_SESSION_INGEST_REPORT: Fields = &[
+    ("received_timestamp", Field::Millis),
+    ("report", Field::Message(DATA_TRANSFER_SESSION_REQ)),
+];
+
+const INVALID_DATA_TRANSFER_INGEST_REPORT: Fields = &[
+    (
+        "report",
+        Field::Message(DATA_TRANSFER_SESSION_INGEST_REPORT),
+    ),
+    ("timestamp", Field::Millis),
+];
+
+const VALID_DATA_TRANSFER_SESSION: Fields = &[
+    ("pub_key", Field::PubKey),
+    ("payer", Field::PubKey),
+    ("first_timestamp", Field::Millis),
+    ("last_timestamp", Field::Millis),
+];
+
+const SUBSCRIBER_LOCATION_REQ: Fields = &[
+    ("subscriber_id", Field::Bytes),
+    ("timestamp", Field::Seconds),
+    ("carrier_pub_key", Field::PubKey),
+    ("signature", Field::Bytes),
+];
+
+const SUBSCRIBER_LOCATION_INGEST_REPORT: Fields = &[
+    ("received_timestamp", Field::Millis),
+    ("report", Field::Message(SUBSCRIBER_LOCATION_REQ)),
+];
+
+const VERIFIED_SUBSCRIBER_LOCATION_INGEST_REPORT: Fields = &[
+    ("report", Field::Message(SUBSCRIBER_LOCATION_INGEST_REPORT)),
+    ("timestamp", Field::Millis),
+];
+
+const RADIO_THRESHOLD_REQ: Fields = &[
+    ("hotspot_pubkey", Field::PubKey),
+    ("threshold_timestamp", Field::Seconds),
+    ("carrier_pub_key", Field::PubKey),
+    ("signature", Field::Bytes),
+];
+
+const RADIO_THRESHOLD_INGEST_REPORT: Fields = &[
+    ("received_timestamp", Field::Millis),
+    ("report", Field::Message(RADIO_THRESHOLD_REQ)),
+];
+
+const VERIFIED_RADIO_THRESHOLD_INGEST_REPORT: Fields = &[
+    ("report", Field::Message(RADIO_THRESHOLD_INGEST_REPORT)),
+    ("timestamp", Field::Millis),
+];
+
+const INVALIDATED_RADIO_THRESHOLD_REQ: Fields = &[
+    ("hotspot_pubkey", Field::PubKey),
+    ("timestamp", Field::Seconds),
+    ("carrier_pub_key", Field::PubKey),
+    ("signature", Field::Bytes),
+];
+
+const INVALIDATED_RADIO_THRESHOLD_INGEST_REPORT: Fields = &[
+    ("received_timestamp", Field::Millis),
+    ("report", Field::Message(INVALIDATED_RADIO_THRESHOLD_REQ)),
+];
+
+const VERIFIED_INVALIDATED_RADIO_THRESHOLD_INGEST_REPORT: Fields = &[
+    (
+        "report",
+        Field::Message(INVALIDATED_RADIO_THRESHOLD_INGEST_REPORT),
+    ),
+    ("timestamp", Field::Millis),
+];
+
+const SP_BOOSTED_REWARDS_BANNED_RADIO_REQ: Fields = &[
+    ("pubkey", Field::PubKey),
+    ("key_type", Field::Message(KEY_TYPE)),
+    ("until", Field::Seconds),
+    ("signature", Field::Bytes),
+];
+
+const SP_BOOSTED_REWARDS_BANNED_RADIO_INGEST_REPORT: Fields = &[
+    ("received_timestamp", Field::Millis),
+    (
+        "report",
+        Field::Message(SP_BOOSTED_REWARDS_BANNED_RADIO_REQ),
+    ),
+];
+
+const VERIFIED_SP_BOOSTED_REWARDS_BANNED_RADIO_INGEST_REPORT: Fields = &[
+    (
+        "report",
+        Field::Message(SP_BOOSTED_REWARDS_BANNED_RADIO_INGEST_REPORT),
+    ),
+    ("timestamp", Field::Millis),
+];
+
+const BOOSTED_HEX_INFO: Fields = &[
+    ("start_ts", Field::Seconds),
+    ("end_ts", Field::Seconds),
+    ("boosted_hex_pubkey", Field::Bytes),
+    ("boost_config_pubkey", Field::Bytes),
+];
+
+const BOOSTED_HEX_UPDATE: Fields = &[
+    ("timestamp", Field::Seconds),
+    ("update", Field::Message(BOOSTED_HEX_INFO)),
+];
+
+const ORACLE_BOOSTING_REPORT: Fields = &[
+    ("coverage_object", Field::Uuid),
+    ("timestamp", Field::Seconds),
+];
+
+const RADIO_REWARD: Fields = &[
+    ("hotspot_key", Field::PubKey),
+    ("coverage_object", Field::Uuid),
+    ("seniority_timestamp", Field::Seconds),
+];
+
+const RADIO_REWARD_V2: Fields = &[
+    ("hotspot_key", Field::PubKey),
+    ("seniority_timestamp", Field::Seconds),
+    ("speedtests", Field::Message(SPEEDTEST)),
+];
+
+/// Oneof of the rewards of a [`MobileRewardShare`]
+const MOBILE_REWARD: Fields = &[
+    ("radio_reward", Field::Message(RADIO_REWARD)),
+    ("radio_reward_v2", Field::Message(RADIO_REWARD_V2)),
+    (
+        "gateway_reward",
+        Field::Message(&[("hotspot_key", Field::PubKey)]),
+    ),
+    (
+        "subscriber_reward",
+        Field::Message(&[("subscriber_id", Field::Bytes)]),
+    ),
+];
+
+const MOBILE_REWARD_SHARE: Fields = &[
+    ("start_period", Field::Seconds),
+    ("end_period", Field::Seconds),
+    ("reward", Field::Message(MOBILE_REWARD)),
+];
+
+const RADIO_REWARD_SHARE: Fields = &[
+    ("owner_key", Field::PubKey),
+    ("hotspot_key", Field::PubKey),
+    ("start_epoch", Field::Seconds),
+    ("end_epoch", Field::Seconds),
+];
+
+const REWARD_MANIFEST: Fields = &[
+    ("start_timestamp", Field::Seconds),
+    ("end_timestamp", Field::Seconds),
+];
+
+const SUBNETWORK_REWARDS: Fields = &[("rewards", Field::Message(&[("account", Field::PubKey)]))];
+
+const ENTROPY_REPORT: Fields = &[("data", Field::Bytes), ("timestamp", Field::Seconds)];
+
+const PRICE_REPORT: Fields = &[("timestamp", Field::Seconds)];
+
+const LORA_BEACON_REPORT_REQ: Fields = &[
+    ("pub_key", Field::PubKey),
+    ("local_entropy", Field::Bytes),
+    ("remote_entropy", Field::Bytes),
+    ("data", Field::Bytes),
+    ("timestamp", Field::Nanos),
+    ("signature", Field::Bytes),
+];
+
+/// Shared by ingested, valid and invalid beacon reports
+const LORA_BEACON_INGEST_REPORT: Fields = &[
+    ("received_timestamp", Field::Millis),
+    ("report", Field::Message(LORA_BEACON_REPORT_REQ)),
+];
+
+const LORA_WITNESS_REPORT_REQ: Fields = &[
+    ("pub_key", Field::PubKey),
+    ("data", Field::Bytes),
+    ("timestamp", Field::Nanos),
+    ("signature", Field::Bytes),
+];
+
+/// Shared by ingested, verified and invalid witness reports
+const LORA_WITNESS_INGEST_REPORT: Fields = &[
+    ("received_timestamp", Field::Millis),
+    ("report", Field::Message(LORA_WITNESS_REPORT_REQ)),
+];
+
+const LORA_POC: Fields = &[
+    ("poc_id", Field::Bytes),
+    ("beacon_report", Field::Message(LORA_BEACON_INGEST_REPORT)),
+    (
+        "selected_witnesses",
+        Field::Message(LORA_WITNESS_INGEST_REPORT),
+    ),
+    (
+        "unselected_witnesses",
+        Field::Message(LORA_WITNESS_INGEST_REPORT),
+    ),
+];
+
+const PACKET_REPORT: Fields = &[
+    ("received_timestamp", Field::Millis),
+    ("gateway", Field::PubKey),
+    ("payload_hash", Field::Bytes),
+];
+
+const VALID_PACKET: Fields = &[
+    ("gateway", Field::PubKey),
+    ("payload_hash", Field::Bytes),
+    ("packet_timestamp", Field::Millis),
+];
+
+const INVALID_PACKET: Fields = &[("gateway", Field::PubKey), ("payload_hash", Field::Bytes)];
+
+const NON_REWARDABLE_PACKET: Fields = &[
+    ("packet", Field::Message(PACKET_REPORT)),
+    ("timestamp", Field::Millis),
+];
+
+/// Oneof of the rewards of an [`IotRewardShare`]
+const IOT_REWARD: Fields = &[(
+    "gateway_reward",
+    Field::Message(&[("hotspot_key", Field::PubKey)]),
+)];
+
+const IOT_REWARD_SHARE: Fields = &[
+    ("start_period", Field::Seconds),
+    ("end_period", Field::Seconds),
+    ("reward", Field::Message(IOT_REWARD)),
+];
+
+const POC_RECEIPT: Fields = &[
+    ("gateway", Field::PubKey),
+    ("timestamp", Field::Nanos),
+    ("data", Field::Bytes),
+    ("signature", Field::Bytes),
+];
+
+const POC_WITNESS: Fields = &[
+    ("gateway", Field::PubKey),
+    ("timestamp", Field::Nanos),
+    ("packet_hash", Field::Bytes),
+    ("signature", Field::Bytes),
+];
+
+const POC_PATH_ELEMENT: Fields = &[
+    ("challengee", Field::PubKey),
+    ("receipt", Field::Message(POC_RECEIPT)),
+    ("witnesses", Field::Message(POC_WITNESS)),
+];
+
+const POC_RECEIPTS_TXN: Fields = &[
+    ("challenger", Field::PubKey),
+    ("secret", Field::Bytes),
+    ("onion_key_hash", Field::Bytes),
+    ("path", Field::Message(POC_PATH_ELEMENT)),
+    ("signature", Field::Bytes),
+    ("block_hash", Field::Bytes),
+    ("timestamp", Field::Nanos),
+];
+
+/// Only signed poc receipts are stored as blockchain txns
+const BLOCKCHAIN_TXN: Fields = &[(
+    "txn",
+    Field::Message(&[("poc_receipts_v2", Field::Message(POC_RECEIPTS_TXN))]),
+)];
+
+/// Serialize a message to JSON, rendering the given fields
+fn to_json<T: serde::Serialize>(msg: &T, fields: Fields) -> Result<Value> {
+    let value = serde_json::to_value(msg)?;
+    Ok(render(Field::Message(fields), value))
+}
+
+fn render(field: Field, value: Value) -> Value {
+    match (field, value) {
+        (Field::Message(fields), Value::Object(map)) => Value::Object(
+            map.into_iter()
+                .map(|(key, value)| {
+                    let value = match lookup(fields, &key) {
+                        Some(field) => render(field, value),
+                        None => value,
+                    };
+                    (key, value)
+                })
+                .collect::<Map<_, _>>(),
+        ),
+        // Bytes are serialized as arrays of numbers, repeated fields as
+        // arrays of their items
+        (field, Value::Array(items)) => {
+            match as_bytes(&items).and_then(|bytes| render_bytes(field, bytes)) {
+                Some(value) => value,
+                None => Value::Array(items.into_iter().map(|item| render(field, item)).collect()),
+            }
+        }
+        (field, Value::Number(number)) => {
+            match number.as_u64().and_then(|value| to_datetime(field, value)) {
+                Some(timestamp) => Value::String(timestamp.to_rfc3339()),
+                None => Value::Number(number),
+            }
+        }
+        (_, value) => value,
+    }
+}
+
+/// Oneof variants are serialized under the name of their variant, which is
+/// matched regardless of case
+fn lookup(fields: Fields, key: &str) -> Option<Field> {
+    let key = key.replace('_', "");
+    fields
+        .iter()
+        .find(|(name, _)| name.replace('_', "").eq_ignore_ascii_case(&key))
+        .map(|(_, field)| *field)
+}
+
+fn render_bytes(field: Field, bytes: Vec<u8>) -> Option<Value> {
+    match field {
+        Field::PubKey => PublicKey::try_from(bytes.as_slice())
+            .ok()
+            .map(|pubkey| Value::String(pubkey.to_string())),
+        Field::Uuid => uuid::Uuid::from_slice(&bytes)
+            .ok()
+            .map(|uuid| Value::String(uuid.to_string())),
+        Field::Bytes => Some(Value::String(
+            base64::engine::general_purpose::STANDARD.encode(bytes),
+        )),
+        _ => None,
+    }
+}
+
+fn as_bytes(items: &[Value]) -> Option<Vec<u8>> {
+    items
+        .iter()
+        .map(|item| item.as_u64().and_then(|v| u8::try_from(v).ok()))
+        .collect()
+}
+
+/// Unset (zero) timestamps are left as is
+fn to_datetime(field: Field, value: u64) -> Option<DateTime<Utc>> {
+    let value = i64::try_from(value).ok().filter(|value| *value != 0)?;
+    match field {
+        Field::Seconds => Utc.timestamp_opt(value, 0).single(),
+        Field::Millis => Utc.timestamp_millis_opt(value).single(),
+        Field::Nanos => Some(Utc.timestamp_nanos(value)),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    #[test]
+    fn renders_pubkeys_and_timestamps() {
+        let pubkey = PublicKey::from_str("112HqsSX9Ft4ehxQCAcdb4cDSYX2ntsBZ7rtooioz3d3VXcF7MRr")
+            .expect("pubkey");
+        let report = DataTransferSessionIngestReportV1 {
+            received_timestamp: 1_700_000_000_000,
+            report: Some(
+                helium_proto::services::poc_mobile::DataTransferSessionReqV1 {
+                    rewardable_bytes: 42,
+                    pub_key: pubkey.to_vec(),
+                    ..Default::default()
+                },
+            ),
+            ..Default::default()
+        };
+
+        let json = decode(
+            FileType::DataTransferSessionIngestReport,
+            &report.encode_to_vec(),
+        )
+        .expect("decoded report");
+        assert_eq!("2023-11-14T22:13:20+00:00", json["received_timestamp"]);
+        assert_eq!(pubkey.to_string(), json["report"]["pub_key"]);
+        assert_eq!(42, json["report"]["rewardable_bytes"]);
+    }
+
+    #[test]
+    fn renders_fields_of_nested_oneofs() {
+        use helium_proto::services::poc_mobile::{mobile_reward_share::Reward, GatewayReward};
+
+        let pubkey = PublicKey::from_str("112HqsSX9Ft4ehxQCAcdb4cDSYX2ntsBZ7rtooioz3d3VXcF7MRr")
+            .expect("pubkey");
+        let share = MobileRewardShare {
+            start_period: 1_700_000_000,
+            end_period: 1_700_086_400,
+            reward: Some(Reward::GatewayReward(GatewayReward {
+                hotspot_key: pubkey.to_vec(),
+                dc_transfer_reward: 1_000,
+                ..Default::default()
+            })),
+        };
+
+        let json = decode(FileType::MobileRewardShare, &share.encode_to_vec())
+            .expect("decoded reward share");
+        assert_eq!("2023-11-14T22:13:20+00:00", json["start_period"]);
+        assert_eq!("2023-11-15T22:13:20+00:00", json["end_period"]);
+        let reward = json["reward"]
+            .as_object()
+            .and_then(|reward| reward.values().next())
+            .expect("gateway reward");
+        assert_eq!(pubkey.to_string(), reward["hotspot_key"]);
+        // Amounts are not mistaken for timestamps
+        assert_eq!(1_000, reward["dc_transfer_reward"]);
+    }
+
+    #[test]
+    fn data_sets_are_not_decodable() {
+        assert!(decode(FileType::UrbanizationDataSet, &[]).is_err());
+    }
+}
@@ -1,7 +1,7 @@
-use crate::{Error, Result};
+use crate::{Compression, Error, Result};
 use config::{Config, File};
 use serde::{Deserialize, Serialize};
-use std::path::Path;
+use std::path::{Path, PathBuf};
 
 #[derive(Debug, Serialize, Deserialize, Clone)]
 pub struct Settings {
@@ -16,6 +16,31 @@ pub struct Settings {
     /// Should only be used for local testing
     pub access_key_id: Option<String>,
     pub secret_access_key: Option<String>,
+
+    /// Storage backend for the store. Default: s3
+    #[serde(default)]
+    pub backend: StoreBackend,
+    /// Root directory for the local backend. Files for the bucket are kept
+    /// in a `<local_root>/<bucket>` directory. Required for the local backend
+    pub local_root: Option<PathBuf>,
+
+    /// Also upload the checksum of each file as a `<key>.sha256` sidecar
+    /// object next to it. Default: false
+    #[serde(default)]
+    pub checksum_sidecar: bool,
+
+    /// Compression of the files written by the sinks uploading to the bucket,
+    /// one of gzip, zstd or none. Default: gzip
+    #[serde(default)]
+    pub compression: Compression,
+}
+
+#[derive(Debug, Serialize, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum StoreBackend {
+    #[default]
+    S3,
+    Local,
 }
 
 pub fn default_region() -> String {
@@ -0,0 +1,385 @@
+//! Bearer token authentication of mobile clients.
+//!
+//! Tokens are read from a TOML file listing, per token, the gRPC methods it
+//! may call and optionally the public keys it may submit reports for. Only
+//! the SHA-256 of a token is kept in the file:
+//!
+//! ```toml
+//! [[tokens]]
+//! name = "carrier-a"
+//! sha256 = "<hex encoded sha256 of the token>"
+//! methods = ["submit_data_transfer_session", "submit_subscriber_location"]
+//! pub_keys = ["112NqN2WWMwtK29PMzRby62fDydBJfsCLkCAf392stdok48ovNT6"]
+//! ```
+//!
+//! The file is re-read periodically so tokens can be issued and revoked
+//! without restarting ingest. A file that fails to load keeps the previous
+//! tokens in place.
+
+use anyhow::{anyhow, Context, Result};
+use futures::{future::LocalBoxFuture, TryFutureExt};
+use helium_crypto::PublicKey;
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::{
+    collections::{HashMap, HashSet},
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::Arc,
+    time::Duration,
+};
+use task_manager::ManagedTask;
+use tokio::{sync::watch, time};
+use tonic::{metadata::MetadataMap, Request, Status};
+
+type Hash = [u8; 32];
+
+pub type TokensReceiver = watch::Receiver<Arc<Tokens>>;
+
+#[derive(Debug, Deserialize)]
+struct TokenFile {
+    #[serde(default)]
+    tokens: Vec<TokenEntry>,
+}
+
+#[derive(Debug, Deserialize)]
+struct TokenEntry {
+    name: String,
+    sha256: String,
+    methods: Vec<String>,
+    pub_keys: Option<Vec<String>>,
+}
+
+/// What a token is allowed to submit
+#[derive(Debug, PartialEq, Eq)]
+pub struct Scope {
+    /// Name of the token, used in logs
+    pub name: String,
+    /// gRPC methods the token may call. `None` allows every method
+    pub methods: Option<HashSet<String>>,
+    /// Public keys reports may be signed by. `None` allows any key
+    pub pub_keys: Option<HashSet<Vec<u8>>>,
+}
+
+impl Scope {
+    /// Scope of the single token from the `token` setting
+    fn unrestricted() -> Self {
+        Self {
+            name: "default".to_string(),
+            methods: None,
+            pub_keys: None,
+        }
+    }
+
+    fn verify_method(&self, method: &str) -> Result<(), Status> {
+        match &self.methods {
+            Some(methods) if !methods.contains(method) => {
+                metrics::counter!("ingest_token_denied", "method" => method.to_string())
+                    .increment(1);
+                Err(Status::permission_denied(format!(
+                    "token not allowed to call {method}"
+                )))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Check that the token may submit reports signed by `public_key`
+    pub fn verify_key(&self, public_key: &PublicKey) -> Result<(), Status> {
+        match &self.pub_keys {
+            Some(pub_keys) if !pub_keys.contains(&public_key.to_vec()) => {
+                tracing::debug!(token = %self.name, %public_key, "public key not allowed");
+                Err(Status::permission_denied(
+                    "token not allowed to submit for public key",
+                ))
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+/// Known tokens keyed by the SHA-256 of the token
+#[derive(Debug, Default)]
+pub struct Tokens {
+    scopes: HashMap<Hash, Arc<Scope>>,
+}
+
+impl Tokens {
+    /// Tokens from the `token` setting and the token file, either of which
+    /// may be absent
+    pub fn load(token: Option<&str>, token_file: Option<&Path>) -> Result<Self> {
+        let mut tokens = match token_file {
+            Some(path) => Self::from_file(path)?,
+            None => Self::default(),
+        };
+        if let Some(token) = token {
+            tokens.scopes.insert(
+                Sha256::digest(token).into(),
+                Arc::new(Scope::unrestricted()),
+            );
+        }
+        Ok(tokens)
+    }
+
+    fn from_file(path: &Path) -> Result<Self> {
+        let file: TokenFile = config::Config::builder()
+            .add_source(config::File::from(path).format(config::FileFormat::Toml))
+            .build()
+            .and_then(|config| config.try_deserialize())
+            .with_context(|| format!("reading token file {}", path.display()))?;
+        let scopes = file
+            .tokens
+            .into_iter()
+            .map(Self::parse_entry)
+            .collect::<Result<_>>()?;
+        Ok(Self { scopes })
+    }
+
+    fn parse_entry(entry: TokenEntry) -> Result<(Hash, Arc<Scope>)> {
+        let hash = hex_decode(&entry.sha256)
+            .and_then(|bytes| Hash::try_from(bytes).ok())
+            .ok_or_else(|| anyhow!("token {} has an invalid sha256", entry.name))?;
+        let pub_keys = entry
+            .pub_keys
+            .map(|pub_keys| {
+                pub_keys
+                    .iter()
+                    .map(|key| PublicKey::from_str(key).map(|key| key.to_vec()))
+                    .collect::<Result<HashSet<_>, _>>()
+            })
+            .transpose()
+            .with_context(|| format!("token {} has an invalid public key", entry.name))?;
+        let scope = Scope {
+            name: entry.name,
+            methods: Some(entry.methods.into_iter().collect()),
+            pub_keys,
+        };
+        Ok((hash, Arc::new(scope)))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.scopes.is_empty()
+    }
+
+    /// Scope of the bearer token in the authorization header, if the token
+    /// is known
+    pub fn authenticate(&self, metadata: &MetadataMap) -> Option<Arc<Scope>> {
+        let token = metadata
+            .get("authorization")?
+            .to_str()
+            .ok()?
+            .strip_prefix("Bearer ")?;
+        let hash: Hash = Sha256::digest(token).into();
+        self.scopes.get(&hash).cloned()
+    }
+}
+
+fn hex_decode(value: &str) -> Option<Vec<u8>> {
+    if value.len() % 2 != 0 {
+        return None;
+    }
+    (0..value.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(value.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+/// Interceptor rejecting requests without a known bearer token. The scope
+/// of the token is attached to the request for the handlers to check.
+pub fn interceptor(
+    tokens: TokensReceiver,
+) -> impl FnMut(Request<()>) -> Result<Request<()>, Status> + Clone {
+    move |mut req: Request<()>| {
+        let scope = tokens.borrow().authenticate(req.metadata());
+        match scope {
+            Some(scope) => {
+                req.extensions_mut().insert(scope);
+                Ok(req)
+            }
+            None => Err(Status::unauthenticated("No valid auth token")),
+        }
+    }
+}
+
+/// Scope attached to a request by the interceptor, checked against the
+/// method being called
+pub fn authorize<T>(request: &Request<T>, method: &str) -> Result<Arc<Scope>, Status> {
+    let scope = request
+        .extensions()
+        .get::<Arc<Scope>>()
+        .cloned()
+        .ok_or_else(|| Status::unauthenticated("No valid auth token"))?;
+    scope.verify_method(method)?;
+    Ok(scope)
+}
+
+/// Reloads the token file periodically
+pub struct TokenUpdater {
+    token: Option<String>,
+    token_file: PathBuf,
+    refresh_interval: Duration,
+    sender: watch::Sender<Arc<Tokens>>,
+}
+
+impl ManagedTask for TokenUpdater {
+    fn start_task(
+        self: Box<Self>,
+        shutdown: triggered::Listener,
+    ) -> LocalBoxFuture<'static, anyhow::Result<()>> {
+        let handle = tokio::spawn(self.run(shutdown));
+        Box::pin(
+            handle
+                .map_err(anyhow::Error::from)
+                .and_then(|result| async move { result }),
+        )
+    }
+}
+
+impl TokenUpdater {
+    /// Load the configured tokens. The updater is only returned when there
+    /// is a token file to reload.
+    pub fn new(
+        token: Option<String>,
+        token_file: Option<PathBuf>,
+        refresh_interval: Duration,
+    ) -> Result<(TokensReceiver, Option<Self>)> {
+        let tokens = Tokens::load(token.as_deref(), token_file.as_deref())?;
+        let (sender, receiver) = watch::channel(Arc::new(tokens));
+        let updater = token_file.map(|token_file| Self {
+            token,
+            token_file,
+            refresh_interval,
+            sender,
+        });
+        Ok((receiver, updater))
+    }
+
+    pub async fn run(self, shutdown: triggered::Listener) -> anyhow::Result<()> {
+        tracing::info!("starting token updater");
+        let mut trigger_timer = time::interval(self.refresh_interval);
+        // The tokens were loaded on creation
+        trigger_timer.tick().await;
+        loop {
+            tokio::select! {
+                biased;
+                _ = shutdown.clone() => break,
+                _ = trigger_timer.tick() => self.reload(),
+            }
+        }
+        tracing::info!("stopping token updater");
+        Ok(())
+    }
+
+    fn reload(&self) {
+        match Tokens::load(self.token.as_deref(), Some(&self.token_file)) {
+            Ok(tokens) => {
+                metrics::gauge!("ingest_tokens").set(tokens.scopes.len() as f64);
+                self.sender.send_replace(Arc::new(tokens));
+            }
+            Err(err) => {
+                tracing::warn!(?err, "failed to reload tokens, keeping previous tokens");
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tonic::metadata::MetadataValue;
+
+    const CARRIER_KEY: &str = "112HqsSX9Ft4ehxQCAcdb4cDSYX2ntsBZ7rtooioz3d3VXcF7MRr";
+
+    fn sha256_hex(token: &str) -> String {
+        Sha256::digest(token)
+            .iter()
+            .map(|byte| format!("{byte:02x}"))
+            .collect()
+    }
+
+    fn metadata(token: &str) -> MetadataMap {
+        let mut metadata = MetadataMap::new();
+        let value: MetadataValue<_> = format!("Bearer {token}").parse().unwrap();
+        metadata.insert("authorization", value);
+        metadata
+    }
+
+    fn write_token_file(dir: &Path) -> PathBuf {
+        let path = dir.join("tokens.toml");
+        std::fs::write(
+            &path,
+            format!(
+                r#"
+                [[tokens]]
+                name = "carrier"
+                sha256 = "{}"
+                methods = ["submit_subscriber_location"]
+                pub_keys = ["{CARRIER_KEY}"]
+                "#,
+                sha256_hex("carrier-token")
+            ),
+        )
+        .unwrap();
+        path
+    }
+
+    #[test]
+    fn scopes_tokens_to_methods_and_keys() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = write_token_file(dir.path());
+        let tokens = Tokens::load(Some("shared-token"), Some(&path)).unwrap();
+
+        assert!(tokens.authenticate(&metadata("unknown")).is_none());
+
+        let shared = tokens.authenticate(&metadata("shared-token")).unwrap();
+        assert!(shared.verify_method("submit_speedtest").is_ok());
+
+        let carrier = tokens.authenticate(&metadata("carrier-token")).unwrap();
+        assert_eq!("carrier", carrier.name);
+        assert!(carrier.verify_method("submit_subscriber_location").is_ok());
+        assert!(carrier.verify_method("submit_speedtest").is_err());
+
+        let carrier_key = PublicKey::from_str(CARRIER_KEY).unwrap();
+        assert!(carrier.verify_key(&carrier_key).is_ok());
+        let other_key =
+            PublicKey::from_str("112NqN2WWMwtK29PMzRby62fDydBJfsCLkCAf392stdok48ovNT6").unwrap();
+        assert!(carrier.verify_key(&other_key).is_err());
+    }
+
+    #[test]
+    fn rejects_invalid_token_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("tokens.toml");
+        std::fs::write(
+            &path,
+            r#"
+            [[tokens]]
+            name = "broken"
+            sha256 = "not-hex"
+            methods = []
+            "#,
+        )
+        .unwrap();
+        assert!(Tokens::load(None, Some(&path)).is_err());
+    }
+
+    #[test]
+    fn keeps_previous_tokens_when_reload_fails() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = write_token_file(dir.path());
+        let (receiver, updater) =
+            TokenUpdater::new(None, Some(path.clone()), Duration::from_secs(60)).unwrap();
+        let updater = updater.expect("updater for token file");
+
+        std::fs::write(&path, "tokens = 1").unwrap();
+        updater.reload();
+        assert!(receiver
+            .borrow()
+            .authenticate(&metadata("carrier-token"))
+            .is_some());
+
+        std::fs::write(&path, "tokens = []").unwrap();
+        updater.reload();
+        assert!(receiver.borrow().is_empty());
+    }
+}
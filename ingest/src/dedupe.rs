@@ -0,0 +1,352 @@
+//! Suppression of resubmitted reports.
+//!
+//! Each gRPC method keeps the keys of the reports it accepted within a time
+//! window. A report whose key was already persisted in the window is
+//! acknowledged without being written again. A report identical to one that
+//! is still being written is answered `UNAVAILABLE`, as the first write may
+//! yet fail, so the client retries once its outcome is known. Keys are the
+//! SHA-256 of the encoded request, or of the event id for requests that carry
+//! one. The number of keys kept per method is bounded; the oldest keys are
+//! dropped first.
+
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+type Key = [u8; 32];
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Settings {
+    /// Window in which resubmitted reports are suppressed. Default: disabled
+    #[serde(with = "humantime_serde", default)]
+    pub window: Option<Duration>,
+    /// Maximum number of keys kept per method. Default: 1_000_000
+    #[serde(default = "default_max_entries")]
+    pub max_entries: usize,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            window: None,
+            max_entries: default_max_entries(),
+        }
+    }
+}
+
+fn default_max_entries() -> usize {
+    1_000_000
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    /// Admitted, its write has not completed yet
+    Pending,
+    Persisted,
+}
+
+#[derive(Debug, Default)]
+struct Seen {
+    keys: HashMap<Key, State>,
+    order: VecDeque<(Instant, Key)>,
+}
+
+impl Seen {
+    fn expire(&mut self, window: Duration, now: Instant) {
+        while let Some((seen_at, key)) = self.order.front() {
+            if now.saturating_duration_since(*seen_at) < window {
+                break;
+            }
+            self.keys.remove(key);
+            self.order.pop_front();
+        }
+    }
+
+    /// Insert `key` as pending, returning the state of the key if it was
+    /// already seen instead
+    fn insert(&mut self, key: Key, max_entries: usize, now: Instant) -> Option<State> {
+        if let Some(state) = self.keys.get(&key) {
+            return Some(*state);
+        }
+        self.keys.insert(key, State::Pending);
+        self.order.push_back((now, key));
+        if self.order.len() > max_entries {
+            if let Some((_, oldest)) = self.order.pop_front() {
+                self.keys.remove(&oldest);
+            }
+        }
+        None
+    }
+
+    fn remove(&mut self, key: &Key) {
+        if self.keys.remove(key).is_some() {
+            // A forgotten key is most likely the latest one inserted
+            if let Some(index) = self.order.iter().rposition(|(_, seen)| seen == key) {
+                self.order.remove(index);
+            }
+        }
+    }
+}
+
+/// A report that was not a duplicate. It is marked persisted once written,
+/// or forgotten again if it could not be, so that its resubmission is
+/// accepted.
+#[derive(Debug)]
+pub struct Admission {
+    method: &'static str,
+    key: Option<Key>,
+}
+
+/// An identical report was admitted and is still being written
+#[derive(Debug)]
+pub struct Pending;
+
+impl From<Pending> for tonic::Status {
+    fn from(_: Pending) -> Self {
+        tonic::Status::unavailable("identical report pending, retry later")
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct DedupeIndex {
+    window: Option<Duration>,
+    max_entries: usize,
+    methods: Arc<Mutex<HashMap<&'static str, Seen>>>,
+}
+
+impl Default for DedupeIndex {
+    fn default() -> Self {
+        Self::new(&Settings::default())
+    }
+}
+
+impl DedupeIndex {
+    pub fn new(settings: &Settings) -> Self {
+        Self {
+            window: settings.window,
+            max_entries: settings.max_entries,
+            methods: Arc::default(),
+        }
+    }
+
+    /// Admit a report unless an identical one was accepted by `method`
+    /// within the window. An admitted report is recorded as pending.
+    pub fn admit<T>(&self, method: &'static str, report: &T) -> Result<Option<Admission>, Pending>
+    where
+        T: prost::Message,
+    {
+        self.admit_key(method, &report.encode_to_vec())
+    }
+
+    /// Admit a report unless a report with the same event id or other
+    /// unique key was accepted by `method` within the window
+    pub fn admit_key(
+        &self,
+        method: &'static str,
+        key: &[u8],
+    ) -> Result<Option<Admission>, Pending> {
+        let Some(window) = self.window else {
+            return Ok(Some(Admission { method, key: None }));
+        };
+        let key: Key = Sha256::digest(key).into();
+        match self.check_at(method, window, key, Instant::now()) {
+            None => Ok(Some(Admission {
+                method,
+                key: Some(key),
+            })),
+            Some(State::Persisted) => {
+                metrics::counter!("ingest_duplicate_report", "method" => method).increment(1);
+                Ok(None)
+            }
+            Some(State::Pending) => {
+                metrics::counter!("ingest_pending_duplicate_report", "method" => method)
+                    .increment(1);
+                Err(Pending)
+            }
+        }
+    }
+
+    /// Mark an admitted report as persisted, acknowledging its duplicates
+    pub fn persisted(&self, admission: Admission) {
+        let Some(key) = admission.key else {
+            return;
+        };
+        let mut methods = self.methods.lock().expect("dedupe index poisoned");
+        if let Some(state) = methods
+            .get_mut(admission.method)
+            .and_then(|seen| seen.keys.get_mut(&key))
+        {
+            *state = State::Persisted;
+        }
+    }
+
+    /// Forget an admitted report that could not be persisted
+    pub fn forget(&self, admission: Admission) {
+        let Some(key) = admission.key else {
+            return;
+        };
+        let mut methods = self.methods.lock().expect("dedupe index poisoned");
+        if let Some(seen) = methods.get_mut(admission.method) {
+            seen.remove(&key);
+        }
+    }
+
+    fn check_at(
+        &self,
+        method: &'static str,
+        window: Duration,
+        key: Key,
+        now: Instant,
+    ) -> Option<State> {
+        let mut methods = self.methods.lock().expect("dedupe index poisoned");
+        let seen = methods.entry(method).or_default();
+        seen.expire(window, now);
+        seen.insert(key, self.max_entries, now)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(value: u8) -> Key {
+        [value; 32]
+    }
+
+    #[test]
+    fn suppresses_keys_within_window_per_method() {
+        let index = DedupeIndex::new(&Settings {
+            window: Some(Duration::from_secs(60)),
+            max_entries: 10,
+        });
+        let window = Duration::from_secs(60);
+        let now = Instant::now();
+
+        assert!(index
+            .check_at("submit_speedtest", window, key(1), now)
+            .is_none());
+        assert!(index
+            .check_at("submit_speedtest", window, key(1), now)
+            .is_some());
+        assert!(index
+            .check_at("submit_cell_heartbeat", window, key(1), now)
+            .is_none());
+
+        let later = now + Duration::from_secs(61);
+        assert!(index
+            .check_at("submit_speedtest", window, key(1), later)
+            .is_none());
+    }
+
+    #[test]
+    fn drops_oldest_keys_when_full() {
+        let index = DedupeIndex::new(&Settings {
+            window: Some(Duration::from_secs(60)),
+            max_entries: 2,
+        });
+        let window = Duration::from_secs(60);
+        let now = Instant::now();
+
+        for value in 1..=3 {
+            assert!(index
+                .check_at("submit_speedtest", window, key(value), now)
+                .is_none());
+        }
+        assert!(index
+            .check_at("submit_speedtest", window, key(1), now)
+            .is_none());
+        assert!(index
+            .check_at("submit_speedtest", window, key(3), now)
+            .is_some());
+    }
+
+    #[test]
+    fn disabled_without_window() {
+        let index = DedupeIndex::default();
+        assert!(matches!(
+            index.admit_key("submit_speedtest", b"event"),
+            Ok(Some(_))
+        ));
+        assert!(matches!(
+            index.admit_key("submit_speedtest", b"event"),
+            Ok(Some(_))
+        ));
+    }
+
+    #[test]
+    fn forgotten_reports_are_admitted_again() {
+        let index = DedupeIndex::new(&Settings {
+            window: Some(Duration::from_secs(60)),
+            max_entries: 10,
+        });
+
+        let admission = index
+            .admit_key("submit_speedtest", b"event")
+            .expect("not pending")
+            .expect("admitted");
+        assert!(index.admit_key("submit_speedtest", b"event").is_err());
+        index.forget(admission);
+        assert!(matches!(
+            index.admit_key("submit_speedtest", b"event"),
+            Ok(Some(_))
+        ));
+    }
+
+    #[test]
+    fn pending_duplicates_are_refused_until_persisted() {
+        let index = DedupeIndex::new(&Settings {
+            window: Some(Duration::from_secs(60)),
+            max_entries: 10,
+        });
+
+        let admission = index
+            .admit_key("submit_speedtest", b"event")
+            .expect("not pending")
+            .expect("admitted");
+        // The first write may still fail, the duplicate has to be retried
+        assert!(index.admit_key("submit_speedtest", b"event").is_err());
+
+        index.persisted(admission);
+        assert!(matches!(
+            index.admit_key("submit_speedtest", b"event"),
+            Ok(None)
+        ));
+    }
+
+    #[test]
+    fn readmitted_reports_are_suppressed_for_the_whole_window() {
+        let index = DedupeIndex::new(&Settings {
+            window: Some(Duration::from_secs(60)),
+            max_entries: 10,
+        });
+        let window = Duration::from_secs(60);
+        let now = Instant::now();
+
+        assert!(index
+            .check_at("submit_speedtest", window, key(1), now)
+            .is_none());
+        index.forget(Admission {
+            method: "submit_speedtest",
+            key: Some(key(1)),
+        });
+
+        let readmitted = now + Duration::from_secs(30);
+        assert!(index
+            .check_at("submit_speedtest", window, key(1), readmitted)
+            .is_none());
+        // The forgotten admission does not expire the readmitted key
+        let first_expiry = now + Duration::from_secs(61);
+        assert!(index
+            .check_at("submit_speedtest", window, key(1), first_expiry)
+            .is_some());
+
+        let expired = readmitted + Duration::from_secs(61);
+        assert!(index
+            .check_at("submit_speedtest", window, key(1), expired)
+            .is_none());
+    }
+}
@@ -0,0 +1,41 @@
+//! Draining of in-flight requests when ingest shuts down.
+//!
+//! On shutdown the grpc server stops accepting connections and the requests
+//! in flight are given time to finish before the file sinks behind them are
+//! stopped. The sinks then write and commit what they were sent, and the
+//! uploader gets its own deadline to upload the committed files.
+
+use anyhow::{Error, Result};
+use std::{future::Future, pin::pin, time::Duration};
+use tokio::time;
+
+/// Run a grpc server built with `serve_with_shutdown` on `shutdown`. Once
+/// shutdown is triggered, the requests in flight get `drain_timeout` to
+/// finish after which the server is stopped regardless.
+pub async fn serve<F>(
+    server: F,
+    shutdown: triggered::Listener,
+    drain_timeout: Duration,
+) -> Result<()>
+where
+    F: Future<Output = Result<(), tonic::transport::Error>>,
+{
+    let mut server = pin!(server);
+    tokio::select! {
+        result = &mut server => return result.map_err(Error::from),
+        _ = shutdown => (),
+    }
+
+    tracing::info!("grpc server is shutting down, draining in-flight requests");
+    match time::timeout(drain_timeout, server).await {
+        Ok(result) => result.map_err(Error::from),
+        Err(_) => {
+            metrics::counter!("ingest_drain_timeouts").increment(1);
+            tracing::warn!(
+                ?drain_timeout,
+                "in-flight requests did not finish in time, stopping grpc server"
+            );
+            Ok(())
+        }
+    }
+}
@@ -1,5 +1,13 @@
+pub mod auth;
+pub mod dedupe;
+pub mod drain;
+pub mod rate_limit;
+pub mod replay;
+pub mod server_combined;
 pub mod server_iot;
 pub mod server_mobile;
+pub mod session_store;
 pub mod settings;
+pub mod validation;
 
-pub use settings::{Mode, Settings};
+pub use settings::{Mode, Settings, WriteAck};
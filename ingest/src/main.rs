@@ -1,6 +1,7 @@
 use anyhow::Result;
+use chrono::Utc;
 use clap::Parser;
-use ingest::{server_iot, server_mobile, Mode, Settings};
+use ingest::{replay, server_combined, server_iot, server_mobile, session_store, Mode, Settings};
 use std::path;
 
 #[derive(Debug, clap::Parser)]
@@ -18,21 +19,20 @@ pub struct Cli {
 
 impl Cli {
     pub async fn run(self) -> Result<()> {
-        self.cmd.run(Settings::new(self.config)?).await
+        match self.cmd {
+            Cmd::Server(cmd) => cmd.run(&Settings::new(self.config)?).await,
+            Cmd::Sessions(cmd) => cmd.run(&Settings::new(self.config)?),
+            // Replaying talks to a running server and needs no settings
+            Cmd::Replay(cmd) => cmd.run().await,
+        }
     }
 }
 
 #[derive(Debug, clap::Subcommand)]
 pub enum Cmd {
     Server(Server),
-}
-
-impl Cmd {
-    pub async fn run(&self, settings: Settings) -> Result<()> {
-        match self {
-            Self::Server(cmd) => cmd.run(&settings).await,
-        }
-    }
+    Replay(replay::Cmd),
+    Sessions(Sessions),
 }
 
 #[derive(Debug, clap::Args)]
@@ -45,11 +45,34 @@ impl Server {
         // Install the prometheus metrics exporter
         poc_metrics::start_metrics(&settings.metrics)?;
 
-        // run the grpc server in either iot or mobile 5g mode
+        // run the grpc server in iot, mobile 5g or combined mode
         match settings.mode {
             Mode::Iot => server_iot::grpc_server(settings).await,
             Mode::Mobile => server_mobile::grpc_server(settings).await,
+            Mode::Combined => server_combined::grpc_server(settings).await,
+        }
+    }
+}
+
+/// List the active iot sessions persisted by the server. The list is as
+/// recent as the last time the server persisted its sessions.
+#[derive(Debug, clap::Args)]
+pub struct Sessions {}
+
+impl Sessions {
+    pub fn run(&self, settings: &Settings) -> Result<()> {
+        let mut cache = path::PathBuf::from(&settings.cache);
+        if matches!(settings.mode, Mode::Combined) {
+            cache.push("iot");
+        }
+        let path = cache.join(session_store::SESSION_FILE);
+        let now = Utc::now();
+        for session in session_store::read_sessions(&path)? {
+            if session.expires_at > now {
+                println!("{}", serde_json::to_string(&session)?);
+            }
         }
+        Ok(())
     }
 }
 
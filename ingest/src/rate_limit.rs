@@ -0,0 +1,249 @@
+//! Token bucket rate limiting of submissions per public key and gRPC method.
+//!
+//! Every (method, public key) pair gets a bucket holding up to `burst`
+//! tokens that refills at `per_second` tokens a second. A submission takes a
+//! token and is rejected with `RESOURCE_EXHAUSTED` when the bucket is empty.
+//! Methods without a configured limit are not limited.
+
+use chrono::Utc;
+use file_store::file_sink::{FileSinkBuilder, FileSinkClient};
+use file_store::file_upload::FileUpload;
+use helium_crypto::PublicKey;
+use serde::Deserialize;
+use std::{
+    collections::HashMap,
+    path::Path,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+use tonic::Status;
+
+/// Prefix of the files holding rate limited reports
+pub const REJECTED_REPORT_PREFIX: &str = "rate_limited_ingest_report";
+
+/// How often buckets that refilled completely are dropped
+const SWEEP_INTERVAL: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct Settings {
+    /// Limit for methods without a limit of their own. Default: unlimited
+    #[serde(default)]
+    pub default: Option<Limit>,
+    /// Limits keyed by gRPC method name, for example "submit_speedtest"
+    #[serde(default)]
+    pub methods: HashMap<String, Limit>,
+    /// Write rate limited reports to a rejected report sink. Default: false
+    #[serde(default)]
+    pub write_rejected: bool,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Deserialize)]
+pub struct Limit {
+    /// Sustained number of submissions allowed per second per public key
+    pub per_second: f64,
+    /// Number of submissions a public key can make in a burst
+    pub burst: u32,
+}
+
+/// A rate limited submission
+#[derive(Clone, PartialEq, prost::Message)]
+pub struct RateLimitedReportV1 {
+    /// gRPC method the report was submitted to
+    #[prost(string, tag = "1")]
+    pub method: String,
+    /// Public key the submission was limited on
+    #[prost(bytes = "vec", tag = "2")]
+    pub pub_key: Vec<u8>,
+    /// Timestamp the report was received at, in milliseconds
+    #[prost(uint64, tag = "3")]
+    pub received_timestamp: u64,
+    /// The encoded request
+    #[prost(bytes = "vec", tag = "4")]
+    pub report: Vec<u8>,
+}
+
+#[derive(Debug)]
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+impl Bucket {
+    fn new(limit: &Limit, now: Instant) -> Self {
+        Self {
+            tokens: limit.burst as f64,
+            updated: now,
+        }
+    }
+
+    fn refill(&mut self, limit: &Limit, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.updated).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * limit.per_second).min(limit.burst as f64);
+        self.updated = now;
+    }
+
+    fn try_take(&mut self, limit: &Limit, now: Instant) -> bool {
+        self.refill(limit, now);
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn is_full(&mut self, limit: &Limit, now: Instant) -> bool {
+        self.refill(limit, now);
+        self.tokens >= limit.burst as f64
+    }
+}
+
+#[derive(Debug)]
+struct Buckets {
+    buckets: HashMap<(&'static str, Vec<u8>), Bucket>,
+    last_sweep: Instant,
+}
+
+#[derive(Debug, Clone)]
+pub struct RateLimiter {
+    settings: Arc<Settings>,
+    buckets: Arc<Mutex<Buckets>>,
+    rejected_sink: Option<FileSinkClient>,
+}
+
+impl Default for RateLimiter {
+    fn default() -> Self {
+        Self::new(Settings::default(), None)
+    }
+}
+
+impl RateLimiter {
+    pub fn new(settings: Settings, rejected_sink: Option<FileSinkClient>) -> Self {
+        Self {
+            settings: Arc::new(settings),
+            buckets: Arc::new(Mutex::new(Buckets {
+                buckets: HashMap::new(),
+                last_sweep: Instant::now(),
+            })),
+            rejected_sink,
+        }
+    }
+
+    /// Builder for the sink holding rate limited reports. The resulting
+    /// `FileSink` has to be run by the caller.
+    pub fn rejected_sink_builder(target_path: &Path, file_upload: FileUpload) -> FileSinkBuilder {
+        FileSinkBuilder::new(
+            REJECTED_REPORT_PREFIX,
+            target_path,
+            file_upload,
+            concat!(env!("CARGO_PKG_NAME"), "_rate_limited_report"),
+        )
+    }
+
+    fn limit(&self, method: &str) -> Option<&Limit> {
+        self.settings
+            .methods
+            .get(method)
+            .or(self.settings.default.as_ref())
+    }
+
+    /// Take a token for a submission of `report` by `public_key`, rejecting
+    /// it with `RESOURCE_EXHAUSTED` when the key is over its limit
+    pub async fn check<T>(
+        &self,
+        method: &'static str,
+        public_key: &PublicKey,
+        report: &T,
+    ) -> Result<(), Status>
+    where
+        T: prost::Message,
+    {
+        let pub_key = public_key.to_vec();
+        if self.try_take(method, &pub_key, Instant::now()) {
+            return Ok(());
+        }
+
+        metrics::counter!("ingest_rate_limited", "method" => method).increment(1);
+        if let Some(sink) = &self.rejected_sink {
+            let rejected = RateLimitedReportV1 {
+                method: method.to_string(),
+                pub_key,
+                received_timestamp: Utc::now().timestamp_millis() as u64,
+                report: report.encode_to_vec(),
+            };
+            _ = sink.write(rejected, &[("method", method)]).await;
+        }
+        Err(Status::resource_exhausted(format!(
+            "rate limit exceeded for {method}"
+        )))
+    }
+
+    fn try_take(&self, method: &'static str, pub_key: &[u8], now: Instant) -> bool {
+        let Some(limit) = self.limit(method) else {
+            return true;
+        };
+        let mut buckets = self.buckets.lock().expect("rate limit buckets poisoned");
+        // Full buckets hold no state worth keeping, dropping them keeps the
+        // map bounded by the number of recently active keys
+        if now.saturating_duration_since(buckets.last_sweep) >= SWEEP_INTERVAL {
+            buckets.buckets.retain(|(method, _), bucket| {
+                self.limit(method)
+                    .is_some_and(|limit| !bucket.is_full(limit, now))
+            });
+            buckets.last_sweep = now;
+        }
+        buckets
+            .buckets
+            .entry((method, pub_key.to_vec()))
+            .or_insert_with(|| Bucket::new(limit, now))
+            .try_take(limit, now)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn limiter(limit: Limit) -> RateLimiter {
+        RateLimiter::new(
+            Settings {
+                methods: HashMap::from([("submit_speedtest".to_string(), limit)]),
+                ..Default::default()
+            },
+            None,
+        )
+    }
+
+    #[test]
+    fn limits_bursts_and_refills_over_time() {
+        let limiter = limiter(Limit {
+            per_second: 0.5,
+            burst: 2,
+        });
+        let now = Instant::now();
+
+        assert!(limiter.try_take("submit_speedtest", b"a", now));
+        assert!(limiter.try_take("submit_speedtest", b"a", now));
+        assert!(!limiter.try_take("submit_speedtest", b"a", now));
+        // Other keys and unlimited methods are not affected
+        assert!(limiter.try_take("submit_speedtest", b"b", now));
+        assert!(limiter.try_take("submit_cell_heartbeat", b"a", now));
+
+        assert!(!limiter.try_take("submit_speedtest", b"a", now + Duration::from_secs(1)));
+        assert!(limiter.try_take("submit_speedtest", b"a", now + Duration::from_secs(2)));
+    }
+
+    #[test]
+    fn drops_full_buckets() {
+        let limiter = limiter(Limit {
+            per_second: 1.0,
+            burst: 1,
+        });
+        let now = Instant::now();
+
+        assert!(limiter.try_take("submit_speedtest", b"a", now));
+        assert!(limiter.try_take("submit_speedtest", b"b", now + SWEEP_INTERVAL));
+        let buckets = limiter.buckets.lock().unwrap();
+        assert_eq!(1, buckets.buckets.len());
+    }
+}
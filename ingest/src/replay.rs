@@ -0,0 +1,450 @@
+//! Replay of dumped ingest reports into a running ingest server.
+//!
+//! Reports are read from `*_ingest_report` files as written by ingest, the
+//! signed request is taken out of each report and submitted again to the
+//! matching gRPC method. Shifting timestamps invalidates the original
+//! signatures, so shifted requests are re-signed with a given keypair, which
+//! also replaces the public key of the request. Signatures nested in a
+//! request, like the one of a data transfer event, are left as they are.
+
+use anyhow::{anyhow, bail, Context, Result};
+use chrono::{Duration as ChronoDuration, Utc};
+use file_store::{file_source, FileInfo, FileType};
+use futures::StreamExt;
+use helium_crypto::{Keypair, Sign};
+use helium_proto::services::{
+    poc_lora::{
+        poc_lora_client::PocLoraClient, LoraBeaconIngestReportV1, LoraBeaconReportReqV1,
+        LoraWitnessIngestReportV1, LoraWitnessReportReqV1,
+    },
+    poc_mobile::{
+        poc_mobile_client::PocMobileClient, CellHeartbeatIngestReportV1, CellHeartbeatReqV1,
+        CoverageObjectIngestReportV1, CoverageObjectReqV1, DataTransferSessionIngestReportV1,
+        DataTransferSessionReqV1, InvalidatedRadioThresholdIngestReportV1,
+        InvalidatedRadioThresholdReportReqV1, RadioThresholdIngestReportV1,
+        RadioThresholdReportReqV1, SpeedtestIngestReportV1, SpeedtestReqV1,
+        SubscriberLocationIngestReportV1, SubscriberLocationReqV1, WifiHeartbeatIngestReportV1,
+        WifiHeartbeatReqV1,
+    },
+};
+use humantime_serde::re::humantime;
+use prost::Message;
+use std::{
+    collections::BTreeMap,
+    path::{Path, PathBuf},
+    str::FromStr,
+    time::Duration,
+};
+use tokio::time::{self, Interval, MissedTickBehavior};
+use tonic::{
+    metadata::{Ascii, MetadataValue},
+    transport::{Channel, Endpoint},
+    Request, Status,
+};
+
+/// Replay ingest report files against an ingest server
+#[derive(Debug, clap::Args)]
+pub struct Cmd {
+    /// Ingest report files to replay
+    #[clap(required = true)]
+    files: Vec<PathBuf>,
+    /// Ingest server to submit to
+    #[clap(long, default_value = "http://127.0.0.1:9081")]
+    endpoint: String,
+    /// Bearer token for the mobile methods
+    #[clap(long)]
+    token: Option<String>,
+    /// Maximum number of reports submitted per second. Default: unlimited
+    #[clap(long)]
+    rate: Option<f64>,
+    /// Shift timestamps forward, either by a duration like "2 days" or to
+    /// "now", which moves the earliest file to the current time
+    #[clap(long)]
+    shift: Option<Shift>,
+    /// Keypair file to re-sign requests with. Required when shifting
+    #[clap(long)]
+    keypair: Option<PathBuf>,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum Shift {
+    Now,
+    By(Duration),
+}
+
+impl FromStr for Shift {
+    type Err = humantime::DurationError;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "now" => Ok(Self::Now),
+            other => humantime::parse_duration(other).map(Self::By),
+        }
+    }
+}
+
+impl Cmd {
+    pub async fn run(&self) -> Result<()> {
+        let files = self
+            .files
+            .iter()
+            .map(|path| Ok((path.as_path(), file_info(path)?)))
+            .collect::<Result<Vec<_>>>()?;
+
+        let rewrite = match (self.shift, &self.keypair) {
+            (None, None) => None,
+            (Some(_), None) => bail!("a keypair is required to re-sign shifted requests"),
+            (shift, Some(keypair)) => {
+                let offset = match shift {
+                    None => ChronoDuration::zero(),
+                    Some(Shift::By(duration)) => ChronoDuration::from_std(duration)?,
+                    Some(Shift::Now) => match files.iter().map(|(_, info)| info.timestamp).min() {
+                        Some(earliest) => Utc::now() - earliest,
+                        None => ChronoDuration::zero(),
+                    },
+                };
+                Some(Rewrite {
+                    keypair: load_keypair(keypair)?,
+                    offset,
+                })
+            }
+        };
+
+        let token = self
+            .token
+            .as_ref()
+            .map(|token| format!("Bearer {token}").parse())
+            .transpose()
+            .context("invalid token")?;
+        let channel = Endpoint::from_shared(self.endpoint.clone())?
+            .connect()
+            .await
+            .with_context(|| format!("connecting to {}", self.endpoint))?;
+        let pacer = match self.rate {
+            Some(rate) if rate > 0.0 => {
+                let mut interval = time::interval(Duration::from_secs_f64(1.0 / rate));
+                interval.set_missed_tick_behavior(MissedTickBehavior::Delay);
+                Some(interval)
+            }
+            Some(rate) => bail!("invalid rate {rate}"),
+            None => None,
+        };
+
+        let mut replayer = Replayer {
+            mobile: PocMobileClient::new(channel.clone()),
+            lora: PocLoraClient::new(channel),
+            token,
+            rewrite,
+            pacer,
+            stats: BTreeMap::new(),
+        };
+        for (path, info) in files {
+            let file_type = FileType::from_str(&info.prefix)?;
+            let mut reports = file_source::source([path]);
+            while let Some(buf) = reports.next().await {
+                replayer.replay(file_type, &buf?).await?;
+            }
+        }
+
+        for (file_type, stats) in replayer.stats {
+            println!(
+                "{file_type}: submitted {} failed {}",
+                stats.submitted, stats.failed
+            );
+        }
+        Ok(())
+    }
+}
+
+fn file_info(path: &Path) -> Result<FileInfo> {
+    let name = path
+        .file_name()
+        .and_then(|name| name.to_str())
+        .ok_or_else(|| anyhow!("invalid file name {}", path.display()))?;
+    Ok(FileInfo::from_str(name)?)
+}
+
+fn load_keypair(path: &Path) -> Result<Keypair> {
+    let data =
+        std::fs::read(path).with_context(|| format!("reading keypair {}", path.display()))?;
+    Ok(Keypair::try_from(&data[..])?)
+}
+
+#[derive(Debug, Default)]
+struct Stats {
+    submitted: u64,
+    failed: u64,
+}
+
+struct Replayer {
+    mobile: PocMobileClient<Channel>,
+    lora: PocLoraClient<Channel>,
+    token: Option<MetadataValue<Ascii>>,
+    rewrite: Option<Rewrite>,
+    pacer: Option<Interval>,
+    stats: BTreeMap<String, Stats>,
+}
+
+impl Replayer {
+    async fn replay(&mut self, file_type: FileType, buf: &[u8]) -> Result<()> {
+        let result = match file_type {
+            FileType::CellSpeedtestIngestReport => {
+                let request = self.request(SpeedtestIngestReportV1::decode(buf)?.report)?;
+                self.pace().await;
+                self.mobile.submit_speedtest(request).await.map(drop)
+            }
+            FileType::CbrsHeartbeatIngestReport => {
+                let request = self.request(CellHeartbeatIngestReportV1::decode(buf)?.report)?;
+                self.pace().await;
+                self.mobile.submit_cell_heartbeat(request).await.map(drop)
+            }
+            FileType::WifiHeartbeatIngestReport => {
+                let request = self.request(WifiHeartbeatIngestReportV1::decode(buf)?.report)?;
+                self.pace().await;
+                self.mobile.submit_wifi_heartbeat(request).await.map(drop)
+            }
+            FileType::CoverageObjectIngestReport => {
+                let request = self.request(CoverageObjectIngestReportV1::decode(buf)?.report)?;
+                self.pace().await;
+                self.mobile.submit_coverage_object(request).await.map(drop)
+            }
+            FileType::DataTransferSessionIngestReport => {
+                let request =
+                    self.request(DataTransferSessionIngestReportV1::decode(buf)?.report)?;
+                self.pace().await;
+                self.mobile
+                    .submit_data_transfer_session(request)
+                    .await
+                    .map(drop)
+            }
+            FileType::SubscriberLocationIngestReport => {
+                let request =
+                    self.request(SubscriberLocationIngestReportV1::decode(buf)?.report)?;
+                self.pace().await;
+                self.mobile
+                    .submit_subscriber_location(request)
+                    .await
+                    .map(drop)
+            }
+            FileType::RadioThresholdIngestReport => {
+                let request = self.request(RadioThresholdIngestReportV1::decode(buf)?.report)?;
+                self.pace().await;
+                self.mobile.submit_threshold_report(request).await.map(drop)
+            }
+            FileType::InvalidatedRadioThresholdIngestReport => {
+                let request =
+                    self.request(InvalidatedRadioThresholdIngestReportV1::decode(buf)?.report)?;
+                self.pace().await;
+                self.mobile
+                    .submit_invalidated_threshold_report(request)
+                    .await
+                    .map(drop)
+            }
+            FileType::IotBeaconIngestReport => {
+                let request = self.request(LoraBeaconIngestReportV1::decode(buf)?.report)?;
+                self.pace().await;
+                self.lora.submit_lora_beacon(request).await.map(drop)
+            }
+            FileType::IotWitnessIngestReport => {
+                let request = self.request(LoraWitnessIngestReportV1::decode(buf)?.report)?;
+                self.pace().await;
+                self.lora.submit_lora_witness(request).await.map(drop)
+            }
+            other => bail!("replaying {other} files is not supported"),
+        };
+        self.record(file_type, result);
+        Ok(())
+    }
+
+    /// The request of an ingest report, rewritten if requested, with the
+    /// bearer token attached
+    fn request<T>(&self, report: Option<T>) -> Result<Request<T>>
+    where
+        T: Resign,
+    {
+        let report = report.ok_or_else(|| anyhow!("ingest report without request"))?;
+        let report = match &self.rewrite {
+            Some(rewrite) => rewrite.apply(report)?,
+            None => report,
+        };
+        let mut request = Request::new(report);
+        if let Some(token) = &self.token {
+            request
+                .metadata_mut()
+                .insert("authorization", token.clone());
+        }
+        Ok(request)
+    }
+
+    async fn pace(&mut self) {
+        if let Some(pacer) = &mut self.pacer {
+            pacer.tick().await;
+        }
+    }
+
+    fn record(&mut self, file_type: FileType, result: std::result::Result<(), Status>) {
+        let stats = self.stats.entry(file_type.to_string()).or_default();
+        match result {
+            Ok(()) => stats.submitted += 1,
+            Err(status) => {
+                stats.failed += 1;
+                eprintln!("{file_type}: {} {}", status.code(), status.message());
+            }
+        }
+    }
+}
+
+/// Timestamp offset applied to requests, which are then signed by `keypair`
+struct Rewrite {
+    keypair: Keypair,
+    offset: ChronoDuration,
+}
+
+impl Rewrite {
+    fn apply<T>(&self, mut report: T) -> Result<T>
+    where
+        T: Resign,
+    {
+        report.shift(self.offset);
+        *report.pub_key_mut() = self.keypair.public_key().to_vec();
+        report.signature_mut().clear();
+        let signature = self.keypair.sign(&report.encode_to_vec())?;
+        *report.signature_mut() = signature;
+        Ok(report)
+    }
+}
+
+/// Requests that can have their timestamps shifted and be signed again
+trait Resign: Message + Sized {
+    fn shift(&mut self, offset: ChronoDuration);
+    fn pub_key_mut(&mut self) -> &mut Vec<u8>;
+    fn signature_mut(&mut self) -> &mut Vec<u8>;
+}
+
+fn shift_seconds(timestamp: &mut u64, offset: ChronoDuration) {
+    *timestamp = timestamp.saturating_add_signed(offset.num_seconds());
+}
+
+fn shift_nanos(timestamp: &mut u64, offset: ChronoDuration) {
+    let offset = offset.num_nanoseconds().unwrap_or(i64::MAX);
+    *timestamp = timestamp.saturating_add_signed(offset);
+}
+
+macro_rules! impl_resign {
+    ($type:ty, $pub_key:ident, |$report:ident, $offset:ident| $shift:block) => {
+        impl Resign for $type {
+            fn shift(&mut self, $offset: ChronoDuration) {
+                let $report = self;
+                $shift
+            }
+
+            fn pub_key_mut(&mut self) -> &mut Vec<u8> {
+                &mut self.$pub_key
+            }
+
+            fn signature_mut(&mut self) -> &mut Vec<u8> {
+                &mut self.signature
+            }
+        }
+    };
+}
+
+impl_resign!(SpeedtestReqV1, pub_key, |report, offset| {
+    shift_seconds(&mut report.timestamp, offset);
+});
+impl_resign!(CellHeartbeatReqV1, pub_key, |report, offset| {
+    shift_seconds(&mut report.timestamp, offset);
+});
+impl_resign!(WifiHeartbeatReqV1, pub_key, |report, offset| {
+    shift_seconds(&mut report.timestamp, offset);
+    if report.location_validation_timestamp != 0 {
+        shift_seconds(&mut report.location_validation_timestamp, offset);
+    }
+});
+impl_resign!(CoverageObjectReqV1, pub_key, |report, offset| {
+    shift_seconds(&mut report.coverage_claim_time, offset);
+});
+impl_resign!(DataTransferSessionReqV1, pub_key, |report, offset| {
+    if let Some(usage) = &mut report.data_transfer_usage {
+        shift_seconds(&mut usage.timestamp, offset);
+    }
+});
+impl_resign!(
+    SubscriberLocationReqV1,
+    carrier_pub_key,
+    |report, offset| {
+        shift_seconds(&mut report.timestamp, offset);
+    }
+);
+impl_resign!(
+    RadioThresholdReportReqV1,
+    carrier_pub_key,
+    |report, offset| {
+        shift_seconds(&mut report.threshold_timestamp, offset);
+    }
+);
+impl_resign!(
+    InvalidatedRadioThresholdReportReqV1,
+    carrier_pub_key,
+    |report, offset| {
+        shift_seconds(&mut report.timestamp, offset);
+    }
+);
+impl_resign!(LoraBeaconReportReqV1, pub_key, |report, offset| {
+    shift_nanos(&mut report.timestamp, offset);
+});
+impl_resign!(LoraWitnessReportReqV1, pub_key, |report, offset| {
+    shift_nanos(&mut report.timestamp, offset);
+});
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use file_store::traits::MsgVerify;
+    use helium_crypto::{KeyTag, KeyType, Network, PublicKey};
+    use rand::rngs::OsRng;
+
+    fn generate_keypair() -> Keypair {
+        Keypair::generate(
+            KeyTag {
+                network: Network::MainNet,
+                key_type: KeyType::Ed25519,
+            },
+            &mut OsRng,
+        )
+    }
+
+    #[test]
+    fn parses_shift() {
+        assert!(matches!(Shift::from_str("now"), Ok(Shift::Now)));
+        assert!(matches!(
+            Shift::from_str("2 days"),
+            Ok(Shift::By(duration)) if duration == Duration::from_secs(2 * 24 * 60 * 60)
+        ));
+        assert!(Shift::from_str("yesterday").is_err());
+    }
+
+    #[test]
+    fn rewrite_shifts_and_resigns_requests() {
+        let original = generate_keypair();
+        let mut speedtest = SpeedtestReqV1 {
+            pub_key: original.public_key().to_vec(),
+            timestamp: 1_700_000_000,
+            download_speed: 100_000_000,
+            ..Default::default()
+        };
+        speedtest.signature = original.sign(&speedtest.encode_to_vec()).unwrap();
+
+        let rewrite = Rewrite {
+            keypair: generate_keypair(),
+            offset: ChronoDuration::hours(1),
+        };
+        let shifted = rewrite.apply(speedtest.clone()).unwrap();
+
+        assert_eq!(speedtest.timestamp + 3600, shifted.timestamp);
+        assert_eq!(speedtest.download_speed, shifted.download_speed);
+        let public_key = PublicKey::try_from(shifted.pub_key.as_slice()).unwrap();
+        assert_eq!(rewrite.keypair.public_key(), &public_key);
+        assert!(shifted.verify(&public_key).is_ok());
+    }
+}
@@ -0,0 +1,72 @@
+use crate::{drain, server_iot, server_mobile, Settings};
+use anyhow::Result;
+use futures::future::LocalBoxFuture;
+use helium_proto::services::poc_lora;
+use std::{net::SocketAddr, path::Path, time::Duration};
+use task_manager::{ManagedTask, TaskManager};
+use tonic::transport;
+
+/// Serves both the iot and the mobile services from one listener
+pub struct GrpcServer {
+    iot: server_iot::GrpcServer,
+    mobile: server_mobile::GrpcServer,
+    address: SocketAddr,
+    drain_timeout: Duration,
+}
+
+impl ManagedTask for GrpcServer {
+    fn start_task(
+        self: Box<Self>,
+        shutdown: triggered::Listener,
+    ) -> LocalBoxFuture<'static, anyhow::Result<()>> {
+        Box::pin(async move {
+            let grpc_server = transport::Server::builder()
+                .layer(custom_tracing::grpc_layer::new_with_span(
+                    server_mobile::make_span,
+                ))
+                .layer(poc_metrics::request_layer!("ingest_server_grpc_connection"))
+                .add_service(poc_lora::Server::new(self.iot))
+                .add_service(self.mobile.into_service())
+                .serve_with_shutdown(self.address, shutdown.clone());
+
+            drain::serve(grpc_server, shutdown, self.drain_timeout).await
+        })
+    }
+}
+
+pub async fn grpc_server(settings: &Settings) -> Result<()> {
+    let cache = Path::new(&settings.cache);
+    let mut task_manager = TaskManager::new();
+    task_manager.serve_health(settings.metrics.health_endpoint);
+
+    // Each network gets its own cache folder so file sinks sharing a prefix,
+    // like the rate limited report sinks, don't pick up each other's files
+    let iot = server_iot::create(
+        settings,
+        settings.iot_output.as_ref().unwrap_or(&settings.output),
+        &cache.join("iot"),
+        &mut task_manager,
+    )
+    .await?;
+    let mobile = server_mobile::create(
+        settings,
+        settings.mobile_output.as_ref().unwrap_or(&settings.output),
+        &cache.join("mobile"),
+        &mut task_manager,
+    )
+    .await?;
+
+    tracing::info!(
+        "grpc listening on {} and server mode {:?}",
+        settings.listen_addr,
+        settings.mode
+    );
+
+    task_manager.add(GrpcServer {
+        iot,
+        mobile,
+        address: settings.listen_addr,
+        drain_timeout: settings.drain_timeout,
+    });
+    task_manager.start().await
+}
@@ -1,5 +1,5 @@
-use crate::Settings;
-use anyhow::{Error, Result};
+use crate::{drain, rate_limit::RateLimiter, session_store::SessionStore, Settings};
+use anyhow::Result;
 use chrono::Utc;
 use file_store::{
     file_sink::{self, FileSinkClient},
@@ -7,10 +7,7 @@ use file_store::{
     traits::MsgVerify,
     FileType,
 };
-use futures::{
-    future::{LocalBoxFuture, TryFutureExt},
-    Stream, StreamExt,
-};
+use futures::{future::LocalBoxFuture, Stream, StreamExt};
 use helium_crypto::{Network, PublicKey};
 use helium_proto::services::poc_lora::{
     self, lora_stream_request_v1::Request as StreamRequest,
@@ -23,7 +20,7 @@ use std::{convert::TryFrom, net::SocketAddr, path::Path, time::Duration};
 use task_manager::{ManagedTask, TaskManager};
 use tokio::{sync::mpsc::Sender, time::Instant};
 use tokio_stream::wrappers::ReceiverStream;
-use tonic::{transport, Request, Response, Status, Streaming};
+use tonic::{transport, Code, Request, Response, Status, Streaming};
 
 pub type GrpcResult<T> = std::result::Result<Response<T>, Status>;
 pub type GrpcStreamResult<T> = ReceiverStream<Result<T, Status>>;
@@ -35,6 +32,8 @@ type Nonce = [u8; 32];
 struct StreamState {
     beacon_report_sink: FileSinkClient,
     witness_report_sink: FileSinkClient,
+    rate_limiter: RateLimiter,
+    session_store: Option<SessionStore>,
     required_network: Network,
     pub_key_bytes: Option<Vec<u8>>,
     session_key: Option<PublicKey>,
@@ -49,6 +48,8 @@ impl StreamState {
         StreamState {
             beacon_report_sink: server.beacon_report_sink.clone(),
             witness_report_sink: server.witness_report_sink.clone(),
+            rate_limiter: server.rate_limiter.clone(),
+            session_store: server.session_store.clone(),
             required_network: server.required_network,
             pub_key_bytes: None,
             session_key: None,
@@ -63,6 +64,7 @@ impl StreamState {
         let nonce: Nonce = rand::random();
         self.nonce = Some(nonce);
         self.timeout = Instant::now() + self.session_key_offer_timeout;
+        metrics::counter!("ingest_iot_session_offers").increment(1);
 
         tx.send(Ok(LoraStreamResponseV1 {
             response: Some(StreamResponse::Offer(LoraStreamSessionOfferV1 {
@@ -80,10 +82,17 @@ impl StreamState {
             .map(|n| init.nonce == n)
             .unwrap_or(false)
         {
-            self.session_key = Some(
-                PublicKey::try_from(init.session_key)
-                    .map_err(|_| Status::invalid_argument("invalid public key"))?,
-            );
+            let session_key = PublicKey::try_from(init.session_key)
+                .map_err(|_| Status::invalid_argument("invalid public key"))?;
+            let expires_at = Utc::now()
+                + chrono::Duration::from_std(self.session_key_timeout)
+                    .map_err(|_| Status::internal("invalid session key timeout"))?;
+            if let Some(session_store) = &self.session_store {
+                session_store.insert(&init.pub_key, &session_key, expires_at);
+            }
+            metrics::counter!("ingest_iot_session_keys_accepted").increment(1);
+
+            self.session_key = Some(session_key);
             self.pub_key_bytes = Some(init.pub_key);
             self.nonce = None;
             self.timeout = Instant::now() + self.session_key_timeout;
@@ -94,29 +103,67 @@ impl StreamState {
         }
     }
 
+    /// Resume the persisted session of a gateway sending reports without
+    /// initializing a session on this stream. The session is only resumed
+    /// if the report is signed by its session key.
+    fn resume<E: MsgVerify>(&mut self, pub_key: &[u8], report: &E) {
+        if self.session_key.is_some() {
+            return;
+        }
+        let Some(session_store) = &self.session_store else {
+            return;
+        };
+        let now = Utc::now();
+        let Some((session_key, expires_at)) = session_store.resume(pub_key, now, |session_key| {
+            report.verify(session_key).is_ok()
+        }) else {
+            return;
+        };
+        metrics::counter!("ingest_iot_sessions_resumed").increment(1);
+        self.session_key = Some(session_key);
+        self.pub_key_bytes = Some(pub_key.to_vec());
+        self.nonce = None;
+        self.timeout = Instant::now() + (expires_at - now).to_std().unwrap_or_default();
+    }
+
+    /// Forget the session of the stream once its session key timed out
+    fn expire(&self) {
+        if let (Some(pub_key), Some(session_key)) = (&self.pub_key_bytes, &self.session_key) {
+            if let Some(session_store) = &self.session_store {
+                session_store.expire(pub_key, session_key);
+            }
+            metrics::counter!("ingest_iot_session_expiries").increment(1);
+        }
+    }
+
     async fn handle_message(&mut self, message: LoraStreamRequestV1) -> Result<(), Status> {
         let timestamp = Utc::now().timestamp_millis() as u64;
+        match &message.request {
+            Some(StreamRequest::BeaconReport(report)) => self.resume(&report.pub_key, report),
+            Some(StreamRequest::WitnessReport(report)) => self.resume(&report.pub_key, report),
+            _ => (),
+        }
         match message.request {
-            Some(StreamRequest::BeaconReport(report)) => {
-                handle_beacon_report(
-                    &self.beacon_report_sink,
-                    timestamp,
-                    report,
-                    self.session_key.as_ref(),
-                    self.pub_key_bytes.as_deref(),
-                )
-                .await
-            }
-            Some(StreamRequest::WitnessReport(report)) => {
-                handle_witness_report(
-                    &self.witness_report_sink,
-                    timestamp,
-                    report,
-                    self.session_key.as_ref(),
-                    self.pub_key_bytes.as_deref(),
-                )
-                .await
-            }
+            Some(StreamRequest::BeaconReport(report)) => handle_beacon_report(
+                &self.beacon_report_sink,
+                &self.rate_limiter,
+                timestamp,
+                report,
+                self.session_key.as_ref(),
+                self.pub_key_bytes.as_deref(),
+            )
+            .await
+            .or_else(skip_rate_limited),
+            Some(StreamRequest::WitnessReport(report)) => handle_witness_report(
+                &self.witness_report_sink,
+                &self.rate_limiter,
+                timestamp,
+                report,
+                self.session_key.as_ref(),
+                self.pub_key_bytes.as_deref(),
+            )
+            .await
+            .or_else(skip_rate_limited),
             Some(StreamRequest::SessionInit(init)) => verify_public_key(&init.pub_key)
                 .and_then(|pk| verify_network(self.required_network, pk))
                 .and_then(|pk| verify_signature(Some(&pk), init))
@@ -139,6 +186,7 @@ impl StreamState {
         loop {
             tokio::select! {
                 _ = tokio::time::sleep_until(self.timeout) => {
+                    self.expire();
                     let pub_key = self.pub_key_bytes.map(|b| bs58::encode(&b).into_string()).unwrap_or("".to_string());
                     tracing::debug!(?pub_key, "stream request timed out");
                     break;
@@ -172,10 +220,16 @@ impl StreamState {
 pub struct GrpcServer {
     pub beacon_report_sink: FileSinkClient,
     pub witness_report_sink: FileSinkClient,
+    pub rate_limiter: RateLimiter,
+    /// Store of session keys gateways can resume. Sessions are not resumed
+    /// without one
+    pub session_store: Option<SessionStore>,
     pub required_network: Network,
     pub address: SocketAddr,
     pub session_key_offer_timeout: std::time::Duration,
     pub session_key_timeout: std::time::Duration,
+    /// How long in-flight requests may take to finish on shutdown
+    pub drain_timeout: std::time::Duration,
 }
 
 impl ManagedTask for GrpcServer {
@@ -184,23 +238,15 @@ impl ManagedTask for GrpcServer {
         shutdown: triggered::Listener,
     ) -> LocalBoxFuture<'static, anyhow::Result<()>> {
         let address = self.address;
+        let drain_timeout = self.drain_timeout;
         Box::pin(async move {
             let grpc_server = transport::Server::builder()
                 .layer(custom_tracing::grpc_layer::new_with_span(make_span))
                 .layer(poc_metrics::request_layer!("ingest_server_iot_connection"))
                 .add_service(poc_lora::Server::new(*self))
-                .serve(address)
-                .map_err(Error::from);
+                .serve_with_shutdown(address, shutdown.clone());
 
-            tokio::select! {
-                _ = shutdown => {
-                    tracing::warn!("grpc server is shutting down");
-                    Ok(())
-                }
-                _ = grpc_server => {
-                    Err(anyhow::anyhow!("grpc server stopping running"))
-                }
-            }
+            drain::serve(grpc_server, shutdown, drain_timeout).await
         })
     }
 }
@@ -238,25 +284,40 @@ where
         })
 }
 
+/// Rate limited reports are dropped without closing the stream
+fn skip_rate_limited(status: Status) -> Result<(), Status> {
+    if status.code() == Code::ResourceExhausted {
+        Ok(())
+    } else {
+        Err(status)
+    }
+}
+
 async fn handle_beacon_report(
     file_sink: &FileSinkClient,
+    rate_limiter: &RateLimiter,
     timestamp: u64,
     report: LoraBeaconReportReqV1,
     signing_key: Option<&PublicKey>,
     expected_pubkey_bytes: Option<&[u8]>,
 ) -> Result<(), Status> {
-    let ingest_report = verify_signature(signing_key, report)
-        .and_then(|report| {
-            expected_pubkey_bytes
-                .map(|bytes| bytes == report.pub_key)
-                .unwrap_or(true)
-                .then_some(report)
-                .ok_or_else(|| Status::invalid_argument("incorrect pub_key"))
-        })
-        .map(|report| LoraBeaconIngestReportV1 {
-            received_timestamp: timestamp,
-            report: Some(report),
-        })?;
+    let report = verify_signature(signing_key, report).and_then(|report| {
+        expected_pubkey_bytes
+            .map(|bytes| bytes == report.pub_key)
+            .unwrap_or(true)
+            .then_some(report)
+            .ok_or_else(|| Status::invalid_argument("incorrect pub_key"))
+    })?;
+
+    let pub_key = verify_public_key(&report.pub_key)?;
+    rate_limiter
+        .check("submit_lora_beacon", &pub_key, &report)
+        .await?;
+
+    let ingest_report = LoraBeaconIngestReportV1 {
+        received_timestamp: timestamp,
+        report: Some(report),
+    };
 
     _ = file_sink.write(ingest_report, []).await;
 
@@ -265,23 +326,29 @@ async fn handle_beacon_report(
 
 async fn handle_witness_report(
     file_sink: &FileSinkClient,
+    rate_limiter: &RateLimiter,
     timestamp: u64,
     report: LoraWitnessReportReqV1,
     session_key: Option<&PublicKey>,
     expected_pubkey_bytes: Option<&[u8]>,
 ) -> Result<(), Status> {
-    let ingest_report = verify_signature(session_key, report)
-        .and_then(|report| {
-            expected_pubkey_bytes
-                .map(|bytes| bytes == report.pub_key)
-                .unwrap_or(true)
-                .then_some(report)
-                .ok_or_else(|| Status::invalid_argument("incorrect pub_key"))
-        })
-        .map(|report| LoraWitnessIngestReportV1 {
-            received_timestamp: timestamp,
-            report: Some(report),
-        })?;
+    let report = verify_signature(session_key, report).and_then(|report| {
+        expected_pubkey_bytes
+            .map(|bytes| bytes == report.pub_key)
+            .unwrap_or(true)
+            .then_some(report)
+            .ok_or_else(|| Status::invalid_argument("incorrect pub_key"))
+    })?;
+
+    let pub_key = verify_public_key(&report.pub_key)?;
+    rate_limiter
+        .check("submit_lora_witness", &pub_key, &report)
+        .await?;
+
+    let ingest_report = LoraWitnessIngestReportV1 {
+        received_timestamp: timestamp,
+        report: Some(report),
+    };
 
     _ = file_sink.write(ingest_report, []).await;
 
@@ -304,6 +371,7 @@ impl poc_lora::PocLora for GrpcServer {
 
         handle_beacon_report(
             &self.beacon_report_sink,
+            &self.rate_limiter,
             timestamp,
             event,
             Some(&pub_key),
@@ -329,6 +397,7 @@ impl poc_lora::PocLora for GrpcServer {
 
         handle_witness_report(
             &self.witness_report_sink,
+            &self.rate_limiter,
             timestamp,
             event,
             Some(&pub_key),
@@ -356,11 +425,38 @@ impl poc_lora::PocLora for GrpcServer {
 }
 
 pub async fn grpc_server(settings: &Settings) -> Result<()> {
+    let mut task_manager = TaskManager::new();
+    task_manager.serve_health(settings.metrics.health_endpoint);
+    let grpc_server = create(
+        settings,
+        &settings.output,
+        Path::new(&settings.cache),
+        &mut task_manager,
+    )
+    .await?;
+
+    tracing::info!(
+        "grpc listening on {} and server mode {:?}",
+        settings.listen_addr,
+        settings.mode
+    );
+
+    task_manager.add(grpc_server);
+    task_manager.start().await
+}
+
+/// Create the iot server writing to `output`. The uploader and file sinks it
+/// needs are added to `task_manager`.
+pub async fn create(
+    settings: &Settings,
+    output: &file_store::Settings,
+    store_base_path: &Path,
+    task_manager: &mut TaskManager,
+) -> Result<GrpcServer> {
     // Initialize uploader
     let (file_upload, file_upload_server) =
-        file_upload::FileUpload::from_settings_tm(&settings.output).await?;
-
-    let store_base_path = Path::new(&settings.cache);
+        file_upload::FileUpload::from_settings_tm(output).await?;
+    let file_upload_server = file_upload_server.drain_timeout(settings.upload_drain_timeout);
 
     // iot beacon reports
     let (beacon_report_sink, beacon_report_sink_server) = file_sink::FileSinkBuilder::new(
@@ -384,27 +480,41 @@ pub async fn grpc_server(settings: &Settings) -> Result<()> {
     .create()
     .await?;
 
-    let grpc_server = GrpcServer {
+    let (rejected_sink, rejected_sink_server) = if settings.rate_limit.write_rejected {
+        let (sink, server) =
+            RateLimiter::rejected_sink_builder(store_base_path, file_upload.clone())
+                .roll_time(Duration::from_secs(5 * 60))
+                .create()
+                .await?;
+        (Some(sink), Some(server))
+    } else {
+        (None, None)
+    };
+
+    let session_store = settings
+        .persist_sessions
+        .then(|| SessionStore::load(store_base_path))
+        .transpose()?;
+
+    task_manager.add(file_upload_server);
+    task_manager.add(beacon_report_sink_server);
+    task_manager.add(witness_report_sink_server);
+    if let Some(rejected_sink_server) = rejected_sink_server {
+        task_manager.add(rejected_sink_server);
+    }
+    if let Some(session_store) = &session_store {
+        task_manager.add(session_store.clone());
+    }
+
+    Ok(GrpcServer {
         beacon_report_sink,
         witness_report_sink,
+        rate_limiter: RateLimiter::new(settings.rate_limit.clone(), rejected_sink),
+        session_store,
         required_network: settings.network,
         address: settings.listen_addr,
         session_key_offer_timeout: settings.session_key_offer_timeout,
         session_key_timeout: settings.session_key_timeout,
-    };
-
-    tracing::info!(
-        "grpc listening on {} and server mode {:?}",
-        settings.listen_addr,
-        settings.mode
-    );
-
-    TaskManager::builder()
-        .add_task(file_upload_server)
-        .add_task(beacon_report_sink_server)
-        .add_task(witness_report_sink_server)
-        .add_task(grpc_server)
-        .build()
-        .start()
-        .await
+        drain_timeout: settings.drain_timeout,
+    })
 }
@@ -1,5 +1,12 @@
-use crate::Settings;
-use anyhow::{bail, Error, Result};
+use crate::{
+    auth::{self, TokenUpdater, TokensReceiver},
+    dedupe::{Admission, DedupeIndex, Pending},
+    drain,
+    rate_limit::RateLimiter,
+    validation::Validator,
+    Settings, WriteAck,
+};
+use anyhow::{bail, Result};
 use chrono::Utc;
 use file_store::{
     file_sink::{self, FileSinkClient},
@@ -8,7 +15,6 @@ use file_store::{
     FileType,
 };
 use futures::future::LocalBoxFuture;
-use futures_util::TryFutureExt;
 use helium_crypto::{Network, PublicKey};
 use helium_proto::services::poc_mobile::{
     self, CellHeartbeatIngestReportV1, CellHeartbeatReqV1, CellHeartbeatRespV1,
@@ -22,10 +28,11 @@ use helium_proto::services::poc_mobile::{
     SubscriberLocationReqV1, SubscriberLocationRespV1, WifiHeartbeatIngestReportV1,
     WifiHeartbeatReqV1, WifiHeartbeatRespV1,
 };
-use std::{net::SocketAddr, path::Path};
+use std::{net::SocketAddr, path::Path, time::Duration};
 use task_manager::{ManagedTask, TaskManager};
+use tokio::sync::oneshot;
 use tonic::{
-    metadata::{Ascii, MetadataValue},
+    service::{interceptor::InterceptedService, Interceptor},
     transport, Request, Response, Status,
 };
 
@@ -42,9 +49,14 @@ pub struct GrpcServer {
     invalidated_radio_threshold_report_sink: FileSinkClient,
     coverage_object_report_sink: FileSinkClient,
     sp_boosted_rewards_ban_sink: FileSinkClient,
+    validator: Validator,
+    rate_limiter: RateLimiter,
+    dedupe: DedupeIndex,
+    write_ack: WriteAck,
     required_network: Network,
     address: SocketAddr,
-    api_token: MetadataValue<Ascii>,
+    drain_timeout: Duration,
+    tokens: TokensReceiver,
 }
 
 impl ManagedTask for GrpcServer {
@@ -52,27 +64,23 @@ impl ManagedTask for GrpcServer {
         self: Box<Self>,
         shutdown: triggered::Listener,
     ) -> LocalBoxFuture<'static, anyhow::Result<()>> {
-        let api_token = self.api_token.clone();
         let address = self.address;
+        let drain_timeout = self.drain_timeout;
         Box::pin(async move {
-            transport::Server::builder()
+            let grpc_server = transport::Server::builder()
                 .layer(custom_tracing::grpc_layer::new_with_span(make_span))
                 .layer(poc_metrics::request_layer!("ingest_server_grpc_connection"))
-                .add_service(poc_mobile::Server::with_interceptor(
-                    *self,
-                    move |req: Request<()>| match req.metadata().get("authorization") {
-                        Some(t) if api_token == t => Ok(req),
-                        _ => Err(Status::unauthenticated("No valid auth token")),
-                    },
-                ))
-                .serve_with_shutdown(address, shutdown)
-                .map_err(Error::from)
-                .await
+                .add_service((*self).into_service())
+                .serve_with_shutdown(address, shutdown.clone());
+
+            drain::serve(grpc_server, shutdown, drain_timeout).await
         })
     }
 }
 
-fn make_span(_request: &http::request::Request<helium_proto::services::Body>) -> tracing::Span {
+pub(crate) fn make_span(
+    _request: &http::request::Request<helium_proto::services::Body>,
+) -> tracing::Span {
     tracing::info_span!(
         custom_tracing::DEFAULT_SPAN,
         pub_key = tracing::field::Empty,
@@ -81,6 +89,15 @@ fn make_span(_request: &http::request::Request<helium_proto::services::Body>) ->
 }
 
 impl GrpcServer {
+    /// The mobile service, authenticating requests with the configured
+    /// tokens
+    pub fn into_service(
+        self,
+    ) -> InterceptedService<poc_mobile::Server<Self>, impl Interceptor + Clone> {
+        let tokens = self.tokens.clone();
+        poc_mobile::Server::with_interceptor(self, auth::interceptor(tokens))
+    }
+
     fn verify_network(&self, public_key: PublicKey) -> VerifyResult<PublicKey> {
         if self.required_network == public_key.network {
             Ok(public_key)
@@ -102,6 +119,70 @@ impl GrpcServer {
             .map_err(|_| Status::invalid_argument("invalid signature"))?;
         Ok((public_key, event))
     }
+
+    /// Write a report to its sink, waiting for it to be persisted as far as
+    /// the write ack mode requires. Failures are returned as `UNAVAILABLE`
+    /// so clients retry the submission.
+    /// A report that could not be persisted is forgotten by the dedupe index
+    /// so its retry is accepted, otherwise its duplicates are acknowledged
+    /// from now on.
+    async fn write_report<T>(
+        &self,
+        sink: &FileSinkClient,
+        report: T,
+        admission: Admission,
+    ) -> VerifyResult<()>
+    where
+        T: prost::Message,
+    {
+        let written = sink.write(report, []).await;
+        let result = match self.write_ack {
+            WriteAck::None => Ok(()),
+            WriteAck::Written => wait_for(written).await,
+            WriteAck::Committed => match wait_for(written).await {
+                Ok(()) => wait_for(sink.await_commit().await).await,
+                Err(status) => Err(status),
+            },
+        };
+        match result {
+            Ok(()) => self.dedupe.persisted(admission),
+            Err(_) => self.dedupe.forget(admission),
+        }
+        result
+    }
+
+    /// Sessions are deduplicated on their event id, like the packet verifier
+    /// does, falling back to their content when they don't have one
+    fn admit_session(
+        &self,
+        event: &DataTransferSessionReqV1,
+    ) -> Result<Option<Admission>, Pending> {
+        const METHOD: &str = "submit_data_transfer_session";
+        match event
+            .data_transfer_usage
+            .as_ref()
+            .filter(|usage| !usage.event_id.is_empty())
+        {
+            Some(usage) => self.dedupe.admit_key(METHOD, usage.event_id.as_bytes()),
+            None => self.dedupe.admit(METHOD, event),
+        }
+    }
+}
+
+async fn wait_for(
+    receiver: file_store::Result<oneshot::Receiver<file_store::Result>>,
+) -> VerifyResult<()> {
+    let result = match receiver {
+        // The sink dropping the receiver means it stopped before persisting
+        Ok(receiver) => receiver
+            .await
+            .unwrap_or_else(|_| Err(file_store::Error::channel())),
+        Err(err) => Err(err),
+    };
+    result.map_err(|err| {
+        tracing::warn!(?err, "failed to persist report");
+        Status::unavailable("report not persisted, retry later")
+    })
 }
 
 #[tonic::async_trait]
@@ -111,20 +192,37 @@ impl poc_mobile::PocMobile for GrpcServer {
         request: Request<SpeedtestReqV1>,
     ) -> GrpcResult<SpeedtestRespV1> {
         let timestamp: u64 = Utc::now().timestamp_millis() as u64;
+        let scope = auth::authorize(&request, "submit_speedtest")?;
         let event = request.into_inner();
 
         custom_tracing::record_b58("pub_key", &event.pub_key);
 
-        let report = self
+        let (public_key, event) = self
             .verify_public_key(event.pub_key.as_ref())
             .and_then(|public_key| self.verify_network(public_key))
-            .and_then(|public_key| self.verify_signature(public_key, event))
-            .map(|(_, event)| SpeedtestIngestReportV1 {
-                received_timestamp: timestamp,
-                report: Some(event),
-            })?;
+            .and_then(|public_key| self.verify_signature(public_key, event))?;
+
+        scope.verify_key(&public_key)?;
+
+        self.validator.check("submit_speedtest", &event)?;
+
+        self.rate_limiter
+            .check("submit_speedtest", &public_key, &event)
+            .await?;
 
-        _ = self.speedtest_report_sink.write(report, []).await;
+        let Some(admission) = self.dedupe.admit("submit_speedtest", &event)? else {
+            return Ok(Response::new(SpeedtestRespV1 {
+                id: timestamp.to_string(),
+            }));
+        };
+
+        let report = SpeedtestIngestReportV1 {
+            received_timestamp: timestamp,
+            report: Some(event),
+        };
+
+        self.write_report(&self.speedtest_report_sink, report, admission)
+            .await?;
 
         let id = timestamp.to_string();
         Ok(Response::new(SpeedtestRespV1 { id }))
@@ -135,20 +233,37 @@ impl poc_mobile::PocMobile for GrpcServer {
         request: Request<CellHeartbeatReqV1>,
     ) -> GrpcResult<CellHeartbeatRespV1> {
         let timestamp: u64 = Utc::now().timestamp_millis() as u64;
+        let scope = auth::authorize(&request, "submit_cell_heartbeat")?;
         let event = request.into_inner();
 
         custom_tracing::record_b58("pub_key", &event.pub_key);
 
-        let report = self
+        let (public_key, event) = self
             .verify_public_key(event.pub_key.as_ref())
             .and_then(|public_key| self.verify_network(public_key))
-            .and_then(|public_key| self.verify_signature(public_key, event))
-            .map(|(_, event)| CellHeartbeatIngestReportV1 {
-                received_timestamp: timestamp,
-                report: Some(event),
-            })?;
+            .and_then(|public_key| self.verify_signature(public_key, event))?;
+
+        scope.verify_key(&public_key)?;
+
+        self.validator.check("submit_cell_heartbeat", &event)?;
 
-        _ = self.heartbeat_report_sink.write(report, []).await;
+        self.rate_limiter
+            .check("submit_cell_heartbeat", &public_key, &event)
+            .await?;
+
+        let Some(admission) = self.dedupe.admit("submit_cell_heartbeat", &event)? else {
+            return Ok(Response::new(CellHeartbeatRespV1 {
+                id: timestamp.to_string(),
+            }));
+        };
+
+        let report = CellHeartbeatIngestReportV1 {
+            received_timestamp: timestamp,
+            report: Some(event),
+        };
+
+        self.write_report(&self.heartbeat_report_sink, report, admission)
+            .await?;
 
         let id = timestamp.to_string();
         Ok(Response::new(CellHeartbeatRespV1 { id }))
@@ -159,20 +274,37 @@ impl poc_mobile::PocMobile for GrpcServer {
         request: Request<WifiHeartbeatReqV1>,
     ) -> GrpcResult<WifiHeartbeatRespV1> {
         let timestamp: u64 = Utc::now().timestamp_millis() as u64;
+        let scope = auth::authorize(&request, "submit_wifi_heartbeat")?;
         let event = request.into_inner();
 
         custom_tracing::record_b58("pub_key", &event.pub_key);
 
-        let report = self
+        let (public_key, event) = self
             .verify_public_key(event.pub_key.as_ref())
             .and_then(|public_key| self.verify_network(public_key))
-            .and_then(|public_key| self.verify_signature(public_key, event))
-            .map(|(_, event)| WifiHeartbeatIngestReportV1 {
-                received_timestamp: timestamp,
-                report: Some(event),
-            })?;
+            .and_then(|public_key| self.verify_signature(public_key, event))?;
 
-        _ = self.wifi_heartbeat_report_sink.write(report, []).await;
+        scope.verify_key(&public_key)?;
+
+        self.validator.check("submit_wifi_heartbeat", &event)?;
+
+        self.rate_limiter
+            .check("submit_wifi_heartbeat", &public_key, &event)
+            .await?;
+
+        let Some(admission) = self.dedupe.admit("submit_wifi_heartbeat", &event)? else {
+            return Ok(Response::new(WifiHeartbeatRespV1 {
+                id: timestamp.to_string(),
+            }));
+        };
+
+        let report = WifiHeartbeatIngestReportV1 {
+            received_timestamp: timestamp,
+            report: Some(event),
+        };
+
+        self.write_report(&self.wifi_heartbeat_report_sink, report, admission)
+            .await?;
 
         let id = timestamp.to_string();
         Ok(Response::new(WifiHeartbeatRespV1 { id }))
@@ -183,20 +315,35 @@ impl poc_mobile::PocMobile for GrpcServer {
         request: Request<DataTransferSessionReqV1>,
     ) -> GrpcResult<DataTransferSessionRespV1> {
         let timestamp = Utc::now().timestamp_millis() as u64;
+        let scope = auth::authorize(&request, "submit_data_transfer_session")?;
         let event = request.into_inner();
 
         custom_tracing::record_b58("pub_key", &event.pub_key);
 
-        let report = self
+        let (public_key, event) = self
             .verify_public_key(event.pub_key.as_ref())
             .and_then(|public_key| self.verify_network(public_key))
-            .and_then(|public_key| self.verify_signature(public_key, event))
-            .map(|(_, event)| DataTransferSessionIngestReportV1 {
-                received_timestamp: timestamp,
-                report: Some(event),
-            })?;
+            .and_then(|public_key| self.verify_signature(public_key, event))?;
+
+        scope.verify_key(&public_key)?;
+
+        self.rate_limiter
+            .check("submit_data_transfer_session", &public_key, &event)
+            .await?;
+
+        let Some(admission) = self.admit_session(&event)? else {
+            return Ok(Response::new(DataTransferSessionRespV1 {
+                id: timestamp.to_string(),
+            }));
+        };
+
+        let report = DataTransferSessionIngestReportV1 {
+            received_timestamp: timestamp,
+            report: Some(event),
+        };
 
-        _ = self.data_transfer_session_sink.write(report, []).await;
+        self.write_report(&self.data_transfer_session_sink, report, admission)
+            .await?;
 
         Ok(Response::new(DataTransferSessionRespV1 {
             id: timestamp.to_string(),
@@ -208,6 +355,7 @@ impl poc_mobile::PocMobile for GrpcServer {
         request: Request<SubscriberLocationReqV1>,
     ) -> GrpcResult<SubscriberLocationRespV1> {
         let timestamp = Utc::now().timestamp_millis() as u64;
+        let scope = auth::authorize(&request, "submit_subscriber_location")?;
         let event = request.into_inner();
         let subscriber_id = event.subscriber_id.clone();
         let timestamp_millis = event.timestamp;
@@ -219,14 +367,10 @@ impl poc_mobile::PocMobile for GrpcServer {
 
         custom_tracing::record("subscriber_id", bs58::encode(&subscriber_id).into_string());
 
-        let report = self
+        let (public_key, event) = self
             .verify_public_key(event.carrier_pub_key.as_ref())
             .and_then(|public_key| self.verify_network(public_key))
             .and_then(|public_key| self.verify_signature(public_key, event))
-            .map(|(_, event)| SubscriberLocationIngestReportV1 {
-                received_timestamp: timestamp,
-                report: Some(event),
-            })
             .map_err(|status| {
                 tracing::debug!(
                     timestamp = %timestamp_millis,
@@ -235,7 +379,25 @@ impl poc_mobile::PocMobile for GrpcServer {
                 status
             })?;
 
-        _ = self.subscriber_location_report_sink.write(report, []).await;
+        scope.verify_key(&public_key)?;
+
+        self.rate_limiter
+            .check("submit_subscriber_location", &public_key, &event)
+            .await?;
+
+        let Some(admission) = self.dedupe.admit("submit_subscriber_location", &event)? else {
+            return Ok(Response::new(SubscriberLocationRespV1 {
+                id: timestamp.to_string(),
+            }));
+        };
+
+        let report = SubscriberLocationIngestReportV1 {
+            received_timestamp: timestamp,
+            report: Some(event),
+        };
+
+        self.write_report(&self.subscriber_location_report_sink, report, admission)
+            .await?;
 
         Ok(Response::new(SubscriberLocationRespV1 {
             id: timestamp.to_string(),
@@ -247,6 +409,7 @@ impl poc_mobile::PocMobile for GrpcServer {
         request: Request<RadioThresholdReportReqV1>,
     ) -> GrpcResult<RadioThresholdReportRespV1> {
         let timestamp = Utc::now().timestamp_millis() as u64;
+        let scope = auth::authorize(&request, "submit_threshold_report")?;
         let event = request.into_inner();
         let hotspot_pubkey = event.hotspot_pubkey.clone();
         let cbsd_id = event.cbsd_id.clone();
@@ -254,14 +417,10 @@ impl poc_mobile::PocMobile for GrpcServer {
 
         custom_tracing::record_b58("pub_key", &hotspot_pubkey);
 
-        let report = self
+        let (public_key, event) = self
             .verify_public_key(event.carrier_pub_key.as_ref())
             .and_then(|public_key| self.verify_network(public_key))
             .and_then(|public_key| self.verify_signature(public_key, event))
-            .map(|(_, event)| RadioThresholdIngestReportV1 {
-                received_timestamp: timestamp,
-                report: Some(event),
-            })
             .map_err(|status| {
                 tracing::debug!(
                     cbsd_id = ?cbsd_id,
@@ -271,7 +430,25 @@ impl poc_mobile::PocMobile for GrpcServer {
                 status
             })?;
 
-        _ = self.radio_threshold_report_sink.write(report, []).await;
+        scope.verify_key(&public_key)?;
+
+        self.rate_limiter
+            .check("submit_threshold_report", &public_key, &event)
+            .await?;
+
+        let Some(admission) = self.dedupe.admit("submit_threshold_report", &event)? else {
+            return Ok(Response::new(RadioThresholdReportRespV1 {
+                id: timestamp.to_string(),
+            }));
+        };
+
+        let report = RadioThresholdIngestReportV1 {
+            received_timestamp: timestamp,
+            report: Some(event),
+        };
+
+        self.write_report(&self.radio_threshold_report_sink, report, admission)
+            .await?;
 
         Ok(Response::new(RadioThresholdReportRespV1 {
             id: timestamp.to_string(),
@@ -283,6 +460,7 @@ impl poc_mobile::PocMobile for GrpcServer {
         request: Request<InvalidatedRadioThresholdReportReqV1>,
     ) -> GrpcResult<InvalidatedRadioThresholdReportRespV1> {
         let timestamp = Utc::now().timestamp_millis() as u64;
+        let scope = auth::authorize(&request, "submit_invalidated_threshold_report")?;
         let event = request.into_inner();
         let hotspot_pubkey = event.hotspot_pubkey.clone();
         let cbsd_id = event.cbsd_id.clone();
@@ -290,14 +468,10 @@ impl poc_mobile::PocMobile for GrpcServer {
 
         custom_tracing::record_b58("pub_key", &hotspot_pubkey);
 
-        let report = self
+        let (public_key, event) = self
             .verify_public_key(event.carrier_pub_key.as_ref())
             .and_then(|public_key| self.verify_network(public_key))
             .and_then(|public_key| self.verify_signature(public_key, event))
-            .map(|(_, event)| InvalidatedRadioThresholdIngestReportV1 {
-                received_timestamp: timestamp,
-                report: Some(event),
-            })
             .map_err(|status| {
                 tracing::debug!(
                     cbsd_id = ?cbsd_id,
@@ -307,10 +481,32 @@ impl poc_mobile::PocMobile for GrpcServer {
                 status
             })?;
 
-        _ = self
-            .invalidated_radio_threshold_report_sink
-            .write(report, [])
-            .await;
+        scope.verify_key(&public_key)?;
+
+        self.rate_limiter
+            .check("submit_invalidated_threshold_report", &public_key, &event)
+            .await?;
+
+        let Some(admission) = self
+            .dedupe
+            .admit("submit_invalidated_threshold_report", &event)?
+        else {
+            return Ok(Response::new(InvalidatedRadioThresholdReportRespV1 {
+                id: timestamp.to_string(),
+            }));
+        };
+
+        let report = InvalidatedRadioThresholdIngestReportV1 {
+            received_timestamp: timestamp,
+            report: Some(event),
+        };
+
+        self.write_report(
+            &self.invalidated_radio_threshold_report_sink,
+            report,
+            admission,
+        )
+        .await?;
 
         Ok(Response::new(InvalidatedRadioThresholdReportRespV1 {
             id: timestamp.to_string(),
@@ -322,20 +518,37 @@ impl poc_mobile::PocMobile for GrpcServer {
         request: Request<CoverageObjectReqV1>,
     ) -> GrpcResult<CoverageObjectRespV1> {
         let timestamp: u64 = Utc::now().timestamp_millis() as u64;
+        let scope = auth::authorize(&request, "submit_coverage_object")?;
         let event = request.into_inner();
 
         custom_tracing::record_b58("pub_key", &event.pub_key);
 
-        let report = self
+        let (public_key, event) = self
             .verify_public_key(event.pub_key.as_ref())
             .and_then(|public_key| self.verify_network(public_key))
-            .and_then(|public_key| self.verify_signature(public_key, event))
-            .map(|(_, event)| CoverageObjectIngestReportV1 {
-                received_timestamp: timestamp,
-                report: Some(event),
-            })?;
+            .and_then(|public_key| self.verify_signature(public_key, event))?;
+
+        scope.verify_key(&public_key)?;
+
+        self.validator.check("submit_coverage_object", &event)?;
+
+        self.rate_limiter
+            .check("submit_coverage_object", &public_key, &event)
+            .await?;
+
+        let Some(admission) = self.dedupe.admit("submit_coverage_object", &event)? else {
+            return Ok(Response::new(CoverageObjectRespV1 {
+                id: timestamp.to_string(),
+            }));
+        };
+
+        let report = CoverageObjectIngestReportV1 {
+            received_timestamp: timestamp,
+            report: Some(event),
+        };
 
-        _ = self.coverage_object_report_sink.write(report, []).await;
+        self.write_report(&self.coverage_object_report_sink, report, admission)
+            .await?;
 
         let id = timestamp.to_string();
         Ok(Response::new(CoverageObjectRespV1 { id }))
@@ -346,22 +559,44 @@ impl poc_mobile::PocMobile for GrpcServer {
         request: Request<ServiceProviderBoostedRewardsBannedRadioReqV1>,
     ) -> GrpcResult<ServiceProviderBoostedRewardsBannedRadioRespV1> {
         let timestamp = Utc::now().timestamp_millis() as u64;
+        let scope = auth::authorize(&request, "submit_sp_boosted_rewards_banned_radio")?;
         let event = request.into_inner();
 
         custom_tracing::record_b58("pub_key", &event.pubkey);
 
-        let report = self
+        let (public_key, event) = self
             .verify_public_key(event.pubkey.as_ref())
             .and_then(|public_key| self.verify_network(public_key))
-            .and_then(|public_key| self.verify_signature(public_key, event))
-            .map(
-                |(_, event)| ServiceProviderBoostedRewardsBannedRadioIngestReportV1 {
-                    received_timestamp: timestamp,
-                    report: Some(event),
+            .and_then(|public_key| self.verify_signature(public_key, event))?;
+
+        scope.verify_key(&public_key)?;
+
+        self.rate_limiter
+            .check(
+                "submit_sp_boosted_rewards_banned_radio",
+                &public_key,
+                &event,
+            )
+            .await?;
+
+        let Some(admission) = self
+            .dedupe
+            .admit("submit_sp_boosted_rewards_banned_radio", &event)?
+        else {
+            return Ok(Response::new(
+                ServiceProviderBoostedRewardsBannedRadioRespV1 {
+                    id: timestamp.to_string(),
                 },
-            )?;
+            ));
+        };
+
+        let report = ServiceProviderBoostedRewardsBannedRadioIngestReportV1 {
+            received_timestamp: timestamp,
+            report: Some(event),
+        };
 
-        _ = self.sp_boosted_rewards_ban_sink.write(report, []).await;
+        self.write_report(&self.sp_boosted_rewards_ban_sink, report, admission)
+            .await?;
 
         let id = timestamp.to_string();
         Ok(Response::new(
@@ -371,11 +606,38 @@ impl poc_mobile::PocMobile for GrpcServer {
 }
 
 pub async fn grpc_server(settings: &Settings) -> Result<()> {
+    let mut task_manager = TaskManager::new();
+    task_manager.serve_health(settings.metrics.health_endpoint);
+    let grpc_server = create(
+        settings,
+        &settings.output,
+        Path::new(&settings.cache),
+        &mut task_manager,
+    )
+    .await?;
+
+    tracing::info!(
+        "grpc listening on {} and server mode {:?}",
+        settings.listen_addr,
+        settings.mode
+    );
+
+    task_manager.add(grpc_server);
+    task_manager.start().await
+}
+
+/// Create the mobile server writing to `output`. The uploader, file sinks
+/// and token updater it needs are added to `task_manager`.
+pub async fn create(
+    settings: &Settings,
+    output: &file_store::Settings,
+    store_base_path: &Path,
+    task_manager: &mut TaskManager,
+) -> Result<GrpcServer> {
     // Initialize uploader
     let (file_upload, file_upload_server) =
-        file_upload::FileUpload::from_settings_tm(&settings.output).await?;
-
-    let store_base_path = Path::new(&settings.cache);
+        file_upload::FileUpload::from_settings_tm(output).await?;
+    let file_upload_server = file_upload_server.drain_timeout(settings.upload_drain_timeout);
 
     let (heartbeat_report_sink, heartbeat_report_sink_server) = file_sink::FileSinkBuilder::new(
         FileType::CbrsHeartbeatIngestReport,
@@ -484,15 +746,45 @@ pub async fn grpc_server(settings: &Settings) -> Result<()> {
         .create()
         .await?;
 
-    let Some(api_token) = settings
-        .token
-        .as_ref()
-        .and_then(|token| format!("Bearer {token}").parse::<MetadataValue<_>>().ok())
-    else {
-        bail!("expected valid api token in settings");
+    let (rejected_sink, rejected_sink_server) = if settings.rate_limit.write_rejected {
+        let (sink, server) =
+            RateLimiter::rejected_sink_builder(store_base_path, file_upload.clone())
+                .roll_time(settings.roll_time)
+                .create()
+                .await?;
+        (Some(sink), Some(server))
+    } else {
+        (None, None)
     };
+    let rate_limiter = RateLimiter::new(settings.rate_limit.clone(), rejected_sink);
 
-    let grpc_server = GrpcServer {
+    if settings.token.is_none() && settings.token_file.is_none() {
+        bail!("expected api token or token file in settings");
+    }
+    let (tokens, token_updater) = TokenUpdater::new(
+        settings.token.clone(),
+        settings.token_file.clone(),
+        settings.token_refresh_interval,
+    )?;
+
+    task_manager.add(file_upload_server);
+    task_manager.add(heartbeat_report_sink_server);
+    task_manager.add(wifi_heartbeat_report_sink_server);
+    task_manager.add(speedtest_report_sink_server);
+    task_manager.add(data_transfer_session_sink_server);
+    task_manager.add(subscriber_location_report_sink_server);
+    task_manager.add(radio_threshold_report_sink_server);
+    task_manager.add(invalidated_radio_threshold_report_sink_server);
+    task_manager.add(coverage_object_report_sink_server);
+    task_manager.add(sp_boosted_rewards_ban_sink_server);
+    if let Some(rejected_sink_server) = rejected_sink_server {
+        task_manager.add(rejected_sink_server);
+    }
+    if let Some(token_updater) = token_updater {
+        task_manager.add(token_updater);
+    }
+
+    Ok(GrpcServer {
         heartbeat_report_sink,
         wifi_heartbeat_report_sink,
         speedtest_report_sink,
@@ -502,30 +794,13 @@ pub async fn grpc_server(settings: &Settings) -> Result<()> {
         invalidated_radio_threshold_report_sink,
         coverage_object_report_sink,
         sp_boosted_rewards_ban_sink,
+        validator: Validator::new(settings.validation.clone()),
+        rate_limiter,
+        dedupe: DedupeIndex::new(&settings.dedupe),
+        write_ack: settings.write_ack,
         required_network: settings.network,
         address: settings.listen_addr,
-        api_token,
-    };
-
-    tracing::info!(
-        "grpc listening on {} and server mode {:?}",
-        settings.listen_addr,
-        settings.mode
-    );
-
-    TaskManager::builder()
-        .add_task(file_upload_server)
-        .add_task(heartbeat_report_sink_server)
-        .add_task(wifi_heartbeat_report_sink_server)
-        .add_task(speedtest_report_sink_server)
-        .add_task(data_transfer_session_sink_server)
-        .add_task(subscriber_location_report_sink_server)
-        .add_task(radio_threshold_report_sink_server)
-        .add_task(invalidated_radio_threshold_report_sink_server)
-        .add_task(coverage_object_report_sink_server)
-        .add_task(sp_boosted_rewards_ban_sink_server)
-        .add_task(grpc_server)
-        .build()
-        .start()
-        .await
+        drain_timeout: settings.drain_timeout,
+        tokens,
+    })
 }
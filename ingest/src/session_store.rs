@@ -0,0 +1,247 @@
+//! Persistence of iot stream session keys.
+//!
+//! Session keys accepted on a stream are kept with their expiry and written
+//! to a JSON file in the cache folder, so a gateway reconnecting before its
+//! session expired, including after a restart of ingest, can keep signing
+//! reports with its session key instead of being offered a new one.
+
+use anyhow::{Context, Result};
+use chrono::{DateTime, Utc};
+use futures::future::LocalBoxFuture;
+use helium_crypto::PublicKey;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+use task_manager::ManagedTask;
+use tokio::time;
+
+/// Name of the session file in the cache folder
+pub const SESSION_FILE: &str = "iot_sessions.json";
+
+/// How often sessions are written to the session file
+const PERSIST_INTERVAL: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Session {
+    /// Gateway public key, base58 encoded
+    pub gateway: String,
+    /// Session public key, base58 encoded
+    pub session_key: String,
+    pub started_at: DateTime<Utc>,
+    pub expires_at: DateTime<Utc>,
+    /// Number of times the session was resumed on a new stream
+    pub resumed: u64,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct SessionStore {
+    path: Option<PathBuf>,
+    sessions: Arc<Mutex<HashMap<String, Session>>>,
+}
+
+impl SessionStore {
+    /// Store persisting to the session file in `cache`, loading the
+    /// sessions that have not expired yet
+    pub fn load(cache: &Path) -> Result<Self> {
+        let path = cache.join(SESSION_FILE);
+        let sessions = read_sessions(&path)?
+            .into_iter()
+            .filter(|session| session.expires_at > Utc::now())
+            .map(|session| (session.gateway.clone(), session))
+            .collect();
+        Ok(Self {
+            path: Some(path),
+            sessions: Arc::new(Mutex::new(sessions)),
+        })
+    }
+
+    /// Record a session key accepted for `gateway`
+    pub fn insert(&self, gateway: &[u8], session_key: &PublicKey, expires_at: DateTime<Utc>) {
+        let gateway = bs58::encode(gateway).into_string();
+        let session = Session {
+            gateway: gateway.clone(),
+            session_key: session_key.to_string(),
+            started_at: Utc::now(),
+            expires_at,
+            resumed: 0,
+        };
+        self.lock().insert(gateway, session);
+    }
+
+    /// The session key and expiry of an active session of `gateway`. The
+    /// session is only resumed if `verify` accepts its session key.
+    pub fn resume(
+        &self,
+        gateway: &[u8],
+        now: DateTime<Utc>,
+        verify: impl FnOnce(&PublicKey) -> bool,
+    ) -> Option<(PublicKey, DateTime<Utc>)> {
+        let gateway = bs58::encode(gateway).into_string();
+        let mut sessions = self.lock();
+        let session = sessions
+            .get_mut(&gateway)
+            .filter(|session| session.expires_at > now)?;
+        let session_key = PublicKey::from_str(&session.session_key)
+            .ok()
+            .filter(|session_key| verify(session_key))?;
+        session.resumed += 1;
+        Some((session_key, session.expires_at))
+    }
+
+    /// Drop the session of `gateway` once it expired. A newer session the
+    /// gateway started on another stream, with another session key, is kept.
+    pub fn expire(&self, gateway: &[u8], session_key: &PublicKey) {
+        let gateway = bs58::encode(gateway).into_string();
+        let session_key = session_key.to_string();
+        let mut sessions = self.lock();
+        if sessions
+            .get(&gateway)
+            .is_some_and(|session| session.session_key == session_key)
+        {
+            sessions.remove(&gateway);
+        }
+    }
+
+    /// Sessions that have not expired by `now`, ordered by gateway
+    pub fn active(&self, now: DateTime<Utc>) -> Vec<Session> {
+        let mut sessions: Vec<Session> = self
+            .lock()
+            .values()
+            .filter(|session| session.expires_at > now)
+            .cloned()
+            .collect();
+        sessions.sort_by(|a, b| a.gateway.cmp(&b.gateway));
+        sessions
+    }
+
+    /// Write the active sessions to the session file
+    pub fn persist(&self) -> Result<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        let now = Utc::now();
+        self.lock().retain(|_, session| session.expires_at > now);
+        let sessions = self.active(now);
+        metrics::gauge!("ingest_iot_active_sessions").set(sessions.len() as f64);
+
+        let tmp_path = path.with_extension("json.tmp");
+        std::fs::write(&tmp_path, serde_json::to_vec(&sessions)?)
+            .with_context(|| format!("writing {}", tmp_path.display()))?;
+        std::fs::rename(&tmp_path, path)
+            .with_context(|| format!("replacing {}", path.display()))?;
+        Ok(())
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<String, Session>> {
+        self.sessions.lock().expect("session store poisoned")
+    }
+}
+
+/// Sessions in a session file. A missing file holds no sessions.
+pub fn read_sessions(path: &Path) -> Result<Vec<Session>> {
+    match std::fs::read(path) {
+        Ok(data) => serde_json::from_slice(&data)
+            .with_context(|| format!("reading sessions from {}", path.display())),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(vec![]),
+        Err(err) => Err(err.into()),
+    }
+}
+
+impl ManagedTask for SessionStore {
+    fn start_task(
+        self: Box<Self>,
+        shutdown: triggered::Listener,
+    ) -> LocalBoxFuture<'static, anyhow::Result<()>> {
+        Box::pin(async move {
+            let mut trigger_timer = time::interval(PERSIST_INTERVAL);
+            loop {
+                tokio::select! {
+                    biased;
+                    _ = shutdown.clone() => break,
+                    _ = trigger_timer.tick() => {
+                        if let Err(err) = self.persist() {
+                            tracing::warn!(?err, "failed to persist iot sessions");
+                        }
+                    }
+                }
+            }
+            // Keep the latest sessions so gateways can resume after a restart
+            self.persist()
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration as ChronoDuration;
+
+    const GATEWAY: &str = "112NqN2WWMwtK29PMzRby62fDydBJfsCLkCAf392stdok48ovNT6";
+    const SESSION_KEY: &str = "11sctWiP9r5wDJVuDe1Th4XSL2vaawaLLSQF8f8iokAoMAJHxqp";
+
+    fn gateway() -> Vec<u8> {
+        PublicKey::from_str(GATEWAY).unwrap().to_vec()
+    }
+
+    #[test]
+    fn resumes_sessions_until_they_expire() {
+        let store = SessionStore::default();
+        let now = Utc::now();
+        let session_key = PublicKey::from_str(SESSION_KEY).unwrap();
+        store.insert(&gateway(), &session_key, now + ChronoDuration::minutes(30));
+
+        // A report not signed by the session key does not resume it
+        assert!(store.resume(&gateway(), now, |_| false).is_none());
+        assert_eq!(0, store.active(now)[0].resumed);
+
+        let (resumed_key, _) = store
+            .resume(&gateway(), now, |_| true)
+            .expect("active session");
+        assert_eq!(session_key, resumed_key);
+        assert_eq!(1, store.active(now)[0].resumed);
+
+        let later = now + ChronoDuration::minutes(31);
+        assert!(store.resume(&gateway(), later, |_| true).is_none());
+        assert!(store.active(later).is_empty());
+    }
+
+    #[test]
+    fn expires_only_sessions_with_the_same_key() {
+        let store = SessionStore::default();
+        let now = Utc::now();
+        let replaced_key = PublicKey::from_str(GATEWAY).unwrap();
+        let session_key = PublicKey::from_str(SESSION_KEY).unwrap();
+        store.insert(&gateway(), &session_key, now + ChronoDuration::minutes(30));
+
+        // A stream whose session was replaced by a newer one does not drop it
+        store.expire(&gateway(), &replaced_key);
+        assert_eq!(1, store.active(now).len());
+
+        store.expire(&gateway(), &session_key);
+        assert!(store.active(now).is_empty());
+    }
+
+    #[test]
+    fn persists_sessions_across_restarts() {
+        let dir = tempfile::tempdir().unwrap();
+        let store = SessionStore::load(dir.path()).unwrap();
+        let session_key = PublicKey::from_str(SESSION_KEY).unwrap();
+        store.insert(
+            &gateway(),
+            &session_key,
+            Utc::now() + ChronoDuration::minutes(30),
+        );
+        store.persist().unwrap();
+
+        let reloaded = SessionStore::load(dir.path()).unwrap();
+        let sessions = reloaded.active(Utc::now());
+        assert_eq!(1, sessions.len());
+        assert_eq!(GATEWAY, sessions[0].gateway);
+        assert!(reloaded.resume(&gateway(), Utc::now(), |_| true).is_some());
+    }
+}
@@ -1,8 +1,13 @@
+use crate::{dedupe, rate_limit, validation};
 use config::{Config, Environment, File};
 use helium_crypto::Network;
 use humantime_serde::re::humantime;
 use serde::Deserialize;
-use std::{net::SocketAddr, path::Path, time::Duration};
+use std::{
+    net::SocketAddr,
+    path::{Path, PathBuf},
+    time::Duration,
+};
 
 #[derive(Debug, Deserialize)]
 pub struct Settings {
@@ -12,7 +17,7 @@ pub struct Settings {
     pub log: String,
     #[serde(default)]
     pub custom_tracing: custom_tracing::Settings,
-    /// Mode to run the server in (iot or mobile). Required
+    /// Mode to run the server in (iot, mobile or combined). Required
     pub mode: Mode,
     /// Listen address. Required. Default is 0.0.0.0:9081
     #[serde(default = "default_listen_addr")]
@@ -30,15 +35,55 @@ pub struct Settings {
     /// Timeout of session key session in seconds
     #[serde(with = "humantime_serde", default = "default_session_key_timeout")]
     pub session_key_timeout: Duration,
+    /// Persist iot session keys in the cache folder so gateways can resume
+    /// their sessions on new streams and after restarts. Default: false
+    #[serde(default)]
+    pub persist_sessions: bool,
     /// Settings for exposed public API
     /// Target bucket for uploads
     pub output: file_store::Settings,
+    /// Target bucket for iot uploads in combined mode. Default: output
+    pub iot_output: Option<file_store::Settings>,
+    /// Target bucket for mobile uploads in combined mode. Default: output
+    pub mobile_output: Option<file_store::Settings>,
     /// Timeout of session key session in seconds
     #[serde(with = "humantime_serde", default = "default_roll_time")]
     pub roll_time: Duration,
     /// API token required as part of a Bearer authentication GRPC request
-    /// header. Used only by the mobile mode currently
+    /// header. Allowed to call every method. Used only by the mobile mode
+    /// currently
     pub token: Option<String>,
+    /// File of tokens scoped to methods and public keys, see `auth`. Used
+    /// only by the mobile mode currently
+    pub token_file: Option<PathBuf>,
+    /// How often the token file is reloaded. Default: 1 minute
+    #[serde(with = "humantime_serde", default = "default_token_refresh_interval")]
+    pub token_refresh_interval: Duration,
+    /// Per public key and method limits on submissions. Default: unlimited
+    #[serde(default)]
+    pub rate_limit: rate_limit::Settings,
+    /// When submissions are acknowledged relative to persisting them: none
+    /// | written | committed. Used only by the mobile mode currently.
+    /// Default: none
+    #[serde(default)]
+    pub write_ack: WriteAck,
+    /// Suppression of resubmitted reports. Used only by the mobile mode
+    /// currently. Default: disabled
+    #[serde(default)]
+    pub dedupe: dedupe::Settings,
+    /// Rejection of reports with invalid values. Used only by the mobile
+    /// mode currently. Default: disabled
+    #[serde(default)]
+    pub validation: validation::Settings,
+    /// How long in-flight requests may take to finish on shutdown before
+    /// the server stops anyway. Default: 10 seconds
+    #[serde(with = "humantime_serde", default = "default_drain_timeout")]
+    pub drain_timeout: Duration,
+    /// How long pending uploads may take on shutdown, once the sinks have
+    /// committed their files. Files not uploaded by then are uploaded on
+    /// restart. Default: 30 seconds
+    #[serde(with = "humantime_serde", default = "default_upload_drain_timeout")]
+    pub upload_drain_timeout: Duration,
     /// Target output bucket details Metrics settings
     pub metrics: poc_metrics::Settings,
 }
@@ -47,6 +92,10 @@ fn default_roll_time() -> Duration {
     humantime::parse_duration("15 minutes").unwrap()
 }
 
+fn default_token_refresh_interval() -> Duration {
+    humantime::parse_duration("1 minute").unwrap()
+}
+
 fn default_session_key_timeout() -> Duration {
     humantime::parse_duration("30 minutes").unwrap()
 }
@@ -55,6 +104,14 @@ fn default_session_key_offer_timeout() -> Duration {
     humantime::parse_duration("5 seconds").unwrap()
 }
 
+fn default_drain_timeout() -> Duration {
+    humantime::parse_duration("10 seconds").unwrap()
+}
+
+fn default_upload_drain_timeout() -> Duration {
+    humantime::parse_duration("30 seconds").unwrap()
+}
+
 fn default_listen_addr() -> SocketAddr {
     "0.0.0.0:9081".parse().unwrap()
 }
@@ -70,6 +127,23 @@ fn default_log() -> String {
 pub enum Mode {
     Iot,
     Mobile,
+    /// Both the iot and mobile methods on one listener
+    Combined,
+}
+
+/// Point at which a submission is acknowledged to the client
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum WriteAck {
+    /// Acknowledge once the report is handed to its file sink, without
+    /// waiting for the result
+    #[default]
+    None,
+    /// Acknowledge once the file sink has written the report
+    Written,
+    /// Acknowledge once the file holding the report has been committed for
+    /// upload. This can take up to the roll time.
+    Committed,
 }
 
 impl Settings {
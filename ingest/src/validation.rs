@@ -0,0 +1,377 @@
+//! Request level validation of mobile reports.
+//!
+//! Reports that are signed correctly can still carry values no verifier
+//! would accept. Each report type lists the rules it violates; a report
+//! violating any enabled rule is rejected with `INVALID_ARGUMENT` before it
+//! is persisted. New report types are validated by implementing [`Validate`].
+
+use chrono::{DateTime, Utc};
+use helium_proto::services::poc_mobile::{
+    CellHeartbeatReqV1, CoverageObjectReqV1, SpeedtestReqV1, WifiHeartbeatReqV1,
+};
+use humantime_serde::re::humantime;
+use serde::Deserialize;
+use std::{collections::HashSet, fmt, sync::Arc, time::Duration};
+use tonic::Status;
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Settings {
+    /// Reject invalid reports. Default: false
+    #[serde(default)]
+    pub enabled: bool,
+    /// How far report timestamps may be ahead of the clock of the ingest
+    /// server. Default: 10 minutes
+    #[serde(with = "humantime_serde", default = "default_max_clock_skew")]
+    pub max_clock_skew: Duration,
+    /// Maximum speedtest upload speed in bytes per second. Default: 10 Gbps
+    #[serde(default = "default_max_speed")]
+    pub max_upload_speed: u64,
+    /// Maximum speedtest download speed in bytes per second. Default: 10 Gbps
+    #[serde(default = "default_max_speed")]
+    pub max_download_speed: u64,
+    /// Maximum speedtest latency in milliseconds. Default: 60_000
+    #[serde(default = "default_max_latency")]
+    pub max_latency: u32,
+    /// Rules that are not enforced. Default: none
+    #[serde(default)]
+    pub disabled_rules: HashSet<Rule>,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            max_clock_skew: default_max_clock_skew(),
+            max_upload_speed: default_max_speed(),
+            max_download_speed: default_max_speed(),
+            max_latency: default_max_latency(),
+            disabled_rules: HashSet::new(),
+        }
+    }
+}
+
+fn default_max_clock_skew() -> Duration {
+    humantime::parse_duration("10 minutes").unwrap()
+}
+
+fn default_max_speed() -> u64 {
+    // 10 Gbps
+    1_250_000_000
+}
+
+fn default_max_latency() -> u32 {
+    60_000
+}
+
+/// A rule a report can violate. Rule names are used in settings and as the
+/// `reason` label of the rejection metric.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Rule {
+    /// Latitude and longitude are both zero
+    ZeroLocation,
+    /// Latitude or longitude is not a valid coordinate
+    LocationOutOfRange,
+    /// The report is timestamped in the future
+    FutureTimestamp,
+    /// A cbrs report without a cbsd id
+    EmptyCbsdId,
+    /// Upload or download speed above the configured maximum
+    SpeedOutOfRange,
+    /// Latency above the configured maximum
+    LatencyOutOfRange,
+    /// A coverage object without any covered hexes
+    EmptyCoverage,
+}
+
+impl Rule {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Rule::ZeroLocation => "zero_location",
+            Rule::LocationOutOfRange => "location_out_of_range",
+            Rule::FutureTimestamp => "future_timestamp",
+            Rule::EmptyCbsdId => "empty_cbsd_id",
+            Rule::SpeedOutOfRange => "speed_out_of_range",
+            Rule::LatencyOutOfRange => "latency_out_of_range",
+            Rule::EmptyCoverage => "empty_coverage",
+        }
+    }
+}
+
+impl fmt::Display for Rule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// Values a report is validated against
+pub struct Context<'a> {
+    pub settings: &'a Settings,
+    pub now: DateTime<Utc>,
+}
+
+impl Context<'_> {
+    /// Whether a timestamp in seconds is ahead of now by more than the
+    /// allowed clock skew
+    fn is_future(&self, timestamp: u64) -> bool {
+        let skew = i64::try_from(self.settings.max_clock_skew.as_secs()).unwrap_or(i64::MAX);
+        let latest = self.now.timestamp().saturating_add(skew);
+        !i64::try_from(timestamp).is_ok_and(|timestamp| timestamp <= latest)
+    }
+
+    fn location(&self, lat: f64, lon: f64, violations: &mut Vec<Rule>) {
+        if !(-90.0..=90.0).contains(&lat) || !(-180.0..=180.0).contains(&lon) {
+            violations.push(Rule::LocationOutOfRange);
+        } else if lat == 0.0 && lon == 0.0 {
+            violations.push(Rule::ZeroLocation);
+        }
+    }
+}
+
+pub trait Validate {
+    /// Rules violated by this report
+    fn violations(&self, ctx: &Context) -> Vec<Rule>;
+}
+
+impl Validate for SpeedtestReqV1 {
+    fn violations(&self, ctx: &Context) -> Vec<Rule> {
+        let mut violations = vec![];
+        if ctx.is_future(self.timestamp) {
+            violations.push(Rule::FutureTimestamp);
+        }
+        if self.upload_speed > ctx.settings.max_upload_speed
+            || self.download_speed > ctx.settings.max_download_speed
+        {
+            violations.push(Rule::SpeedOutOfRange);
+        }
+        if self.latency > ctx.settings.max_latency {
+            violations.push(Rule::LatencyOutOfRange);
+        }
+        violations
+    }
+}
+
+impl Validate for CellHeartbeatReqV1 {
+    fn violations(&self, ctx: &Context) -> Vec<Rule> {
+        let mut violations = vec![];
+        if ctx.is_future(self.timestamp) {
+            violations.push(Rule::FutureTimestamp);
+        }
+        ctx.location(self.lat, self.lon, &mut violations);
+        if self.cbsd_id.is_empty() {
+            violations.push(Rule::EmptyCbsdId);
+        }
+        violations
+    }
+}
+
+impl Validate for WifiHeartbeatReqV1 {
+    fn violations(&self, ctx: &Context) -> Vec<Rule> {
+        let mut violations = vec![];
+        if ctx.is_future(self.timestamp) {
+            violations.push(Rule::FutureTimestamp);
+        }
+        ctx.location(self.lat, self.lon, &mut violations);
+        violations
+    }
+}
+
+impl Validate for CoverageObjectReqV1 {
+    fn violations(&self, ctx: &Context) -> Vec<Rule> {
+        let mut violations = vec![];
+        if ctx.is_future(self.coverage_claim_time) {
+            violations.push(Rule::FutureTimestamp);
+        }
+        if self.coverage.is_empty() {
+            violations.push(Rule::EmptyCoverage);
+        }
+        violations
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Validator {
+    settings: Arc<Settings>,
+}
+
+impl Validator {
+    pub fn new(settings: Settings) -> Self {
+        Self {
+            settings: Arc::new(settings),
+        }
+    }
+
+    /// Reject a report submitted to `method` that violates an enabled rule
+    pub fn check<T>(&self, method: &'static str, report: &T) -> Result<(), Status>
+    where
+        T: Validate,
+    {
+        match self.first_violation(report, Utc::now()) {
+            None => Ok(()),
+            Some(rule) => {
+                metrics::counter!(
+                    "ingest_report_rejected",
+                    "method" => method,
+                    "reason" => rule.as_str()
+                )
+                .increment(1);
+                Err(Status::invalid_argument(format!("invalid report: {rule}")))
+            }
+        }
+    }
+
+    fn first_violation<T>(&self, report: &T, now: DateTime<Utc>) -> Option<Rule>
+    where
+        T: Validate,
+    {
+        if !self.settings.enabled {
+            return None;
+        }
+        let ctx = Context {
+            settings: &self.settings,
+            now,
+        };
+        report
+            .violations(&ctx)
+            .into_iter()
+            .find(|rule| !self.settings.disabled_rules.contains(rule))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration as ChronoDuration;
+
+    fn validator(disabled_rules: impl IntoIterator<Item = Rule>) -> Validator {
+        Validator::new(Settings {
+            enabled: true,
+            disabled_rules: disabled_rules.into_iter().collect(),
+            ..Default::default()
+        })
+    }
+
+    fn heartbeat(now: DateTime<Utc>) -> CellHeartbeatReqV1 {
+        CellHeartbeatReqV1 {
+            timestamp: now.timestamp() as u64,
+            lat: 37.77,
+            lon: -122.41,
+            cbsd_id: "P27-SCE4255W120200039521".to_string(),
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn accepts_valid_heartbeat() {
+        let now = Utc::now();
+        assert_eq!(None, validator([]).first_violation(&heartbeat(now), now));
+    }
+
+    #[test]
+    fn rejects_invalid_heartbeats() {
+        let now = Utc::now();
+        let validator = validator([]);
+
+        let zero_location = CellHeartbeatReqV1 {
+            lat: 0.0,
+            lon: 0.0,
+            ..heartbeat(now)
+        };
+        assert_eq!(
+            Some(Rule::ZeroLocation),
+            validator.first_violation(&zero_location, now)
+        );
+
+        let out_of_range = CellHeartbeatReqV1 {
+            lat: 91.0,
+            ..heartbeat(now)
+        };
+        assert_eq!(
+            Some(Rule::LocationOutOfRange),
+            validator.first_violation(&out_of_range, now)
+        );
+
+        let empty_cbsd_id = CellHeartbeatReqV1 {
+            cbsd_id: String::new(),
+            ..heartbeat(now)
+        };
+        assert_eq!(
+            Some(Rule::EmptyCbsdId),
+            validator.first_violation(&empty_cbsd_id, now)
+        );
+    }
+
+    #[test]
+    fn allows_clock_skew_on_timestamps() {
+        let now = Utc::now();
+        let validator = validator([]);
+
+        let skewed = CellHeartbeatReqV1 {
+            timestamp: (now + ChronoDuration::minutes(5)).timestamp() as u64,
+            ..heartbeat(now)
+        };
+        assert_eq!(None, validator.first_violation(&skewed, now));
+
+        let future = CellHeartbeatReqV1 {
+            timestamp: (now + ChronoDuration::hours(1)).timestamp() as u64,
+            ..heartbeat(now)
+        };
+        assert_eq!(
+            Some(Rule::FutureTimestamp),
+            validator.first_violation(&future, now)
+        );
+    }
+
+    #[test]
+    fn rejects_absurd_speedtests() {
+        let now = Utc::now();
+        let speedtest = SpeedtestReqV1 {
+            timestamp: now.timestamp() as u64,
+            upload_speed: 10_000_000,
+            download_speed: 100_000_000,
+            latency: 50,
+            ..Default::default()
+        };
+        let validator = validator([]);
+        assert_eq!(None, validator.first_violation(&speedtest, now));
+
+        let too_fast = SpeedtestReqV1 {
+            download_speed: u64::MAX,
+            ..speedtest.clone()
+        };
+        assert_eq!(
+            Some(Rule::SpeedOutOfRange),
+            validator.first_violation(&too_fast, now)
+        );
+
+        let too_slow = SpeedtestReqV1 {
+            latency: 3_600_000,
+            ..speedtest
+        };
+        assert_eq!(
+            Some(Rule::LatencyOutOfRange),
+            validator.first_violation(&too_slow, now)
+        );
+    }
+
+    #[test]
+    fn skips_disabled_rules() {
+        let now = Utc::now();
+        let report = CellHeartbeatReqV1 {
+            lat: 0.0,
+            lon: 0.0,
+            cbsd_id: String::new(),
+            ..heartbeat(now)
+        };
+
+        assert_eq!(
+            Some(Rule::EmptyCbsdId),
+            validator([Rule::ZeroLocation]).first_violation(&report, now)
+        );
+        assert_eq!(
+            None,
+            validator([Rule::ZeroLocation, Rule::EmptyCbsdId]).first_violation(&report, now)
+        );
+        assert_eq!(None, Validator::default().first_violation(&report, now));
+    }
+}
@@ -10,7 +10,11 @@ use helium_proto::services::poc_lora::{
     LoraStreamSessionInitV1, LoraStreamSessionOfferV1, LoraWitnessIngestReportV1,
     LoraWitnessReportReqV1,
 };
-use ingest::server_iot::GrpcServer;
+use ingest::{
+    rate_limit::{self, Limit, RateLimiter},
+    server_iot::GrpcServer,
+    session_store::SessionStore,
+};
 use prost::Message;
 use rand::rngs::OsRng;
 use task_manager::TaskManager;
@@ -71,6 +75,55 @@ async fn initialize_session_and_send_beacon_and_witness() {
         .await;
 }
 
+#[tokio::test]
+async fn reconnecting_gateway_resumes_stored_session() {
+    let (beacon_client, mut beacons) = create_file_sink();
+    let (witness_client, _) = create_file_sink();
+    let addr = get_socket_addr().expect("socket addr");
+
+    LocalSet::new()
+        .run_until(async move {
+            tokio::task::spawn_local(async move {
+                let mut server =
+                    create_test_server(addr, beacon_client, witness_client, None, None);
+                server.session_store = Some(SessionStore::default());
+                TaskManager::builder()
+                    .add_task(server)
+                    .build()
+                    .start()
+                    .await
+            });
+
+            let pub_key = generate_keypair();
+            let session_key = generate_keypair();
+
+            let mut client = connect_and_stream(addr).await;
+            let offer = client.receive_offer().await;
+            client
+                .send_init(
+                    offer,
+                    pub_key.public_key(),
+                    session_key.public_key(),
+                    &pub_key,
+                )
+                .await;
+            drop(client);
+
+            // A new stream accepts reports signed by the stored session key
+            // without initializing a session
+            let mut client = connect_and_stream(addr).await;
+            let _offer = client.receive_offer().await;
+            client.send_beacon(pub_key.public_key(), &session_key).await;
+
+            let beacon_ingest_report = beacons.receive_beacon().await;
+            assert_eq!(
+                beacon_ingest_report.report.unwrap().pub_key,
+                <Vec<u8>>::from(pub_key.public_key())
+            );
+        })
+        .await;
+}
+
 #[tokio::test]
 async fn stream_stops_after_incorrectly_signed_init_request() {
     let (beacon_client, _) = create_file_sink();
@@ -405,6 +458,68 @@ async fn stream_stops_on_session_timeout() {
         .await;
 }
 
+#[tokio::test]
+async fn stream_drops_rate_limited_reports_without_closing() {
+    let (beacon_client, mut beacons) = create_file_sink();
+    let (witness_client, mut witnesses) = create_file_sink();
+    let addr = get_socket_addr().expect("socket addr");
+
+    LocalSet::new()
+        .run_until(async move {
+            tokio::task::spawn_local(async move {
+                let mut server =
+                    create_test_server(addr, beacon_client, witness_client, None, None);
+                server.rate_limiter = RateLimiter::new(
+                    rate_limit::Settings {
+                        methods: [(
+                            "submit_lora_beacon".to_string(),
+                            Limit {
+                                per_second: 0.001,
+                                burst: 1,
+                            },
+                        )]
+                        .into(),
+                        ..Default::default()
+                    },
+                    None,
+                );
+                TaskManager::builder()
+                    .add_task(server)
+                    .build()
+                    .start()
+                    .await
+            });
+
+            let pub_key = generate_keypair();
+            let session_key = generate_keypair();
+
+            let mut client = connect_and_stream(addr).await;
+            let offer = client.receive_offer().await;
+
+            client
+                .send_init(
+                    offer,
+                    pub_key.public_key(),
+                    session_key.public_key(),
+                    &pub_key,
+                )
+                .await;
+
+            client.send_beacon(pub_key.public_key(), &session_key).await;
+            beacons.receive_beacon().await;
+
+            // Over the limit, dropped without closing the stream
+            client.send_beacon(pub_key.public_key(), &session_key).await;
+            client
+                .send_witness(pub_key.public_key(), &session_key)
+                .await;
+
+            witnesses.receive_witness().await;
+            beacons.assert_no_messages();
+        })
+        .await;
+}
+
 struct MockFileSinkReceiver {
     receiver: tokio::sync::mpsc::Receiver<SinkMessage>,
 }
@@ -583,10 +698,13 @@ fn create_test_server(
     GrpcServer {
         beacon_report_sink: beacon_file_sink,
         witness_report_sink: witness_file_sink,
+        rate_limiter: RateLimiter::default(),
+        session_store: None,
         required_network: Network::MainNet,
         address: socket_addr,
         session_key_offer_timeout: std::time::Duration::from_millis(offer_timeout),
         session_key_timeout: std::time::Duration::from_millis(timeout),
+        drain_timeout: seconds(1),
     }
 }
 
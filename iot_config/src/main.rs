@@ -72,7 +72,7 @@ impl Daemon {
 
         let gateway_svc = GatewayService::new(
             settings,
-            metadata_pool,
+            metadata_pool.clone(),
             region_map.clone(),
             auth_cache.clone(),
             delegate_key_cache,
@@ -116,6 +116,12 @@ impl Daemon {
         let db_cleaner = DbCleaner::new(pool.clone(), settings.deleted_entry_retention);
 
         TaskManager::builder()
+            .health_endpoint(settings.metrics.health_endpoint)
+            .health_check("db_pool", db_store::health_check(pool.clone()))
+            .health_check(
+                "metadata_db_pool",
+                db_store::health_check(metadata_pool.clone()),
+            )
             .add_task(grpc_server)
             .add_task(db_cleaner)
             .build()
@@ -286,6 +286,8 @@ impl Server {
         .await?;
 
         TaskManager::builder()
+            .health_endpoint(settings.metrics.health_endpoint)
+            .health_check("db_pool", db_store::health_check(pool.clone()))
             .add_task(file_upload_server)
             .add_task(gateway_rewards_sink_server)
             .add_task(reward_manifests_sink_server)
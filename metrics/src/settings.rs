@@ -7,6 +7,10 @@ pub struct Settings {
     /// Scrape endpoint for metrics
     #[serde(default = "default_metrics_endpoint")]
     pub endpoint: SocketAddr,
+    /// Endpoint serving the health and readiness of the managed tasks, not
+    /// served when unset
+    #[serde(default)]
+    pub health_endpoint: Option<SocketAddr>,
 }
 
 fn default_metrics_endpoint() -> SocketAddr {
@@ -103,6 +103,12 @@ impl Daemon {
         };
 
         TaskManager::builder()
+            .health_endpoint(settings.metrics.health_endpoint)
+            .health_check("db_pool", db_store::health_check(pool.clone()))
+            .health_check(
+                "metadata_db_pool",
+                db_store::health_check(metadata_pool.clone()),
+            )
             .add_task(grpc_server)
             .build()
             .start()
@@ -3,7 +3,7 @@ use file_store::file_sink::FileSinkClient;
 use file_store::mobile_session::{
     DataTransferSessionIngestReport, InvalidDataTransferIngestReport,
 };
-use futures::{Stream, StreamExt};
+use futures::{Stream, TryStreamExt};
 use helium_crypto::PublicKeyBinary;
 use helium_proto::services::mobile_config::NetworkKeyRole;
 use helium_proto::services::poc_mobile::{
@@ -23,11 +23,11 @@ pub async fn accumulate_sessions(
     conn: &mut Transaction<'_, Postgres>,
     invalid_data_session_report_sink: &FileSinkClient,
     curr_file_ts: DateTime<Utc>,
-    reports: impl Stream<Item = DataTransferSessionIngestReport>,
+    reports: impl Stream<Item = file_store::Result<DataTransferSessionIngestReport>>,
 ) -> anyhow::Result<()> {
     tokio::pin!(reports);
 
-    while let Some(report) = reports.next().await {
+    while let Some(report) = reports.try_next().await? {
         // If the reward has been cancelled or it fails verification checks then skip
         // the report and write it out to s3 as invalid
         if report.report.rewardable_bytes == 0 {
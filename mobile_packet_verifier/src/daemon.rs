@@ -2,7 +2,7 @@ use crate::{burner::Burner, event_ids::EventIdPurger, settings::Settings};
 use anyhow::{bail, Result};
 use chrono::{TimeZone, Utc};
 use file_store::{
-    file_info_poller::{FileInfoStream, LookbackBehavior},
+    file_info_poller::{FileInfoStream, LookbackBehavior, MsgDecodeFileInfoPollerParser},
     file_sink::FileSinkClient,
     file_source, file_upload,
     mobile_session::DataTransferSessionIngestReport,
@@ -87,8 +87,16 @@ where
                     tracing::info!("Verifying file: {}", file.file_info);
                     let ts = file.file_info.timestamp;
                     let mut transaction = self.pool.begin().await?;
-                    let reports = file.into_stream(&mut transaction).await?;
-                    crate::accumulate::accumulate_sessions(&self.gateway_info_resolver, &self.authorization_verifier, &mut transaction, &self.invalid_data_session_report_sink, ts, reports).await?;
+                    let accumulated = match file.into_try_stream(&mut transaction).await {
+                        Ok(reports) => crate::accumulate::accumulate_sessions(&self.gateway_info_resolver, &self.authorization_verifier, &mut transaction, &self.invalid_data_session_report_sink, ts, reports).await,
+                        Err(err) => Err(err.into()),
+                    };
+                    if let Err(err) = accumulated {
+                        // The file is not recorded, drop the invalid reports
+                        // written for it so they are not uploaded twice
+                        self.invalid_data_session_report_sink.rollback().await?;
+                        return Err(err);
+                    }
                     transaction.commit().await?;
                     self.invalid_data_session_report_sink.commit().await?;
                 },
@@ -170,6 +178,7 @@ impl Cmd {
                 ))
                 .prefix(FileType::DataTransferSessionIngestReport.to_string())
                 .lookback(LookbackBehavior::StartAfter(settings.start_after))
+                .stream_parser(MsgDecodeFileInfoPollerParser)
                 .create()
                 .await?;
 
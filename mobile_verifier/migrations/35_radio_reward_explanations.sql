@@ -0,0 +1,8 @@
+CREATE TABLE IF NOT EXISTS radio_reward_explanations (
+    hotspot_key TEXT NOT NULL,
+    cbsd_id TEXT NOT NULL,
+    period_start TIMESTAMPTZ NOT NULL,
+    period_end TIMESTAMPTZ NOT NULL,
+    explanation JSONB NOT NULL,
+    PRIMARY KEY (hotspot_key, cbsd_id, period_end)
+);
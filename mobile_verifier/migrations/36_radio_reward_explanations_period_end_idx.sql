@@ -0,0 +1 @@
+CREATE INDEX IF NOT EXISTS radio_reward_explanations_period_end_idx ON radio_reward_explanations (period_end);
@@ -1,3 +1,5 @@
+pub mod reward_diff;
 pub mod reward_from_db;
 pub mod server;
+pub mod shadow_reward;
 pub mod verify_disktree;
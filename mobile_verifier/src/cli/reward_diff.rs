@@ -0,0 +1,269 @@
+use crate::reward_totals::Rewards;
+use anyhow::Result;
+use serde::Serialize;
+use std::{
+    collections::{BTreeSet, HashMap},
+    path::PathBuf,
+};
+
+/// Compare the rewards of two reward runs.
+///
+/// Each run is given as `MobileRewardShare` files or as reward manifests,
+/// whose written files are read from the folder holding the manifest.
+/// Rewards are joined by reward type and entity, radio rewards by hotspot
+/// key and cbsd id, and reported as totals per reward type and the entities
+/// whose rewards moved the most.
+#[derive(Debug, clap::Args)]
+pub struct Cmd {
+    /// Reward share or manifest files of the base run
+    #[clap(long, required = true, num_args = 1..)]
+    base: Vec<PathBuf>,
+    /// Reward share or manifest files of the run compared to the base
+    #[clap(long, required = true, num_args = 1..)]
+    compare: Vec<PathBuf>,
+    /// Number of largest movers to report
+    #[clap(long, default_value_t = 10)]
+    top: usize,
+    /// Print the full diff as JSON, including every changed entity
+    #[clap(long)]
+    json: bool,
+}
+
+impl Cmd {
+    pub async fn run(self) -> Result<()> {
+        let base = Rewards::read(&self.base).await?;
+        let compare = Rewards::read(&self.compare).await?;
+        let diff = RewardDiff::new(&base, &compare, self.top);
+
+        if self.json {
+            println!("{}", serde_json::to_string_pretty(&diff)?);
+        } else {
+            print!("{diff}");
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct EntityDelta {
+    pub reward_type: &'static str,
+    pub key: String,
+    pub base: u64,
+    pub compare: u64,
+    pub delta: i128,
+}
+
+#[derive(Debug, PartialEq, Serialize)]
+pub struct TypeTotals {
+    pub reward_type: &'static str,
+    pub base: u64,
+    pub compare: u64,
+    pub delta: i128,
+    /// Entities only rewarded in the compared run
+    pub added: usize,
+    /// Entities only rewarded in the base run
+    pub removed: usize,
+    /// Entities rewarded in both runs with different amounts
+    pub changed: usize,
+}
+
+#[derive(Debug, Serialize)]
+pub struct RewardDiff {
+    pub totals: Vec<TypeTotals>,
+    /// Entities with the largest absolute deltas
+    pub top_movers: Vec<EntityDelta>,
+    /// Every entity whose rewards differ, by reward type and key
+    pub deltas: Vec<EntityDelta>,
+}
+
+impl RewardDiff {
+    pub fn new(base: &Rewards, compare: &Rewards, top: usize) -> Self {
+        let empty = HashMap::new();
+        let reward_types: BTreeSet<_> = base
+            .amounts()
+            .keys()
+            .chain(compare.amounts().keys())
+            .copied()
+            .collect();
+
+        let mut totals = vec![];
+        let mut deltas = vec![];
+        for reward_type in reward_types {
+            let base = base.amounts().get(reward_type).unwrap_or(&empty);
+            let compare = compare.amounts().get(reward_type).unwrap_or(&empty);
+            let keys: BTreeSet<&String> = base.keys().chain(compare.keys()).collect();
+
+            let mut type_totals = TypeTotals {
+                reward_type,
+                base: base.values().sum(),
+                compare: compare.values().sum(),
+                delta: 0,
+                added: 0,
+                removed: 0,
+                changed: 0,
+            };
+            type_totals.delta = type_totals.compare as i128 - type_totals.base as i128;
+
+            for key in keys {
+                let (base, compare) = (base.get(key), compare.get(key));
+                match (base, compare) {
+                    (None, Some(_)) => type_totals.added += 1,
+                    (Some(_), None) => type_totals.removed += 1,
+                    (Some(base), Some(compare)) if base != compare => type_totals.changed += 1,
+                    _ => continue,
+                }
+                let base = base.copied().unwrap_or_default();
+                let compare = compare.copied().unwrap_or_default();
+                deltas.push(EntityDelta {
+                    reward_type,
+                    key: key.clone(),
+                    base,
+                    compare,
+                    delta: compare as i128 - base as i128,
+                });
+            }
+            totals.push(type_totals);
+        }
+
+        let mut movers: Vec<&EntityDelta> = deltas.iter().collect();
+        movers.sort_by(|a, b| b.delta.abs().cmp(&a.delta.abs()));
+        let top_movers = movers.into_iter().take(top).cloned().collect();
+
+        Self {
+            totals,
+            top_movers,
+            deltas,
+        }
+    }
+}
+
+impl std::fmt::Display for RewardDiff {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        writeln!(
+            f,
+            "{:<18} {:>22} {:>22} {:>22} {:>8} {:>8} {:>8}",
+            "reward type", "base", "compare", "delta", "added", "removed", "changed"
+        )?;
+        for totals in &self.totals {
+            writeln!(
+                f,
+                "{:<18} {:>22} {:>22} {:>+22} {:>8} {:>8} {:>8}",
+                totals.reward_type,
+                totals.base,
+                totals.compare,
+                totals.delta,
+                totals.added,
+                totals.removed,
+                totals.changed
+            )?;
+        }
+        if self.top_movers.is_empty() {
+            return writeln!(f, "\nno entity rewards changed");
+        }
+        writeln!(f, "\nlargest movers")?;
+        for delta in &self.top_movers {
+            writeln!(
+                f,
+                "{:<18} {} {} -> {} ({:+})",
+                delta.reward_type, delta.key, delta.base, delta.compare, delta.delta
+            )?;
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use helium_crypto::PublicKey;
+    use helium_proto::{
+        services::poc_mobile::{
+            mobile_reward_share::Reward, GatewayReward, MobileRewardShare, ServiceProviderReward,
+        },
+        ServiceProvider,
+    };
+    use std::str::FromStr;
+
+    const HOTSPOT_1: &str = "112NqN2WWMwtK29PMzRby62fDydBJfsCLkCAf392stdok48ovNT6";
+    const HOTSPOT_2: &str = "11sctWiP9r5wDJVuDe1Th4XSL2vaawaLLSQF8f8iokAoMAJHxqp";
+    const HOTSPOT_3: &str = "112HqsSX9Ft4ehxQCAcdb4cDSYX2ntsBZ7rtooioz3d3VXcF7MRr";
+
+    fn gateway_reward(hotspot: &str, dc_transfer_reward: u64) -> MobileRewardShare {
+        MobileRewardShare {
+            start_period: 0,
+            end_period: 0,
+            reward: Some(Reward::GatewayReward(GatewayReward {
+                hotspot_key: PublicKey::from_str(hotspot).unwrap().to_vec(),
+                dc_transfer_reward,
+                ..Default::default()
+            })),
+        }
+    }
+
+    fn service_provider_reward(amount: u64) -> MobileRewardShare {
+        MobileRewardShare {
+            start_period: 0,
+            end_period: 0,
+            reward: Some(Reward::ServiceProviderReward(ServiceProviderReward {
+                service_provider_id: ServiceProvider::HeliumMobile as i32,
+                amount,
+            })),
+        }
+    }
+
+    fn rewards(shares: Vec<MobileRewardShare>) -> Rewards {
+        let mut rewards = Rewards::default();
+        for share in shares {
+            rewards.add(share).unwrap();
+        }
+        rewards
+    }
+
+    #[test]
+    fn reports_totals_and_largest_movers() {
+        let base = rewards(vec![
+            gateway_reward(HOTSPOT_1, 100),
+            gateway_reward(HOTSPOT_2, 50),
+            gateway_reward(HOTSPOT_2, 50),
+            service_provider_reward(1_000),
+        ]);
+        let compare = rewards(vec![
+            gateway_reward(HOTSPOT_1, 90),
+            gateway_reward(HOTSPOT_3, 300),
+            service_provider_reward(1_000),
+        ]);
+
+        let diff = RewardDiff::new(&base, &compare, 2);
+
+        assert_eq!(
+            vec![
+                TypeTotals {
+                    reward_type: "gateway",
+                    base: 200,
+                    compare: 390,
+                    delta: 190,
+                    added: 1,
+                    removed: 1,
+                    changed: 1,
+                },
+                TypeTotals {
+                    reward_type: "service_provider",
+                    base: 1_000,
+                    compare: 1_000,
+                    delta: 0,
+                    added: 0,
+                    removed: 0,
+                    changed: 0,
+                },
+            ],
+            diff.totals
+        );
+        assert_eq!(3, diff.deltas.len());
+        let movers: Vec<(&str, i128)> = diff
+            .top_movers
+            .iter()
+            .map(|delta| (delta.key.as_str(), delta.delta))
+            .collect();
+        assert_eq!(vec![(HOTSPOT_3, 300), (HOTSPOT_2, -100)], movers);
+    }
+}
@@ -1,80 +1,134 @@
 use crate::{
-    heartbeats::HeartbeatReward,
-    reward_shares::{
-        get_scheduled_tokens_for_poc, CoverageShares, DataTransferAndPocAllocatedRewardBuckets,
-    },
-    rewarder::boosted_hex_eligibility::BoostedHexEligibility,
+    reward_shares::get_total_scheduled_tokens,
+    reward_totals::Rewards,
+    rewarder::{self, mobile_bone_price},
     speedtests_average::SpeedtestAverages,
     Settings,
 };
-use anyhow::Result;
-use chrono::NaiveDateTime;
-use helium_crypto::PublicKey;
-use helium_proto::services::poc_mobile as proto;
-use mobile_config::boosted_hex_info::BoostedHexes;
+use anyhow::{anyhow, Result};
+use chrono::{DateTime, NaiveDateTime, Utc};
+use coverage_point_calculator::{RuleSet, RULE_SETS};
+use file_store::{
+    file_sink::{self, FileSinkClient},
+    file_source, FileType,
+};
+use futures::{
+    stream::{self, StreamExt},
+    SinkExt,
+};
+use helium_proto::{services::poc_mobile::MobileRewardShare, BoostedHexInfoV1};
+use mobile_config::{
+    boosted_hex_info::{BoostedHexInfo, BoostedHexInfoStream, BoostedHexes},
+    client::{
+        hex_boosting_client::{HexBoostingClient, HexBoostingInfoResolver},
+        CarrierServiceClient, ClientError,
+    },
+};
+use prost::{bytes::Bytes, Message};
 use serde_json::json;
-use std::collections::HashMap;
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+use tokio::{sync::mpsc, task::JoinHandle};
+use tokio_util::codec::LengthDelimitedCodec;
 
-/// Reward a period from the entries in the database
+/// Reward a period from the entries in the database.
+///
+/// Every reward category of the rewarder is computed, producing the same
+/// `MobileRewardShare`s, without writing to the database or the output
+/// bucket. Boosted hexes are read from a snapshot file so a past period can
+/// be reproduced exactly, or fetched from mobile_config.
 #[derive(Debug, clap::Args)]
 pub struct Cmd {
     #[clap(long)]
     start: NaiveDateTime,
     #[clap(long)]
     end: NaiveDateTime,
+    /// Mobile price for the period as supplied by the price oracle
+    #[clap(long)]
+    price: u64,
+    /// Snapshot of the boosted hexes to reward with, a file of
+    /// `BoostedHexInfoV1`s. Default: the boosted hexes of mobile_config
+    #[clap(long)]
+    boosted_hexes: Option<PathBuf>,
+    /// Write the boosted hexes fetched from mobile_config to a snapshot file
+    #[clap(long, conflicts_with = "boosted_hexes")]
+    save_boosted_hexes: Option<PathBuf>,
+    /// Name of the coverage point rule set to reward with. Default: the rule
+    /// set in force for the period
+    #[clap(long)]
+    rule_set: Option<String>,
+    /// Folder to write the reward shares to as a `mobile_reward_share` file,
+    /// which can be compared to the rewarded period with `reward-diff`
+    #[clap(long)]
+    output: Option<PathBuf>,
 }
 
 impl Cmd {
     pub async fn run(self, settings: &Settings) -> Result<()> {
-        let Self { start, end } = self;
-
-        let start = start.and_utc();
-        let end = end.and_utc();
+        let start = self.start.and_utc();
+        let end = self.end.and_utc();
 
         tracing::info!("Rewarding shares from the following time range: {start} to {end}");
         let epoch = start..end;
-        let expected_rewards = get_scheduled_tokens_for_poc(epoch.end - epoch.start);
+        let expected_rewards = get_total_scheduled_tokens(epoch.end - epoch.start);
+        let rule_set = match &self.rule_set {
+            Some(name) => RuleSet::by_name(name).ok_or_else(|| {
+                let names: Vec<_> = RULE_SETS.iter().map(|rule_set| rule_set.name).collect();
+                anyhow!("unknown rule set {name}, expected one of {names:?}")
+            })?,
+            None => RuleSet::for_period(&epoch),
+        };
 
-        let (shutdown_trigger, _shutdown_listener) = triggered::trigger();
         let pool = settings.database.connect(env!("CARGO_PKG_NAME")).await?;
+        let carrier_client = CarrierServiceClient::from_settings(&settings.config_client)?;
+        let boosted_hexes = match &self.boosted_hexes {
+            Some(path) => BoostedHexSnapshot::read(path).await?,
+            None => {
+                let hex_boosting_client =
+                    HexBoostingClient::from_settings(&settings.config_client)?;
+                let snapshot = BoostedHexSnapshot::fetch(&hex_boosting_client).await?;
+                if let Some(path) = &self.save_boosted_hexes {
+                    snapshot.write(path).await?;
+                }
+                snapshot
+            }
+        };
 
-        let heartbeats = HeartbeatReward::validated(&pool, &epoch);
-        let speedtest_averages =
-            SpeedtestAverages::aggregate_epoch_averages(epoch.end, &pool).await?;
-
-        let reward_shares = CoverageShares::new(
+        let (mobile_rewards, written_rewards) = collecting_sink("reward_from_db_reward_shares");
+        let (speedtest_averages, _) = collecting_sink("reward_from_db_speedtest_averages");
+        let (poc_dc_shares, _explanations) = rewarder::write_reward_shares(
             &pool,
-            heartbeats,
+            &carrier_client,
+            &boosted_hexes,
+            &mobile_rewards,
             &speedtest_averages,
-            &BoostedHexes::default(),
-            &BoostedHexEligibility::default(),
             &epoch,
+            Some(rule_set),
+            mobile_bone_price(self.price),
         )
         .await?;
+        // Closing the sinks ends the collecting tasks
+        drop(mobile_rewards);
+        drop(speedtest_averages);
+        let written_rewards = written_rewards.await?;
 
-        let mut total_rewards = 0_u64;
-        let mut owner_rewards = HashMap::<_, u64>::new();
-        let radio_rewards = reward_shares
-            .into_rewards(
-                DataTransferAndPocAllocatedRewardBuckets::new(&epoch),
-                &epoch,
-            )
-            .ok_or(anyhow::anyhow!("no rewardable events"))?
-            .1;
-        for (_reward_amount, reward, _v2) in radio_rewards {
-            if let Some(proto::mobile_reward_share::Reward::RadioReward(proto::RadioReward {
-                hotspot_key,
-                poc_reward,
-                ..
-            })) = reward.reward
-            {
-                total_rewards += poc_reward;
-                *owner_rewards
-                    .entry(PublicKey::try_from(hotspot_key)?)
-                    .or_default() += poc_reward;
-            }
+        let mut rewards = Rewards::default();
+        for share in &written_rewards {
+            rewards.add(MobileRewardShare::decode(share.as_slice())?)?;
         }
-        let rewards: Vec<_> = owner_rewards.into_iter().collect();
+        let totals = rewards.totals();
+        // v1 radio rewards duplicate the v2 ones
+        let total_rewards: u64 = totals
+            .iter()
+            .filter(|(reward_type, _)| **reward_type != "radio")
+            .map(|(_, amount)| amount)
+            .sum();
+        let owner_rewards: Vec<_> = rewards.by_hotspot().into_iter().collect();
+
+        let speedtest_averages =
+            SpeedtestAverages::aggregate_epoch_averages(epoch.end, &pool).await?;
         let mut multiplier_count = HashMap::<_, usize>::new();
         let speedtest_multipliers: Vec<_> = speedtest_averages
             .averages
@@ -86,18 +140,129 @@ impl Cmd {
             })
             .collect();
 
+        let output = match &self.output {
+            Some(dir) => {
+                let path = dir.join(format!(
+                    "{}.{}",
+                    FileType::MobileRewardShare.to_str(),
+                    epoch.end.timestamp_millis()
+                ));
+                write_frames(&path, written_rewards).await?;
+                Some(path)
+            }
+            None => None,
+        };
+
         println!(
             "{}",
             serde_json::to_string_pretty(&json!({
+                "poc_bones_per_reward_share": poc_dc_shares.normal.to_string(),
+                "boosted_poc_bones_per_reward_share": poc_dc_shares.boost.to_string(),
+                "boosted_hexes": boosted_hexes.hexes.len(),
+                "rule_set": rule_set.name,
                 "multiplier_count": multiplier_count,
                 "speedtest_multipliers": speedtest_multipliers,
-                "rewards": rewards,
+                "rewards": owner_rewards,
+                "reward_totals": totals,
                 "total_rewards": total_rewards,
                 "expected_rewards": expected_rewards,
+                "output": output,
             }))?
         );
 
-        shutdown_trigger.trigger();
         Ok(())
     }
 }
+
+/// Boosted hexes rewarded with, read from a snapshot file or fetched from
+/// mobile_config
+#[derive(Clone)]
+struct BoostedHexSnapshot {
+    hexes: Vec<BoostedHexInfo>,
+}
+
+impl BoostedHexSnapshot {
+    async fn fetch(hex_boosting_client: &HexBoostingClient) -> Result<Self> {
+        let boosted_hexes = BoostedHexes::get_all(hex_boosting_client).await?;
+        Ok(Self {
+            hexes: boosted_hexes.hexes.into_values().collect(),
+        })
+    }
+
+    async fn read(path: &Path) -> Result<Self> {
+        let mut hexes = vec![];
+        let mut file_stream = file_source::source([path]);
+        while let Some(msg) = file_stream.next().await {
+            hexes.push(BoostedHexInfo::try_from(BoostedHexInfoV1::decode(msg?)?)?);
+        }
+        Ok(Self { hexes })
+    }
+
+    async fn write(&self, path: &Path) -> Result<()> {
+        let frames = self
+            .hexes
+            .iter()
+            .cloned()
+            .map(|info| BoostedHexInfoV1::try_from(info).map(|proto| proto.encode_to_vec()))
+            .collect::<Result<Vec<_>>>()?;
+        write_frames(path, frames).await
+    }
+}
+
+#[async_trait::async_trait]
+impl HexBoostingInfoResolver for BoostedHexSnapshot {
+    type Error = ClientError;
+
+    async fn stream_boosted_hexes_info(&mut self) -> Result<BoostedHexInfoStream, Self::Error> {
+        Ok(stream::iter(self.hexes.clone()).boxed())
+    }
+
+    /// A snapshot does not record when hexes were modified, all of them
+    /// are returned
+    async fn stream_modified_boosted_hexes_info(
+        &mut self,
+        _timestamp: DateTime<Utc>,
+    ) -> Result<BoostedHexInfoStream, Self::Error> {
+        self.stream_boosted_hexes_info().await
+    }
+}
+
+/// A file sink client acknowledging every message, returning the written
+/// messages once the client is dropped
+fn collecting_sink(metric: &'static str) -> (FileSinkClient, JoinHandle<Vec<Vec<u8>>>) {
+    let (sender, mut receiver) = mpsc::channel(50);
+    let handle = tokio::spawn(async move {
+        let mut written = vec![];
+        while let Some(msg) = receiver.recv().await {
+            match msg {
+                file_sink::Message::Data(on_write_tx, bytes) => {
+                    written.push(bytes);
+                    let _ = on_write_tx.send(Ok(()));
+                }
+                file_sink::Message::Commit(on_commit_tx)
+                | file_sink::Message::Rollback(on_commit_tx) => {
+                    let _ = on_commit_tx.send(Ok(vec![]));
+                }
+                file_sink::Message::Acknowledge(on_ack_tx, _)
+                | file_sink::Message::AwaitCommit(on_ack_tx) => {
+                    let _ = on_ack_tx.send(Ok(()));
+                }
+            }
+        }
+        written
+    });
+    (FileSinkClient::new(sender, metric), handle)
+}
+
+/// Write `frames` to an uncompressed file readable by `file_source`
+async fn write_frames(path: &Path, frames: impl IntoIterator<Item = Vec<u8>>) -> Result<()> {
+    let file = tokio::fs::File::create(path).await?;
+    let mut transport = LengthDelimitedCodec::builder()
+        .max_frame_length(file_sink::MAX_FRAME_LENGTH)
+        .new_write(file);
+    for frame in frames {
+        transport.send(Bytes::from(frame)).await?;
+    }
+    transport.close().await?;
+    Ok(())
+}
@@ -7,6 +7,7 @@ use crate::{
     geofence::Geofence,
     heartbeats::{cbrs::CbrsHeartbeatDaemon, wifi::WifiHeartbeatDaemon},
     radio_threshold::RadioThresholdIngestor,
+    reward_explanation::ExplanationServer,
     rewarder::Rewarder,
     sp_boosted_rewards_bans::ServiceProviderBoostedRewardsBanIngestor,
     speedtests::SpeedtestDaemon,
@@ -101,7 +102,13 @@ impl Cmd {
         let (new_coverage_obj_notifier, new_coverage_obj_notification) =
             new_coverage_object_notification_channel();
 
-        TaskManager::builder()
+        let explanation_server = settings
+            .reward_explanation_listen
+            .map(|listen| ExplanationServer::new(listen, pool.clone()));
+
+        let mut task_manager = TaskManager::builder()
+            .health_endpoint(settings.metrics.health_endpoint)
+            .health_check("db_pool", db_store::health_check(pool.clone()))
             .add_task(file_upload_server)
             .add_task(valid_heartbeats_server)
             .add_task(seniority_updates_server)
@@ -205,8 +212,10 @@ impl Cmd {
                 )
                 .await?,
             )
-            .build()
-            .start()
-            .await
+            .build();
+        if let Some(explanation_server) = explanation_server {
+            task_manager.add(explanation_server);
+        }
+        task_manager.start().await
     }
 }
@@ -0,0 +1,88 @@
+use crate::{
+    rewarder::{self, Rewarder},
+    Settings,
+};
+use anyhow::{bail, Result};
+use chrono::NaiveDateTime;
+use file_store::file_upload;
+use mobile_config::client::{hex_boosting_client::HexBoostingClient, CarrierServiceClient};
+use std::time::Duration;
+use task_manager::{ManagedTask, TaskManager};
+
+/// How long the written files may take to upload once rewarded
+const UPLOAD_TIMEOUT: Duration = Duration::from_secs(5 * 60);
+
+/// Reward a period without clearing the rewarded data from the database or
+/// moving the reward schedule. Reward shares, the reward manifest and
+/// speedtest averages are written to the output bucket with a prefix so a
+/// new version can be compared to production before it goes live.
+#[derive(Debug, clap::Args)]
+pub struct Cmd {
+    /// Start of the period. Default: the next period to be rewarded
+    #[clap(long, requires = "end")]
+    start: Option<NaiveDateTime>,
+    /// End of the period
+    #[clap(long, requires = "start")]
+    end: Option<NaiveDateTime>,
+    /// Prepended to the file types of the written files
+    #[clap(long, default_value = "shadow")]
+    prefix: String,
+}
+
+impl Cmd {
+    pub async fn run(self, settings: &Settings) -> Result<()> {
+        if self.prefix.is_empty()
+            || !self
+                .prefix
+                .chars()
+                .all(|c| c.is_ascii_lowercase() || c == '_')
+        {
+            bail!("prefix must be lowercase letters and underscores");
+        }
+
+        let pool = settings.database.connect(env!("CARGO_PKG_NAME")).await?;
+        let reward_period = match (self.start, self.end) {
+            (Some(start), Some(end)) => start.and_utc()..end.and_utc(),
+            _ => {
+                rewarder::last_rewarded_end_time(&pool).await?
+                    ..rewarder::next_rewarded_end_time(&pool).await?
+            }
+        };
+
+        let (file_upload, file_upload_server) =
+            file_upload::FileUpload::from_settings_tm(&settings.output).await?;
+        let carrier_client = CarrierServiceClient::from_settings(&settings.config_client)?;
+        let hex_boosting_client = HexBoostingClient::from_settings(&settings.config_client)?;
+
+        let (rewarder, sinks) = Rewarder::create_shadow(
+            pool,
+            settings,
+            file_upload,
+            carrier_client,
+            hex_boosting_client,
+            &self.prefix,
+        )
+        .await?;
+
+        let (shutdown_trigger, shutdown) = triggered::trigger();
+        let services = TaskManager::builder()
+            .add_task(file_upload_server.drain_timeout(UPLOAD_TIMEOUT))
+            .add_task(sinks)
+            .build();
+        let reward = async move {
+            let result = rewarder.shadow_reward(&reward_period).await;
+            // Dropping the rewarder lets the sinks and uploader finish
+            drop(rewarder);
+            shutdown_trigger.trigger();
+            result
+        };
+
+        let (written_files, services) =
+            tokio::join!(reward, Box::new(services).start_task(shutdown));
+        services?;
+        for file in written_files? {
+            println!("{file}");
+        }
+        Ok(())
+    }
+}
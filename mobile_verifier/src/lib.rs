@@ -6,7 +6,9 @@ pub mod data_session;
 pub mod geofence;
 pub mod heartbeats;
 pub mod radio_threshold;
+pub mod reward_explanation;
 pub mod reward_shares;
+pub mod reward_totals;
 pub mod rewarder;
 pub mod seniority;
 mod settings;
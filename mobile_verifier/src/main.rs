@@ -1,7 +1,7 @@
 use anyhow::Result;
 use clap::Parser;
 use mobile_verifier::{
-    cli::{reward_from_db, server, verify_disktree},
+    cli::{reward_diff, reward_from_db, server, shadow_reward, verify_disktree},
     Settings,
 };
 use std::path;
@@ -32,6 +32,8 @@ impl Cli {
 pub enum Cmd {
     Server(server::Cmd),
     RewardFromDb(reward_from_db::Cmd),
+    RewardDiff(reward_diff::Cmd),
+    ShadowReward(shadow_reward::Cmd),
     /// Verify a Disktree file for HexBoosting.
     ///
     /// Go through every cell and ensure it's value can be turned into an Assignment.
@@ -44,6 +46,8 @@ impl Cmd {
         match self {
             Self::Server(cmd) => cmd.run(&settings).await,
             Self::RewardFromDb(cmd) => cmd.run(&settings).await,
+            Self::RewardDiff(cmd) => cmd.run().await,
+            Self::ShadowReward(cmd) => cmd.run(&settings).await,
             Self::VerifyDisktree(cmd) => cmd.run(&settings).await,
         }
     }
@@ -0,0 +1,389 @@
+//! Explanations of the poc rewards of a radio.
+//!
+//! For every radio rewarded in a period, the rewarder persists what its
+//! reward was made of: each covered hex with its rank, oracle assignments
+//! and boost, the location trust scores, the speedtests and their tier and
+//! whether the radio was eligible for boosted rewards. Explanations are kept
+//! for the configured retention and served read-only over HTTP:
+//!
+//! - `GET /v1/radios/{hotspot_key}/reward_explanation` answers the
+//!   explanation of the latest rewarded period. Query parameters
+//!   `cbsd_id` select a cbrs radio and `period_end` a period.
+
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    routing::get,
+    Json, Router,
+};
+use chrono::{DateTime, Utc};
+use coverage_point_calculator::SpeedtestTier;
+use file_store::traits::TimestampDecode;
+use futures::{future::LocalBoxFuture, TryFutureExt};
+use helium_crypto::PublicKeyBinary;
+use helium_proto::services::poc_mobile::{
+    self as proto, mobile_reward_share::Reward as ProtoReward, radio_reward_v2,
+    OracleBoostingAssignment,
+};
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+use sqlx::{Pool, Postgres};
+use std::{net::SocketAddr, str::FromStr};
+use task_manager::ManagedTask;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RadioRewardExplanation {
+    pub hotspot_key: String,
+    pub cbsd_id: Option<String>,
+    pub period_start: DateTime<Utc>,
+    pub period_end: DateTime<Utc>,
+    pub base_poc_reward: u64,
+    pub boosted_poc_reward: u64,
+    pub base_coverage_points: Decimal,
+    pub boosted_coverage_points: Decimal,
+    pub base_reward_shares: Decimal,
+    pub boosted_reward_shares: Decimal,
+    pub location_trust_multiplier: Decimal,
+    pub location_trust_scores: Vec<LocationTrustExplanation>,
+    pub speedtest_multiplier: Decimal,
+    /// Tier of the average of the speedtests
+    pub speedtest_tier: String,
+    pub speedtests: Vec<SpeedtestExplanation>,
+    /// Whether the radio was eligible for boosted rewards, or why not
+    pub boosted_hex_eligibility: String,
+    pub hexes: Vec<HexExplanation>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LocationTrustExplanation {
+    pub meters_to_asserted: u64,
+    pub trust_score: Decimal,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SpeedtestExplanation {
+    pub upload_speed_bps: u64,
+    pub download_speed_bps: u64,
+    pub latency_ms: u32,
+    pub timestamp: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HexExplanation {
+    /// Hex in its h3 string representation
+    pub location: String,
+    /// 1-based rank of the radio in the hex
+    pub rank: u32,
+    pub rank_multiplier: Decimal,
+    pub urbanized: String,
+    pub footfall: String,
+    pub landtype: String,
+    pub assignment_multiplier: Decimal,
+    /// Provider boost, 0 when the hex is not boosted or the radio is not
+    /// eligible for boosted rewards
+    pub boosted_multiplier: u32,
+    pub base_coverage_points: Decimal,
+    pub boosted_coverage_points: Decimal,
+}
+
+impl RadioRewardExplanation {
+    /// Explanation of a radio reward share, `None` for other reward shares.
+    /// `speedtest_tier` is the tier the coverage points were calculated with.
+    pub fn from_reward_share(
+        share: &proto::MobileRewardShare,
+        speedtest_tier: SpeedtestTier,
+    ) -> anyhow::Result<Option<Self>> {
+        let Some(ProtoReward::RadioRewardV2(reward)) = &share.reward else {
+            return Ok(None);
+        };
+
+        let speedtest_multiplier = decimal(&reward.speedtest_multiplier);
+        let speedtests = reward
+            .speedtests
+            .iter()
+            .map(|speedtest| {
+                Ok(SpeedtestExplanation {
+                    upload_speed_bps: speedtest.upload_speed_bps,
+                    download_speed_bps: speedtest.download_speed_bps,
+                    latency_ms: speedtest.latency_ms,
+                    timestamp: speedtest.timestamp.to_timestamp()?,
+                })
+            })
+            .collect::<anyhow::Result<_>>()?;
+
+        Ok(Some(Self {
+            hotspot_key: PublicKeyBinary::from(reward.hotspot_key.clone()).to_string(),
+            cbsd_id: Some(reward.cbsd_id.clone()).filter(|cbsd_id| !cbsd_id.is_empty()),
+            period_start: share.start_period.to_timestamp()?,
+            period_end: share.end_period.to_timestamp()?,
+            base_poc_reward: reward.base_poc_reward,
+            boosted_poc_reward: reward.boosted_poc_reward,
+            base_coverage_points: decimal(&reward.base_coverage_points_sum),
+            boosted_coverage_points: decimal(&reward.boosted_coverage_points_sum),
+            base_reward_shares: decimal(&reward.base_reward_shares),
+            boosted_reward_shares: decimal(&reward.boosted_reward_shares),
+            location_trust_multiplier: decimal(&reward.location_trust_score_multiplier),
+            location_trust_scores: reward
+                .location_trust_scores
+                .iter()
+                .map(|score| LocationTrustExplanation {
+                    meters_to_asserted: score.meters_to_asserted,
+                    trust_score: decimal(&score.trust_score),
+                })
+                .collect(),
+            speedtest_multiplier,
+            speedtest_tier: format!("{speedtest_tier:?}").to_lowercase(),
+            speedtests,
+            boosted_hex_eligibility: proto::BoostedHexStatus::try_from(reward.boosted_hex_status)
+                .map(|status| status.as_str_name().to_string())
+                .unwrap_or_else(|_| reward.boosted_hex_status.to_string()),
+            hexes: reward
+                .covered_hexes
+                .iter()
+                .map(HexExplanation::from)
+                .collect(),
+        }))
+    }
+}
+
+impl From<&radio_reward_v2::CoveredHex> for HexExplanation {
+    fn from(hex: &radio_reward_v2::CoveredHex) -> Self {
+        Self {
+            location: format!("{:x}", hex.location),
+            rank: hex.rank,
+            rank_multiplier: decimal(&hex.rank_multiplier),
+            urbanized: assignment(hex.urbanized),
+            footfall: assignment(hex.footfall),
+            landtype: assignment(hex.landtype),
+            assignment_multiplier: decimal(&hex.assignment_multiplier),
+            boosted_multiplier: hex.boosted_multiplier,
+            base_coverage_points: decimal(&hex.base_coverage_points),
+            boosted_coverage_points: decimal(&hex.boosted_coverage_points),
+        }
+    }
+}
+
+fn decimal(value: &Option<helium_proto::Decimal>) -> Decimal {
+    value
+        .as_ref()
+        .and_then(|value| Decimal::from_str(&value.value).ok())
+        .unwrap_or_default()
+}
+
+fn assignment(value: i32) -> String {
+    OracleBoostingAssignment::try_from(value)
+        .map(|assignment| assignment.as_str_name().to_string())
+        .unwrap_or_else(|_| value.to_string())
+}
+
+pub mod db {
+    use super::RadioRewardExplanation;
+    use chrono::{DateTime, Utc};
+    use sqlx::{types::Json, PgExecutor, Postgres, QueryBuilder, Transaction};
+
+    const NUMBER_OF_FIELDS_IN_QUERY: u16 = 5;
+    const EXPLANATIONS_MAX_BATCH_ENTRIES: usize = (u16::MAX / NUMBER_OF_FIELDS_IN_QUERY) as usize;
+
+    pub async fn save(
+        transaction: &mut Transaction<'_, Postgres>,
+        explanations: &[RadioRewardExplanation],
+    ) -> Result<(), sqlx::Error> {
+        for explanations in explanations.chunks(EXPLANATIONS_MAX_BATCH_ENTRIES) {
+            QueryBuilder::new(
+                "INSERT INTO radio_reward_explanations (hotspot_key, cbsd_id, period_start, period_end, explanation)",
+            )
+            .push_values(explanations, |mut b, explanation| {
+                b.push_bind(&explanation.hotspot_key)
+                    .push_bind(explanation.cbsd_id.clone().unwrap_or_default())
+                    .push_bind(explanation.period_start)
+                    .push_bind(explanation.period_end)
+                    .push_bind(Json(explanation));
+            })
+            .push(
+                r#"
+                ON CONFLICT (hotspot_key, cbsd_id, period_end) DO UPDATE SET
+                  period_start = EXCLUDED.period_start,
+                  explanation = EXCLUDED.explanation
+                "#,
+            )
+            .build()
+            .execute(&mut *transaction)
+            .await?;
+        }
+        Ok(())
+    }
+
+    /// The explanation of the period ending at `period_end`, or of the
+    /// latest rewarded period
+    pub async fn get(
+        db: impl PgExecutor<'_>,
+        hotspot_key: &str,
+        cbsd_id: Option<&str>,
+        period_end: Option<DateTime<Utc>>,
+    ) -> Result<Option<RadioRewardExplanation>, sqlx::Error> {
+        let explanation = sqlx::query_scalar::<_, Json<RadioRewardExplanation>>(
+            r#"
+            SELECT explanation FROM radio_reward_explanations
+            WHERE hotspot_key = $1 AND cbsd_id = $2
+              AND ($3::timestamptz IS NULL OR period_end = $3)
+            ORDER BY period_end DESC
+            LIMIT 1
+            "#,
+        )
+        .bind(hotspot_key)
+        .bind(cbsd_id.unwrap_or_default())
+        .bind(period_end)
+        .fetch_optional(db)
+        .await?;
+        Ok(explanation.map(|Json(explanation)| explanation))
+    }
+
+    pub async fn clear_before(
+        transaction: &mut Transaction<'_, Postgres>,
+        timestamp: DateTime<Utc>,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query("DELETE FROM radio_reward_explanations WHERE period_end < $1")
+            .bind(timestamp)
+            .execute(&mut *transaction)
+            .await?;
+        Ok(())
+    }
+}
+
+/// Read-only HTTP endpoint serving reward explanations
+pub struct ExplanationServer {
+    address: SocketAddr,
+    pool: Pool<Postgres>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ExplanationQuery {
+    cbsd_id: Option<String>,
+    period_end: Option<DateTime<Utc>>,
+}
+
+impl ExplanationServer {
+    pub fn new(address: SocketAddr, pool: Pool<Postgres>) -> Self {
+        Self { address, pool }
+    }
+
+    pub async fn run(self, shutdown: triggered::Listener) -> anyhow::Result<()> {
+        let app = Router::new()
+            .route(
+                "/v1/radios/:hotspot_key/reward_explanation",
+                get(reward_explanation),
+            )
+            .with_state(self.pool);
+        let listener = tokio::net::TcpListener::bind(self.address).await?;
+        tracing::info!("reward explanation endpoint listening on {}", self.address);
+        axum::serve(listener, app)
+            .with_graceful_shutdown(shutdown)
+            .await?;
+        Ok(())
+    }
+}
+
+impl ManagedTask for ExplanationServer {
+    fn start_task(
+        self: Box<Self>,
+        shutdown: triggered::Listener,
+    ) -> LocalBoxFuture<'static, anyhow::Result<()>> {
+        let handle = tokio::spawn(self.run(shutdown));
+        Box::pin(
+            handle
+                .map_err(anyhow::Error::from)
+                .and_then(|result| async move { result }),
+        )
+    }
+}
+
+async fn reward_explanation(
+    State(pool): State<Pool<Postgres>>,
+    Path(hotspot_key): Path<String>,
+    Query(query): Query<ExplanationQuery>,
+) -> Result<Json<RadioRewardExplanation>, StatusCode> {
+    match db::get(
+        &pool,
+        &hotspot_key,
+        query.cbsd_id.as_deref(),
+        query.period_end,
+    )
+    .await
+    {
+        Ok(Some(explanation)) => Ok(Json(explanation)),
+        Ok(None) => Err(StatusCode::NOT_FOUND),
+        Err(err) => {
+            tracing::error!(?err, hotspot_key, "failed to fetch reward explanation");
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use file_store::traits::TimestampEncode;
+
+    fn proto_decimal(value: &str) -> Option<helium_proto::Decimal> {
+        Some(helium_proto::Decimal {
+            value: value.to_string(),
+        })
+    }
+
+    #[test]
+    fn explains_radio_reward_v2() {
+        let hotspot_key =
+            PublicKeyBinary::from_str("112NqN2WWMwtK29PMzRby62fDydBJfsCLkCAf392stdok48ovNT6")
+                .unwrap();
+        let start = Utc::now() - chrono::Duration::hours(24);
+        let end = Utc::now();
+        let share = proto::MobileRewardShare {
+            start_period: start.encode_timestamp(),
+            end_period: end.encode_timestamp(),
+            reward: Some(ProtoReward::RadioRewardV2(proto::RadioRewardV2 {
+                hotspot_key: hotspot_key.clone().into(),
+                base_poc_reward: 100,
+                speedtest_multiplier: proto_decimal("0.75"),
+                boosted_hex_status: proto::BoostedHexStatus::RadioThresholdNotMet.into(),
+                covered_hexes: vec![radio_reward_v2::CoveredHex {
+                    location: 0x8a1fb46622dffff,
+                    rank: 2,
+                    rank_multiplier: proto_decimal("0.5"),
+                    ..Default::default()
+                }],
+                ..Default::default()
+            })),
+        };
+
+        let explanation =
+            RadioRewardExplanation::from_reward_share(&share, SpeedtestTier::Acceptable)
+                .unwrap()
+                .expect("radio reward explained");
+
+        assert_eq!(hotspot_key.to_string(), explanation.hotspot_key);
+        assert_eq!(None, explanation.cbsd_id);
+        assert_eq!(end.timestamp(), explanation.period_end.timestamp());
+        assert_eq!(100, explanation.base_poc_reward);
+        assert_eq!("acceptable", explanation.speedtest_tier);
+        assert_eq!(
+            proto::BoostedHexStatus::RadioThresholdNotMet.as_str_name(),
+            explanation.boosted_hex_eligibility
+        );
+        assert_eq!("8a1fb46622dffff", explanation.hexes[0].location);
+        assert_eq!(2, explanation.hexes[0].rank);
+        assert_eq!(Decimal::new(5, 1), explanation.hexes[0].rank_multiplier);
+    }
+
+    #[test]
+    fn ignores_other_reward_shares() {
+        let share = proto::MobileRewardShare {
+            start_period: 0,
+            end_period: 0,
+            reward: Some(ProtoReward::GatewayReward(proto::GatewayReward::default())),
+        };
+        assert_eq!(
+            None,
+            RadioRewardExplanation::from_reward_share(&share, SpeedtestTier::Good).unwrap()
+        );
+    }
+}
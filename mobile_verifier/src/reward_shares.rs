@@ -8,7 +8,7 @@ use crate::{
     subscriber_location::SubscriberValidatedLocations,
 };
 use chrono::{DateTime, Duration, Utc};
-use coverage_point_calculator::SPBoostedRewardEligibility;
+use coverage_point_calculator::{RuleSet, SPBoostedRewardEligibility, SpeedtestTier};
 use file_store::traits::TimestampEncode;
 use futures::{Stream, StreamExt};
 use helium_crypto::PublicKeyBinary;
@@ -471,6 +471,7 @@ struct RadioInfo {
 pub struct CoverageShares {
     coverage_map: coverage_map::CoverageMap,
     radio_infos: HashMap<RadioId, RadioInfo>,
+    rule_set: RuleSet,
 }
 
 impl CoverageShares {
@@ -580,9 +581,16 @@ impl CoverageShares {
         Ok(Self {
             coverage_map,
             radio_infos,
+            rule_set: RuleSet::for_period(reward_period),
         })
     }
 
+    /// Calculate coverage points with `rule_set` instead of the rules in
+    /// force for the reward period
+    pub fn with_rule_set(self, rule_set: RuleSet) -> Self {
+        Self { rule_set, ..self }
+    }
+
     fn coverage_points(
         &self,
         radio_id: &RadioId,
@@ -598,7 +606,8 @@ impl CoverageShares {
             ranked_coverage.to_vec()
         };
 
-        let coverage_points = coverage_point_calculator::CoveragePoints::new(
+        let coverage_points = coverage_point_calculator::CoveragePoints::with_rule_set(
+            self.rule_set,
             radio_info.radio_type,
             radio_info.sp_boosted_reward_eligibility,
             radio_info.speedtests.clone(),
@@ -615,7 +624,14 @@ impl CoverageShares {
         epoch: &'_ Range<DateTime<Utc>>,
     ) -> Option<(
         CalculatedPocRewardShares,
-        impl Iterator<Item = (u64, proto::MobileRewardShare, proto::MobileRewardShare)> + '_,
+        impl Iterator<
+                Item = (
+                    u64,
+                    proto::MobileRewardShare,
+                    proto::MobileRewardShare,
+                    SpeedtestTier,
+                ),
+            > + '_,
     )> {
         struct ProcessedRadio {
             radio_id: RadioId,
@@ -667,6 +683,7 @@ impl CoverageShares {
                     } = radio;
 
                     let poc_reward = rewards_per_share.poc_reward(&points);
+                    let speedtest_tier = points.speedtest_tier;
                     let (mobile_reward_v1, mobile_reward_v2) =
                         coverage_point_to_mobile_reward_share(
                             points,
@@ -677,9 +694,14 @@ impl CoverageShares {
                             seniority.seniority_ts,
                             coverage_obj_uuid,
                         );
-                    (poc_reward, mobile_reward_v1, mobile_reward_v2)
+                    (
+                        poc_reward,
+                        mobile_reward_v1,
+                        mobile_reward_v2,
+                        speedtest_tier,
+                    )
                 })
-                .filter(|(poc_reward, _mobile_reward_v1, _mobile_reward_v2)| *poc_reward > 0),
+                .filter(|(poc_reward, ..)| *poc_reward > 0),
         ))
     }
 
@@ -1534,7 +1556,7 @@ mod test {
         let mut allocated_poc_rewards = 0_u64;
 
         let epoch = (now - Duration::hours(1))..now;
-        for (reward_amount, _mobile_reward_v1, mobile_reward_v2) in CoverageShares::new(
+        for (reward_amount, _, mobile_reward_v2, _) in CoverageShares::new(
             &hex_coverage,
             stream::iter(heartbeat_rewards),
             &speedtest_avgs,
@@ -1714,7 +1736,7 @@ mod test {
 
         let reward_shares = DataTransferAndPocAllocatedRewardBuckets::new_poc_only(&epoch);
 
-        for (_reward_amount, _mobile_reward_v1, mobile_reward_v2) in CoverageShares::new(
+        for (_reward_amount, _, mobile_reward_v2, _) in CoverageShares::new(
             &hex_coverage,
             stream::iter(heartbeat_rewards),
             &speedtest_avgs,
@@ -1847,7 +1869,7 @@ mod test {
         let epoch = (now - duration)..now;
 
         let reward_shares = DataTransferAndPocAllocatedRewardBuckets::new_poc_only(&epoch);
-        for (_reward_amount, _mobile_reward_v1, mobile_reward_v2) in CoverageShares::new(
+        for (_reward_amount, _, mobile_reward_v2, _) in CoverageShares::new(
             &hex_coverage,
             stream::iter(heartbeat_rewards),
             &speedtest_avgs,
@@ -1981,7 +2003,7 @@ mod test {
         let epoch = (now - duration)..now;
 
         let reward_shares = DataTransferAndPocAllocatedRewardBuckets::new_poc_only(&epoch);
-        for (_reward_amount, _mobile_reward_v1, mobile_reward_v2) in CoverageShares::new(
+        for (_reward_amount, _, mobile_reward_v2, _) in CoverageShares::new(
             &hex_coverage,
             stream::iter(heartbeat_rewards),
             &speedtest_avgs,
@@ -2127,12 +2149,13 @@ mod test {
         let coverage_shares = CoverageShares {
             coverage_map,
             radio_infos,
+            rule_set: RuleSet::for_period(&epoch),
         };
 
         let reward_shares = DataTransferAndPocAllocatedRewardBuckets::new_poc_only(&epoch);
         // gw2 does not have enough speedtests for a mulitplier
         let expected_hotspot = gw1;
-        for (_reward_amount, _mobile_reward_v1, mobile_reward_v2) in coverage_shares
+        for (_reward_amount, _, mobile_reward_v2, _) in coverage_shares
             .into_rewards(reward_shares, &epoch)
             .expect("rewards output")
             .1
@@ -2154,6 +2177,7 @@ mod test {
             coverage_map: coverage_map::CoverageMapBuilder::default()
                 .build(&BoostedHexes::default(), epoch.start),
             radio_infos: HashMap::new(),
+            rule_set: RuleSet::for_period(&epoch),
         };
 
         let reward_shares = DataTransferAndPocAllocatedRewardBuckets::new_poc_only(&epoch);
@@ -0,0 +1,191 @@
+//! Totals of the rewards written by a reward run.
+//!
+//! Reward shares are summed by reward type and rewarded entity, radio
+//! rewards by hotspot key and cbsd id, so reward runs can be reported on and
+//! compared.
+
+use anyhow::{Context, Result};
+use file_store::{
+    file_source, reward_manifest::RewardManifest, traits::MsgDecode, FileInfo, FileType,
+};
+use futures::stream::StreamExt;
+use helium_crypto::PublicKey;
+use helium_proto::services::poc_mobile::{
+    mobile_reward_share::Reward, MobileRewardShare, UnallocatedRewardType,
+};
+use helium_proto::ServiceProvider;
+use prost::Message;
+use std::{
+    collections::{BTreeMap, HashMap},
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+
+/// Reward amounts by reward type and entity
+#[derive(Debug, Default)]
+pub struct Rewards {
+    amounts: BTreeMap<&'static str, HashMap<String, u64>>,
+}
+
+impl Rewards {
+    /// Read the reward shares of `paths`, resolving manifests to the files
+    /// they reference
+    pub async fn read(paths: &[PathBuf]) -> Result<Self> {
+        let mut share_files = vec![];
+        for path in paths {
+            if is_manifest(path) {
+                share_files.extend(manifest_files(path).await?);
+            } else {
+                share_files.push(path.clone());
+            }
+        }
+
+        let mut rewards = Self::default();
+        let mut file_stream = file_source::source(&share_files);
+        while let Some(msg) = file_stream.next().await {
+            rewards.add(MobileRewardShare::decode(msg?)?)?;
+        }
+        Ok(rewards)
+    }
+
+    pub fn add(&mut self, share: MobileRewardShare) -> Result<()> {
+        let Some(reward) = share.reward else {
+            return Ok(());
+        };
+        let (reward_type, key, amount) = match reward {
+            Reward::RadioReward(reward) => (
+                "radio",
+                radio_key(reward.hotspot_key, &reward.cbsd_id)?,
+                reward.poc_reward,
+            ),
+            Reward::RadioRewardV2(reward) => (
+                "radio_v2",
+                radio_key(reward.hotspot_key, &reward.cbsd_id)?,
+                reward.base_poc_reward + reward.boosted_poc_reward,
+            ),
+            Reward::GatewayReward(reward) => (
+                "gateway",
+                PublicKey::try_from(reward.hotspot_key)?.to_string(),
+                reward.dc_transfer_reward,
+            ),
+            Reward::SubscriberReward(reward) => (
+                "subscriber",
+                uuid::Uuid::from_slice(&reward.subscriber_id)?.to_string(),
+                reward.discovery_location_amount,
+            ),
+            Reward::ServiceProviderReward(reward) => (
+                "service_provider",
+                ServiceProvider::try_from(reward.service_provider_id)
+                    .map(|sp| sp.as_str_name().to_string())
+                    .unwrap_or_else(|_| reward.service_provider_id.to_string()),
+                reward.amount,
+            ),
+            Reward::UnallocatedReward(reward) => (
+                "unallocated",
+                UnallocatedRewardType::try_from(reward.reward_type)
+                    .map(|reward_type| reward_type.as_str_name().to_string())
+                    .unwrap_or_else(|_| reward.reward_type.to_string()),
+                reward.amount,
+            ),
+        };
+        *self
+            .amounts
+            .entry(reward_type)
+            .or_default()
+            .entry(key)
+            .or_default() += amount;
+        Ok(())
+    }
+
+    /// Amount rewarded per entity, by reward type
+    pub fn amounts(&self) -> &BTreeMap<&'static str, HashMap<String, u64>> {
+        &self.amounts
+    }
+
+    /// Total amount rewarded per reward type
+    pub fn totals(&self) -> BTreeMap<&'static str, u64> {
+        self.amounts
+            .iter()
+            .map(|(reward_type, amounts)| (*reward_type, amounts.values().sum()))
+            .collect()
+    }
+
+    /// Poc rewards of the radios of each hotspot, from the v1 radio rewards
+    pub fn by_hotspot(&self) -> BTreeMap<String, u64> {
+        let mut by_hotspot = BTreeMap::<String, u64>::new();
+        for (key, amount) in self.amounts.get("radio").into_iter().flatten() {
+            let hotspot_key = key
+                .split_once('/')
+                .map_or(key.as_str(), |(hotspot_key, _)| hotspot_key);
+            *by_hotspot.entry(hotspot_key.to_string()).or_default() += amount;
+        }
+        by_hotspot
+    }
+}
+
+fn radio_key(hotspot_key: Vec<u8>, cbsd_id: &str) -> Result<String> {
+    let hotspot_key = PublicKey::try_from(hotspot_key)?;
+    Ok(if cbsd_id.is_empty() {
+        hotspot_key.to_string()
+    } else {
+        format!("{hotspot_key}/{cbsd_id}")
+    })
+}
+
+fn is_manifest(path: &Path) -> bool {
+    path.file_name()
+        .and_then(|name| FileInfo::from_str(&name.to_string_lossy()).ok())
+        .is_some_and(|info| info.prefix.ends_with(FileType::RewardManifest.to_str()))
+}
+
+async fn manifest_files(path: &Path) -> Result<Vec<PathBuf>> {
+    let dir = path.parent().unwrap_or(Path::new("."));
+    let mut files = vec![];
+    let mut manifests = file_source::source([path]);
+    while let Some(msg) = manifests.next().await {
+        let manifest = RewardManifest::decode(msg?)
+            .with_context(|| format!("decoding manifest {}", path.display()))?;
+        files.extend(manifest.written_files.iter().map(|file| dir.join(file)));
+    }
+    Ok(files)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use helium_proto::services::poc_mobile::RadioReward;
+
+    const HOTSPOT_1: &str = "112NqN2WWMwtK29PMzRby62fDydBJfsCLkCAf392stdok48ovNT6";
+    const HOTSPOT_2: &str = "11sctWiP9r5wDJVuDe1Th4XSL2vaawaLLSQF8f8iokAoMAJHxqp";
+
+    fn radio_reward(hotspot: &str, cbsd_id: &str, poc_reward: u64) -> MobileRewardShare {
+        MobileRewardShare {
+            start_period: 0,
+            end_period: 0,
+            reward: Some(Reward::RadioReward(RadioReward {
+                hotspot_key: PublicKey::from_str(hotspot).unwrap().to_vec(),
+                cbsd_id: cbsd_id.to_string(),
+                poc_reward,
+                ..Default::default()
+            })),
+        }
+    }
+
+    #[test]
+    fn sums_radio_rewards_by_hotspot() {
+        let mut rewards = Rewards::default();
+        for share in [
+            radio_reward(HOTSPOT_1, "P27-SCE4255W0001", 10),
+            radio_reward(HOTSPOT_1, "P27-SCE4255W0002", 20),
+            radio_reward(HOTSPOT_2, "", 5),
+        ] {
+            rewards.add(share).unwrap();
+        }
+
+        assert_eq!(BTreeMap::from([("radio", 35)]), rewards.totals());
+        assert_eq!(
+            BTreeMap::from([(HOTSPOT_1.to_string(), 30), (HOTSPOT_2.to_string(), 5)]),
+            rewards.by_hotspot()
+        );
+    }
+}
@@ -3,6 +3,7 @@ use crate::{
     coverage, data_session,
     heartbeats::{self, HeartbeatReward},
     radio_threshold,
+    reward_explanation::{self, RadioRewardExplanation},
     reward_shares::{
         self, CalculatedPocRewardShares, CoverageShares, DataTransferAndPocAllocatedRewardBuckets,
         MapperShares, ServiceProviderShares, TransferRewards,
@@ -13,9 +14,10 @@ use crate::{
 };
 use anyhow::bail;
 use chrono::{DateTime, TimeZone, Utc};
+use coverage_point_calculator::RuleSet;
 use db_store::meta;
 use file_store::{
-    file_sink::{self, FileSinkClient},
+    file_sink::{self, FileManifest, FileSinkClient},
     file_upload::FileUpload,
     traits::TimestampEncode,
     FileType,
@@ -62,6 +64,7 @@ pub struct Rewarder<A, B> {
     reward_manifests: FileSinkClient,
     price_tracker: PriceTracker,
     speedtest_averages: FileSinkClient,
+    reward_explanation_retention: Duration,
 }
 
 impl<A, B> Rewarder<A, B>
@@ -86,6 +89,7 @@ where
             concat!(env!("CARGO_PKG_NAME"), "_radio_reward_shares"),
         )
         .auto_commit(false)
+        .acknowledgements(true)
         .create()
         .await?;
 
@@ -109,6 +113,7 @@ where
             reward_manifests,
             price_tracker,
             speedtests_avg,
+            settings.reward_explanation_retention,
         );
 
         Ok(TaskManager::builder()
@@ -119,6 +124,73 @@ where
             .build())
     }
 
+    /// Rewarder for [`Rewarder::shadow_reward`], writing reward shares,
+    /// manifests and speedtest averages with `prefix` prepended to their
+    /// file types so they are not picked up as production rewards. The
+    /// returned task manager runs the price tracker and sinks it needs.
+    pub async fn create_shadow(
+        pool: Pool<Postgres>,
+        settings: &Settings,
+        file_upload: FileUpload,
+        carrier_service_verifier: A,
+        hex_boosting_info_resolver: B,
+        prefix: &str,
+    ) -> anyhow::Result<(Self, TaskManager)> {
+        let (price_tracker, price_daemon) = PriceTracker::new_tm(&settings.price_tracker).await?;
+
+        let (mobile_rewards, mobile_rewards_server) = file_sink::FileSinkBuilder::new(
+            format!("{prefix}_{}", FileType::MobileRewardShare.to_str()),
+            settings.store_base_path(),
+            file_upload.clone(),
+            concat!(env!("CARGO_PKG_NAME"), "_shadow_radio_reward_shares"),
+        )
+        .auto_commit(false)
+        .acknowledgements(true)
+        .create()
+        .await?;
+
+        let (reward_manifests, reward_manifests_server) = file_sink::FileSinkBuilder::new(
+            format!("{prefix}_{}", FileType::RewardManifest.to_str()),
+            settings.store_base_path(),
+            file_upload.clone(),
+            concat!(env!("CARGO_PKG_NAME"), "_shadow_reward_manifest"),
+        )
+        .auto_commit(false)
+        .create()
+        .await?;
+
+        let (speedtest_averages, speedtest_averages_server) = file_sink::FileSinkBuilder::new(
+            format!("{prefix}_{}", FileType::SpeedtestAvg.to_str()),
+            settings.store_base_path(),
+            file_upload,
+            concat!(env!("CARGO_PKG_NAME"), "_shadow_speedtest_average"),
+        )
+        .auto_commit(false)
+        .create()
+        .await?;
+
+        let rewarder = Rewarder::new(
+            pool,
+            carrier_service_verifier,
+            hex_boosting_info_resolver,
+            settings.reward_period,
+            settings.reward_period_offset,
+            mobile_rewards,
+            reward_manifests,
+            price_tracker,
+            speedtest_averages,
+            settings.reward_explanation_retention,
+        );
+
+        let task_manager = TaskManager::builder()
+            .add_task(price_daemon)
+            .add_task(mobile_rewards_server)
+            .add_task(reward_manifests_server)
+            .add_task(speedtest_averages_server)
+            .build();
+        Ok((rewarder, task_manager))
+    }
+
     #[allow(clippy::too_many_arguments)]
     pub fn new(
         pool: Pool<Postgres>,
@@ -130,6 +202,7 @@ where
         reward_manifests: FileSinkClient,
         price_tracker: PriceTracker,
         speedtest_averages: FileSinkClient,
+        reward_explanation_retention: Duration,
     ) -> Self {
         Self {
             pool,
@@ -141,6 +214,7 @@ where
             reward_manifests,
             price_tracker,
             speedtest_averages,
+            reward_explanation_retention,
         }
     }
 
@@ -250,61 +324,115 @@ where
             reward_period.end
         );
 
-        let mobile_price = self
-            .price_tracker
-            .price(&helium_proto::BlockchainTokenTypeV1::Mobile)
+        let (poc_dc_shares, explanations, written_files) =
+            self.write_rewards(reward_period).await?;
+
+        let mut transaction = self.pool.begin().await?;
+        // clear out the various db tables
+        heartbeats::clear_heartbeats(&mut transaction, &reward_period.start).await?;
+        speedtests::clear_speedtests(&mut transaction, &reward_period.start).await?;
+        data_session::clear_hotspot_data_sessions(&mut transaction, &reward_period.start).await?;
+        coverage::clear_coverage_objects(&mut transaction, &reward_period.start).await?;
+        sp_boosted_rewards_bans::clear_bans(&mut transaction, reward_period.start).await?;
+        // subscriber_location::clear_location_shares(&mut transaction, &reward_period.end).await?;
+
+        let next_reward_period = scheduler.next_reward_period();
+        save_last_rewarded_end_time(&mut transaction, &next_reward_period.start).await?;
+        save_next_rewarded_end_time(&mut transaction, &next_reward_period.end).await?;
+        transaction.commit().await?;
+
+        // now that the db has been purged, safe to write out the manifest
+        self.write_manifest(reward_period, poc_dc_shares, written_files)
             .await?;
+        telemetry::last_rewarded_end_time(next_reward_period.start);
 
-        // Mobile prices are supplied in 10^6, so we must convert them to Decimal
-        let mobile_bone_price = Decimal::from(mobile_price)
-                / dec!(1_000_000)  // Per Mobile token
-                / dec!(1_000_000); // Per Bone
+        // explanations are informational, failing to save them must not
+        // fail rewarding
+        if let Err(err) = self
+            .save_reward_explanations(reward_period, &explanations)
+            .await
+        {
+            tracing::error!(?err, "failed to save reward explanations");
+        }
+        Ok(())
+    }
 
-        // process rewards for poc and data transfer
-        let poc_dc_shares = reward_poc_and_dc(
-            &self.pool,
-            &self.hex_service_client,
-            &self.mobile_rewards,
-            &self.speedtest_averages,
-            reward_period,
-            mobile_bone_price,
+    async fn save_reward_explanations(
+        &self,
+        reward_period: &Range<DateTime<Utc>>,
+        explanations: &[RadioRewardExplanation],
+    ) -> anyhow::Result<()> {
+        let mut transaction = self.pool.begin().await?;
+        reward_explanation::db::save(&mut transaction, explanations).await?;
+        reward_explanation::db::clear_before(
+            &mut transaction,
+            reward_period.end - chrono::Duration::from_std(self.reward_explanation_retention)?,
         )
         .await?;
+        transaction.commit().await?;
+        Ok(())
+    }
 
-        // process rewards for mappers
-        reward_mappers(&self.pool, &self.mobile_rewards, reward_period).await?;
+    /// Reward `reward_period` without clearing the rewarded data from the
+    /// database or moving the reward schedule, so a period can be rewarded
+    /// by a new version and compared to production before it goes live.
+    /// Returns the reward share files referenced by the written manifest.
+    pub async fn shadow_reward(
+        &self,
+        reward_period: &Range<DateTime<Utc>>,
+    ) -> anyhow::Result<FileManifest> {
+        tracing::info!(
+            "Shadow rewarding for period: {} to {}",
+            reward_period.start,
+            reward_period.end
+        );
 
-        // process rewards for service providers
-        reward_service_providers(
+        let (poc_dc_shares, _explanations, written_files) =
+            self.write_rewards(reward_period).await?;
+        self.write_manifest(reward_period, poc_dc_shares, written_files.clone())
+            .await?;
+        Ok(written_files)
+    }
+
+    /// Write and commit the reward shares of every reward category
+    async fn write_rewards(
+        &self,
+        reward_period: &Range<DateTime<Utc>>,
+    ) -> anyhow::Result<(
+        CalculatedPocRewardShares,
+        Vec<RadioRewardExplanation>,
+        FileManifest,
+    )> {
+        let mobile_price = self
+            .price_tracker
+            .price(&helium_proto::BlockchainTokenTypeV1::Mobile)
+            .await?;
+
+        let (poc_dc_shares, explanations) = write_reward_shares(
             &self.pool,
             &self.carrier_client,
+            &self.hex_service_client,
             &self.mobile_rewards,
+            &self.speedtest_averages,
             reward_period,
-            mobile_bone_price,
+            None,
+            mobile_bone_price(mobile_price),
         )
         .await?;
 
-        // process rewards for oracles
-        reward_oracles(&self.mobile_rewards, reward_period).await?;
-
         self.speedtest_averages.commit().await?;
         let written_files = self.mobile_rewards.commit().await?.await??;
+        Ok((poc_dc_shares, explanations, written_files))
+    }
 
-        let mut transaction = self.pool.begin().await?;
-        // clear out the various db tables
-        heartbeats::clear_heartbeats(&mut transaction, &reward_period.start).await?;
-        speedtests::clear_speedtests(&mut transaction, &reward_period.start).await?;
-        data_session::clear_hotspot_data_sessions(&mut transaction, &reward_period.start).await?;
-        coverage::clear_coverage_objects(&mut transaction, &reward_period.start).await?;
-        sp_boosted_rewards_bans::clear_bans(&mut transaction, reward_period.start).await?;
-        // subscriber_location::clear_location_shares(&mut transaction, &reward_period.end).await?;
-
-        let next_reward_period = scheduler.next_reward_period();
-        save_last_rewarded_end_time(&mut transaction, &next_reward_period.start).await?;
-        save_next_rewarded_end_time(&mut transaction, &next_reward_period.end).await?;
-        transaction.commit().await?;
-
-        // now that the db has been purged, safe to write out the manifest
+    /// Write the manifest referencing the reward share files written for
+    /// `reward_period`
+    async fn write_manifest(
+        &self,
+        reward_period: &Range<DateTime<Utc>>,
+        poc_dc_shares: CalculatedPocRewardShares,
+        written_files: FileManifest,
+    ) -> anyhow::Result<()> {
         let reward_data = ManifestMobileRewardData {
             poc_bones_per_reward_share: Some(helium_proto::Decimal {
                 value: poc_dc_shares.normal.to_string(),
@@ -318,7 +446,7 @@ where
                 RewardManifest {
                     start_timestamp: reward_period.start.encode_timestamp(),
                     end_timestamp: reward_period.end.encode_timestamp(),
-                    written_files,
+                    written_files: written_files.clone(),
                     reward_data: Some(MobileRewardData(reward_data)),
                 },
                 [],
@@ -327,7 +455,8 @@ where
             .await??;
 
         self.reward_manifests.commit().await?;
-        telemetry::last_rewarded_end_time(next_reward_period.start);
+        // the reward files are now referenced by a manifest
+        self.mobile_rewards.acknowledge(written_files).await?;
         Ok(())
     }
 }
@@ -350,6 +479,61 @@ where
     }
 }
 
+/// Convert a mobile price as supplied by the price oracle to the price of
+/// a bone
+pub fn mobile_bone_price(mobile_price: u64) -> Decimal {
+    // Mobile prices are supplied in 10^6, so we must convert them to Decimal
+    Decimal::from(mobile_price)
+        / dec!(1_000_000)  // Per Mobile token
+        / dec!(1_000_000) // Per Bone
+}
+
+/// Write the reward shares of every reward category for `reward_period`
+/// without committing them, returning the explanations of the radio rewards.
+/// Coverage points are calculated with `rule_set`, or the rules in force for
+/// the reward period when not given.
+#[allow(clippy::too_many_arguments)]
+pub async fn write_reward_shares(
+    pool: &Pool<Postgres>,
+    carrier_client: &impl CarrierServiceVerifier<Error = ClientError>,
+    hex_service_client: &impl HexBoostingInfoResolver<Error = ClientError>,
+    mobile_rewards: &FileSinkClient,
+    speedtest_avg_sink: &FileSinkClient,
+    reward_period: &Range<DateTime<Utc>>,
+    rule_set: Option<RuleSet>,
+    mobile_bone_price: Decimal,
+) -> anyhow::Result<(CalculatedPocRewardShares, Vec<RadioRewardExplanation>)> {
+    // process rewards for poc and data transfer
+    let poc_dc_rewards = reward_poc_and_dc_with_rule_set(
+        pool,
+        hex_service_client,
+        mobile_rewards,
+        speedtest_avg_sink,
+        reward_period,
+        rule_set.unwrap_or_else(|| RuleSet::for_period(reward_period)),
+        mobile_bone_price,
+    )
+    .await?;
+
+    // process rewards for mappers
+    reward_mappers(pool, mobile_rewards, reward_period).await?;
+
+    // process rewards for service providers
+    reward_service_providers(
+        pool,
+        carrier_client,
+        mobile_rewards,
+        reward_period,
+        mobile_bone_price,
+    )
+    .await?;
+
+    // process rewards for oracles
+    reward_oracles(mobile_rewards, reward_period).await?;
+
+    Ok(poc_dc_rewards)
+}
+
 pub async fn reward_poc_and_dc(
     pool: &Pool<Postgres>,
     hex_service_client: &impl HexBoostingInfoResolver<Error = ClientError>,
@@ -357,7 +541,28 @@ pub async fn reward_poc_and_dc(
     speedtest_avg_sink: &FileSinkClient,
     reward_period: &Range<DateTime<Utc>>,
     mobile_bone_price: Decimal,
-) -> anyhow::Result<CalculatedPocRewardShares> {
+) -> anyhow::Result<(CalculatedPocRewardShares, Vec<RadioRewardExplanation>)> {
+    reward_poc_and_dc_with_rule_set(
+        pool,
+        hex_service_client,
+        mobile_rewards,
+        speedtest_avg_sink,
+        reward_period,
+        RuleSet::for_period(reward_period),
+        mobile_bone_price,
+    )
+    .await
+}
+
+async fn reward_poc_and_dc_with_rule_set(
+    pool: &Pool<Postgres>,
+    hex_service_client: &impl HexBoostingInfoResolver<Error = ClientError>,
+    mobile_rewards: &FileSinkClient,
+    speedtest_avg_sink: &FileSinkClient,
+    reward_period: &Range<DateTime<Utc>>,
+    rule_set: RuleSet,
+    mobile_bone_price: Decimal,
+) -> anyhow::Result<(CalculatedPocRewardShares, Vec<RadioRewardExplanation>)> {
     let mut reward_shares = DataTransferAndPocAllocatedRewardBuckets::new(reward_period);
 
     let transfer_rewards = TransferRewards::from_transfer_sessions(
@@ -385,12 +590,13 @@ pub async fn reward_poc_and_dc(
     .await?;
 
     reward_shares.handle_unallocated_data_transfer(dc_unallocated_amount);
-    let (poc_unallocated_amount, calculated_poc_reward_shares) = reward_poc(
+    let (poc_unallocated_amount, calculated_poc_reward_shares, explanations) = reward_poc(
         pool,
         hex_service_client,
         mobile_rewards,
         speedtest_avg_sink,
         reward_period,
+        rule_set,
         reward_shares,
     )
     .await?;
@@ -408,7 +614,7 @@ pub async fn reward_poc_and_dc(
     )
     .await?;
 
-    Ok(calculated_poc_reward_shares)
+    Ok((calculated_poc_reward_shares, explanations))
 }
 
 async fn reward_poc(
@@ -417,8 +623,13 @@ async fn reward_poc(
     mobile_rewards: &FileSinkClient,
     speedtest_avg_sink: &FileSinkClient,
     reward_period: &Range<DateTime<Utc>>,
+    rule_set: RuleSet,
     reward_shares: DataTransferAndPocAllocatedRewardBuckets,
-) -> anyhow::Result<(Decimal, CalculatedPocRewardShares)> {
+) -> anyhow::Result<(
+    Decimal,
+    CalculatedPocRewardShares,
+    Vec<RadioRewardExplanation>,
+)> {
     let heartbeats = HeartbeatReward::validated(pool, reward_period);
     let speedtest_averages =
         SpeedtestAverages::aggregate_epoch_averages(reward_period.end, pool).await?;
@@ -440,9 +651,11 @@ async fn reward_poc(
         &boosted_hex_eligibility,
         reward_period,
     )
-    .await?;
+    .await?
+    .with_rule_set(rule_set);
 
     let total_poc_rewards = reward_shares.total_poc();
+    let mut explanations = vec![];
 
     let (unallocated_poc_amount, calculated_poc_rewards_per_share) =
         if let Some((calculated_poc_rewards_per_share, mobile_reward_shares)) =
@@ -450,10 +663,18 @@ async fn reward_poc(
         {
             // handle poc reward outputs
             let mut allocated_poc_rewards = 0_u64;
-            for (poc_reward_amount, mobile_reward_share_v1, mobile_reward_share_v2) in
-                mobile_reward_shares
+            for (
+                poc_reward_amount,
+                mobile_reward_share_v1,
+                mobile_reward_share_v2,
+                speedtest_tier,
+            ) in mobile_reward_shares
             {
                 allocated_poc_rewards += poc_reward_amount;
+                explanations.extend(RadioRewardExplanation::from_reward_share(
+                    &mobile_reward_share_v2,
+                    speedtest_tier,
+                )?);
                 mobile_rewards
                     .write(mobile_reward_share_v1, [])
                     .await?
@@ -474,7 +695,11 @@ async fn reward_poc(
             // default unallocated poc reward to the total poc reward
             (total_poc_rewards, CalculatedPocRewardShares::default())
         };
-    Ok((unallocated_poc_amount, calculated_poc_rewards_per_share))
+    Ok((
+        unallocated_poc_amount,
+        calculated_poc_rewards_per_share,
+        explanations,
+    ))
 }
 
 pub async fn reward_dc(
@@ -639,7 +864,7 @@ pub async fn last_rewarded_end_time(db: &Pool<Postgres>) -> db_store::Result<Dat
         .ok_or(db_store::Error::DecodeError)
 }
 
-async fn next_rewarded_end_time(db: &Pool<Postgres>) -> db_store::Result<DateTime<Utc>> {
+pub async fn next_rewarded_end_time(db: &Pool<Postgres>) -> db_store::Result<DateTime<Utc>> {
     Utc.timestamp_opt(meta::fetch(db, "next_rewarded_end_time").await?, 0)
         .single()
         .ok_or(db_store::Error::DecodeError)
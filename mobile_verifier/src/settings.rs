@@ -3,6 +3,7 @@ use config::{Config, ConfigError, Environment, File};
 use humantime_serde::re::humantime;
 use serde::Deserialize;
 use std::{
+    net::SocketAddr,
     path::{Path, PathBuf},
     time::Duration,
 };
@@ -50,6 +51,16 @@ pub struct Settings {
     pub usa_geofence_regions: String,
     #[serde(default = "default_fencing_resolution")]
     pub usa_fencing_resolution: u8,
+    /// Listen address of the read-only reward explanation endpoint. Not
+    /// served when unset
+    #[serde(default)]
+    pub reward_explanation_listen: Option<SocketAddr>,
+    /// How long the reward explanations of a radio are kept
+    #[serde(
+        with = "humantime_serde",
+        default = "default_reward_explanation_retention"
+    )]
+    pub reward_explanation_retention: Duration,
 }
 
 fn default_fencing_resolution() -> u8 {
@@ -81,6 +92,10 @@ fn default_data_sets_poll_duration() -> Duration {
     humantime::parse_duration("30 minutes").unwrap()
 }
 
+fn default_reward_explanation_retention() -> Duration {
+    humantime::parse_duration("30 days").unwrap()
+}
+
 impl Settings {
     /// Load Settings from a given path. Settings are loaded from a given
     /// optional path and can be overriden with environment variables.
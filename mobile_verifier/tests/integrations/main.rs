@@ -5,9 +5,11 @@ mod heartbeats;
 mod hex_boosting;
 mod last_location;
 mod modeled_coverage;
+mod reward_explanations;
 mod rewarder_mappers;
 mod rewarder_oracles;
 mod rewarder_poc_dc;
 mod rewarder_sp_rewards;
 mod seniority;
+mod shadow_reward;
 mod speedtests;
@@ -0,0 +1,141 @@
+use chrono::{DateTime, Duration, Utc};
+use mobile_verifier::reward_explanation::{db, RadioRewardExplanation};
+use rust_decimal_macros::dec;
+use sqlx::PgPool;
+
+const HOTSPOT_KEY: &str = "11eX55faMbqZB7jzN4p67m6w7ScPMH6ubnvCjCPLh72J49PaJEL";
+
+fn explanation(
+    cbsd_id: Option<&str>,
+    period_end: DateTime<Utc>,
+    base_poc_reward: u64,
+) -> RadioRewardExplanation {
+    RadioRewardExplanation {
+        hotspot_key: HOTSPOT_KEY.to_string(),
+        cbsd_id: cbsd_id.map(str::to_string),
+        period_start: period_end - Duration::hours(24),
+        period_end,
+        base_poc_reward,
+        boosted_poc_reward: 0,
+        base_coverage_points: dec!(400),
+        boosted_coverage_points: dec!(0),
+        base_reward_shares: dec!(400),
+        boosted_reward_shares: dec!(0),
+        location_trust_multiplier: dec!(1),
+        location_trust_scores: vec![],
+        speedtest_multiplier: dec!(1),
+        speedtest_tier: "good".to_string(),
+        speedtests: vec![],
+        boosted_hex_eligibility: "Eligible".to_string(),
+        hexes: vec![],
+    }
+}
+
+fn period_end() -> DateTime<Utc> {
+    "2024-07-01 00:00:00.000000000 UTC".parse().unwrap()
+}
+
+#[sqlx::test]
+async fn save_replaces_explanation_of_same_period(pool: PgPool) -> anyhow::Result<()> {
+    let mut transaction = pool.begin().await?;
+    db::save(&mut transaction, &[explanation(None, period_end(), 10)]).await?;
+    db::save(&mut transaction, &[explanation(None, period_end(), 20)]).await?;
+    transaction.commit().await?;
+
+    let count: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM radio_reward_explanations")
+        .fetch_one(&pool)
+        .await?;
+    assert_eq!(1, count);
+
+    let saved = db::get(&pool, HOTSPOT_KEY, None, None).await?;
+    assert_eq!(Some(explanation(None, period_end(), 20)), saved);
+
+    Ok(())
+}
+
+#[sqlx::test]
+async fn get_returns_latest_period_unless_one_is_requested(pool: PgPool) -> anyhow::Result<()> {
+    let previous_period_end = period_end() - Duration::hours(24);
+    let mut transaction = pool.begin().await?;
+    db::save(
+        &mut transaction,
+        &[
+            explanation(None, previous_period_end, 10),
+            explanation(None, period_end(), 20),
+        ],
+    )
+    .await?;
+    transaction.commit().await?;
+
+    let latest = db::get(&pool, HOTSPOT_KEY, None, None).await?;
+    assert_eq!(Some(explanation(None, period_end(), 20)), latest);
+
+    let previous = db::get(&pool, HOTSPOT_KEY, None, Some(previous_period_end)).await?;
+    assert_eq!(Some(explanation(None, previous_period_end, 10)), previous);
+
+    let missing = db::get(
+        &pool,
+        HOTSPOT_KEY,
+        None,
+        Some(period_end() + Duration::hours(24)),
+    )
+    .await?;
+    assert_eq!(None, missing);
+
+    Ok(())
+}
+
+#[sqlx::test]
+async fn get_filters_by_cbsd_id(pool: PgPool) -> anyhow::Result<()> {
+    let mut transaction = pool.begin().await?;
+    db::save(
+        &mut transaction,
+        &[
+            explanation(None, period_end(), 10),
+            explanation(Some("P27-SCE4255W120200039521"), period_end(), 20),
+        ],
+    )
+    .await?;
+    transaction.commit().await?;
+
+    let wifi = db::get(&pool, HOTSPOT_KEY, None, None).await?;
+    assert_eq!(Some(explanation(None, period_end(), 10)), wifi);
+
+    let cbrs = db::get(&pool, HOTSPOT_KEY, Some("P27-SCE4255W120200039521"), None).await?;
+    assert_eq!(
+        Some(explanation(
+            Some("P27-SCE4255W120200039521"),
+            period_end(),
+            20
+        )),
+        cbrs
+    );
+
+    let unknown = db::get(&pool, HOTSPOT_KEY, Some("unknown"), None).await?;
+    assert_eq!(None, unknown);
+
+    Ok(())
+}
+
+#[sqlx::test]
+async fn clear_before_removes_older_periods(pool: PgPool) -> anyhow::Result<()> {
+    let previous_period_end = period_end() - Duration::hours(24);
+    let mut transaction = pool.begin().await?;
+    db::save(
+        &mut transaction,
+        &[
+            explanation(None, previous_period_end, 10),
+            explanation(None, period_end(), 20),
+        ],
+    )
+    .await?;
+    db::clear_before(&mut transaction, period_end()).await?;
+    transaction.commit().await?;
+
+    let previous = db::get(&pool, HOTSPOT_KEY, None, Some(previous_period_end)).await?;
+    assert_eq!(None, previous);
+    let latest = db::get(&pool, HOTSPOT_KEY, None, None).await?;
+    assert_eq!(Some(explanation(None, period_end(), 20)), latest);
+
+    Ok(())
+}
@@ -24,7 +24,7 @@ use uuid::Uuid;
 const HOTSPOT_1: &str = "112NqN2WWMwtK29PMzRby62fDydBJfsCLkCAf392stdok48ovNT6";
 const HOTSPOT_2: &str = "11uJHS2YaEWJqgqC7yza9uvSmpv5FWoMQXiP8WbxBGgNUmifUJf";
 const HOTSPOT_3: &str = "112E7TxoNHV46M6tiPA8N1MkeMeQxc9ztb4JQLXBVAAUfq1kJLoF";
-const PAYER_1: &str = "11eX55faMbqZB7jzN4p67m6w7ScPMH6ubnvCjCPLh72J49PaJEL";
+pub const PAYER_1: &str = "11eX55faMbqZB7jzN4p67m6w7ScPMH6ubnvCjCPLh72J49PaJEL";
 
 #[sqlx::test]
 async fn test_poc_and_dc_rewards(pool: PgPool) -> anyhow::Result<()> {
@@ -161,7 +161,7 @@ async fn receive_expected_rewards(
     Ok((poc_rewards, dc_rewards, unallocated_poc_reward))
 }
 
-async fn seed_heartbeats(
+pub async fn seed_heartbeats(
     ts: DateTime<Utc>,
     txn: &mut Transaction<'_, Postgres>,
 ) -> anyhow::Result<()> {
@@ -264,7 +264,7 @@ async fn seed_heartbeats(
     Ok(())
 }
 
-async fn update_assignments(pool: &PgPool) -> anyhow::Result<()> {
+pub async fn update_assignments(pool: &PgPool) -> anyhow::Result<()> {
     let _ = common::set_unassigned_oracle_boosting_assignments(
         pool,
         &common::mock_hex_boost_data_default(),
@@ -273,7 +273,7 @@ async fn update_assignments(pool: &PgPool) -> anyhow::Result<()> {
     Ok(())
 }
 
-async fn seed_speedtests(
+pub async fn seed_speedtests(
     ts: DateTime<Utc>,
     txn: &mut Transaction<'_, Postgres>,
 ) -> anyhow::Result<()> {
@@ -312,7 +312,7 @@ async fn seed_speedtests(
     Ok(())
 }
 
-async fn seed_data_sessions(
+pub async fn seed_data_sessions(
     ts: DateTime<Utc>,
     txn: &mut Transaction<'_, Postgres>,
 ) -> anyhow::Result<()> {
@@ -19,7 +19,7 @@ const HOTSPOT_1: &str = "112NqN2WWMwtK29PMzRby62fDydBJfsCLkCAf392stdok48ovNT6";
 const HOTSPOT_2: &str = "11eX55faMbqZB7jzN4p67m6w7ScPMH6ubnvCjCPLh72J49PaJEL";
 const PAYER_1: &str = "11uJHS2YaEWJqgqC7yza9uvSmpv5FWoMQXiP8WbxBGgNUmifUJf";
 const PAYER_2: &str = "11sctWiP9r5wDJVuDe1Th4XSL2vaawaLLSQF8f8iokAoMAJHxqp";
-const SP_1: &str = "Helium Mobile";
+pub const SP_1: &str = "Helium Mobile";
 
 pub type ValidSpMap = HashMap<String, String>;
 
@@ -29,7 +29,7 @@ pub struct MockCarrierServiceClient {
 }
 
 impl MockCarrierServiceClient {
-    fn new(valid_sps: ValidSpMap) -> Self {
+    pub fn new(valid_sps: ValidSpMap) -> Self {
         Self { valid_sps }
     }
 }
@@ -0,0 +1,191 @@
+use std::{collections::HashMap, io::Write, path::Path};
+
+use chrono::{DateTime, Duration as ChronoDuration, Utc};
+use db_store::meta;
+use file_store::{
+    file_upload::{self, FileUpload},
+    FileInfo, FileType,
+};
+use flate2::{write::GzEncoder, Compression};
+use helium_proto::{BlockchainTokenTypeV1, Message, PriceReportV1};
+use mobile_verifier::{rewarder::Rewarder, Settings};
+use sqlx::PgPool;
+use task_manager::ManagedTask;
+use tempfile::TempDir;
+
+use crate::{
+    common::MockHexBoostingClient,
+    rewarder_poc_dc::{
+        seed_data_sessions, seed_heartbeats, seed_speedtests, update_assignments, PAYER_1,
+    },
+    rewarder_sp_rewards::{MockCarrierServiceClient, SP_1},
+};
+
+const TABLES: &[&str] = &[
+    "cbrs_heartbeats",
+    "wifi_heartbeats",
+    "speedtests",
+    "coverage_objects",
+    "hexes",
+    "hotspot_data_transfer_sessions",
+    "sp_boosted_rewards_bans",
+];
+
+#[sqlx::test]
+async fn shadow_reward_leaves_rewarded_data_and_schedule(pool: PgPool) -> anyhow::Result<()> {
+    let tmp_dir = TempDir::new()?;
+    write_price_report(tmp_dir.path())?;
+    let settings = settings(tmp_dir.path())?;
+
+    let now = Utc::now();
+    let epoch = (now - ChronoDuration::hours(24))..now;
+
+    // seed all the things a production reward would clear
+    let mut txn = pool.begin().await?;
+    seed_heartbeats(epoch.start, &mut txn).await?;
+    seed_speedtests(epoch.end, &mut txn).await?;
+    seed_data_sessions(epoch.start, &mut txn).await?;
+    txn.commit().await?;
+    update_assignments(&pool).await?;
+    seed_expired_ban(&pool, epoch.start).await?;
+    meta::store(&pool, "last_rewarded_end_time", epoch.start.timestamp()).await?;
+    meta::store(&pool, "next_rewarded_end_time", epoch.end.timestamp()).await?;
+
+    let before = row_counts(&pool).await?;
+    assert!(before.iter().all(|(_, count)| *count > 0));
+
+    let (upload_tx, mut upload_rx) = file_upload::message_channel();
+    let carrier_client =
+        MockCarrierServiceClient::new(HashMap::from([(PAYER_1.to_string(), SP_1.to_string())]));
+    let (rewarder, sinks) = Rewarder::create_shadow(
+        pool.clone(),
+        &settings,
+        FileUpload {
+            sender: upload_tx,
+            compression: Default::default(),
+        },
+        carrier_client,
+        MockHexBoostingClient::new(vec![]),
+        "shadow",
+    )
+    .await?;
+
+    let (shutdown_trigger, shutdown) = triggered::trigger();
+    let reward_period = epoch.clone();
+    let reward = async move {
+        let result = rewarder.shadow_reward(&reward_period).await;
+        // Dropping the rewarder lets the sinks finish
+        drop(rewarder);
+        shutdown_trigger.trigger();
+        result
+    };
+    let (written_files, sinks) = tokio::join!(reward, Box::new(sinks).start_task(shutdown));
+    sinks?;
+
+    // Only prefixed files are written
+    let written_files = written_files?;
+    assert!(!written_files.is_empty());
+    assert!(written_files.iter().all(|file| file.starts_with("shadow_")));
+
+    let mut uploaded = vec![];
+    while let Ok(request) = upload_rx.try_recv() {
+        uploaded.push(
+            request
+                .path
+                .file_name()
+                .expect("uploaded file name")
+                .to_string_lossy()
+                .to_string(),
+        );
+    }
+    assert!(!uploaded.is_empty());
+    assert!(
+        uploaded.iter().all(|file| file.starts_with("shadow_")),
+        "unexpected uploads: {uploaded:?}"
+    );
+
+    // Nothing rewarded is cleared and the schedule does not move
+    assert_eq!(before, row_counts(&pool).await?);
+    assert_eq!(
+        epoch.start.timestamp(),
+        meta::fetch::<i64>(&pool, "last_rewarded_end_time").await?
+    );
+    assert_eq!(
+        epoch.end.timestamp(),
+        meta::fetch::<i64>(&pool, "next_rewarded_end_time").await?
+    );
+
+    Ok(())
+}
+
+fn settings(dir: &Path) -> anyhow::Result<Settings> {
+    Ok(serde_json::from_value(serde_json::json!({
+        "cache": dir.join("cache"),
+        "database": { "max_connections": 1 },
+        "ingest": { "bucket": "ingest" },
+        "data_transfer_ingest": { "bucket": "data_transfer_ingest" },
+        "output": { "bucket": "output" },
+        "data_sets": { "bucket": "data_sets" },
+        "metrics": {},
+        "price_tracker": {
+            "price_duration_minutes": 60,
+            "file_store": { "bucket": "price", "backend": "local", "local_root": dir },
+        },
+        "config_client": {
+            "url": "http://localhost:6080",
+            "signing_keypair": "",
+            "config_pubkey": "",
+        },
+        "data_sets_directory": dir.join("data_sets"),
+        "usa_and_mexico_geofence_regions": "",
+        "usa_geofence_regions": "",
+    }))?)
+}
+
+/// Write a recent mobile price to the local price bucket in `dir`
+fn write_price_report(dir: &Path) -> anyhow::Result<()> {
+    let now = Utc::now();
+    let report = PriceReportV1 {
+        price: 1_000_000,
+        timestamp: now.timestamp() as u64,
+        token_type: BlockchainTokenTypeV1::Mobile.into(),
+    }
+    .encode_to_vec();
+
+    let mut encoder = GzEncoder::new(vec![], Compression::default());
+    encoder.write_all(&(report.len() as u32).to_be_bytes())?;
+    encoder.write_all(&report)?;
+
+    let bucket = dir.join("price");
+    std::fs::create_dir_all(&bucket)?;
+    let key = FileInfo::from((FileType::PriceReport, now)).key;
+    std::fs::write(bucket.join(key), encoder.finish()?)?;
+    Ok(())
+}
+
+/// A ban that expired long enough ago to be cleared by a production reward
+async fn seed_expired_ban(pool: &PgPool, before: DateTime<Utc>) -> anyhow::Result<()> {
+    sqlx::query(
+        r#"
+            INSERT INTO sp_boosted_rewards_bans (radio_type, radio_key, received_timestamp, until)
+            VALUES ('wifi', $1, $2, $3)
+        "#,
+    )
+    .bind(PAYER_1)
+    .bind(before - ChronoDuration::days(60))
+    .bind(before - ChronoDuration::days(30))
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+async fn row_counts(pool: &PgPool) -> anyhow::Result<Vec<(&'static str, i64)>> {
+    let mut counts = vec![];
+    for table in TABLES {
+        let count: i64 = sqlx::query_scalar(&format!("SELECT COUNT(*) FROM {table}"))
+            .fetch_one(pool)
+            .await?;
+        counts.push((*table, count));
+    }
+    Ok(counts)
+}
@@ -4,7 +4,7 @@ use file_store::{file_sink, file_upload, FileType};
 use futures_util::TryFutureExt;
 use poc_entropy::{entropy_generator::EntropyGenerator, server::ApiServer, Settings};
 use std::{net::SocketAddr, path, time::Duration};
-use tokio::{self, signal};
+use task_manager::TaskManager;
 
 const ENTROPY_SINK_ROLL_SECS: u64 = 2 * 60;
 
@@ -51,16 +51,6 @@ impl Server {
         // Install the prometheus metrics exporter
         poc_metrics::start_metrics(&settings.metrics)?;
 
-        // configure shutdown trigger
-        let (shutdown_trigger, shutdown) = triggered::trigger();
-        let mut sigterm = signal::unix::signal(signal::unix::SignalKind::terminate())?;
-        tokio::spawn(async move {
-            tokio::select! {
-                _ = sigterm.recv() => shutdown_trigger.trigger(),
-                _ = signal::ctrl_c() => shutdown_trigger.trigger(),
-            }
-        });
-
         // Initialize uploader
         let store_base_path = path::Path::new(&settings.cache);
 
@@ -86,19 +76,20 @@ impl Server {
 
         tracing::info!("api listening on {}", api_server.socket_addr);
 
-        tokio::try_join!(
-            api_server.run(&shutdown),
+        let mut task_manager = TaskManager::new();
+        task_manager.serve_health(settings.metrics.health_endpoint);
+        task_manager.add(file_upload_server);
+        task_manager.add(entropy_sink_server);
+        task_manager.add(move |shutdown: triggered::Listener| async move {
             entropy_generator
                 .run(entropy_sink, &shutdown)
-                .map_err(Error::from),
-            entropy_sink_server
-                .run(shutdown.clone())
-                .map_err(Error::from),
-            file_upload_server
-                .run(shutdown.clone())
-                .map_err(Error::from),
-        )
-        .map(|_| ())
+                .map_err(Error::from)
+                .await
+        });
+        task_manager.add(move |shutdown: triggered::Listener| async move {
+            api_server.run(&shutdown).await
+        });
+        task_manager.start().await
     }
 }
 
@@ -92,6 +92,7 @@ impl Server {
         .await?;
 
         let mut task_manager = TaskManager::new();
+        task_manager.serve_health(settings.metrics.health_endpoint);
         task_manager.add(file_upload_server);
         task_manager.add(price_sink_server);
 
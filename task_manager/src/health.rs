@@ -0,0 +1,298 @@
+//! Liveness and readiness of the tasks run by a [`TaskManager`].
+//!
+//! A `TaskManager` records the state of each task it runs in its `Health`.
+//! Components add checks of their own, like the backlog of a file sink or
+//! the state of a database pool. A [`HealthServer`] exposes the report over
+//! HTTP for orchestrators to probe:
+//!
+//! - `GET /health` answers 200 unless a task failed
+//! - `GET /ready` answers 200 once tasks were started, none failed and all
+//!   checks pass. A task that finished cleanly, like a one-off startup
+//!   task, does not make the service unready.
+//!
+//! Any other status is 503, both routes return the full report as JSON.
+//!
+//! [`TaskManager`]: crate::TaskManager
+
+use crate::ManagedTask;
+use axum::{extract::State, http::StatusCode, routing::get, Json, Router};
+use futures::{future::LocalBoxFuture, TryFutureExt};
+use serde::Serialize;
+use std::{
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+};
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "state", rename_all = "snake_case")]
+pub enum TaskState {
+    Running,
+    Stopped,
+    Failed { error: String },
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TaskStatus {
+    pub name: String,
+    #[serde(flatten)]
+    pub state: TaskState,
+}
+
+/// Result of a health check
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct CheckStatus {
+    pub healthy: bool,
+    pub detail: String,
+}
+
+impl CheckStatus {
+    pub fn healthy(detail: impl Into<String>) -> Self {
+        Self {
+            healthy: true,
+            detail: detail.into(),
+        }
+    }
+
+    pub fn unhealthy(detail: impl Into<String>) -> Self {
+        Self {
+            healthy: false,
+            detail: detail.into(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct NamedCheck {
+    pub name: String,
+    #[serde(flatten)]
+    pub status: CheckStatus,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Report {
+    /// No task failed
+    pub live: bool,
+    /// Tasks were started, none failed and all checks pass
+    pub ready: bool,
+    pub tasks: Vec<TaskStatus>,
+    pub checks: Vec<NamedCheck>,
+}
+
+/// A check evaluated on every report. Checks are called while a report is
+/// built and should not block.
+pub type Check = Box<dyn Fn() -> CheckStatus + Send + Sync>;
+
+#[derive(Default)]
+struct Inner {
+    tasks: Mutex<Vec<TaskStatus>>,
+    checks: Mutex<Vec<(String, Check)>>,
+    /// Health of nested task managers, included in the report
+    nested: Mutex<Vec<Health>>,
+}
+
+/// Shared health state of the tasks of a `TaskManager` and any additional
+/// checks
+#[derive(Clone, Default)]
+pub struct Health {
+    inner: Arc<Inner>,
+}
+
+impl std::fmt::Debug for Health {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Health").finish_non_exhaustive()
+    }
+}
+
+impl Health {
+    pub fn add_check<F>(&self, name: impl Into<String>, check: F)
+    where
+        F: Fn() -> CheckStatus + Send + Sync + 'static,
+    {
+        self.add_boxed_check(name.into(), Box::new(check));
+    }
+
+    fn add_boxed_check(&self, name: String, check: Check) {
+        self.inner
+            .checks
+            .lock()
+            .expect("health checks poisoned")
+            .push((name, check));
+    }
+
+    /// Track a task started under `name`, returning its id
+    pub(crate) fn start_task(&self, name: String, check: Option<Check>) -> usize {
+        if let Some(check) = check {
+            self.add_boxed_check(name.clone(), check);
+        }
+        let mut tasks = self.inner.tasks.lock().expect("health tasks poisoned");
+        tasks.push(TaskStatus {
+            name,
+            state: TaskState::Running,
+        });
+        tasks.len() - 1
+    }
+
+    pub(crate) fn add_nested(&self, health: Health) {
+        self.inner
+            .nested
+            .lock()
+            .expect("nested health poisoned")
+            .push(health);
+    }
+
+    pub(crate) fn set_state(&self, id: usize, state: TaskState) {
+        if let Some(task) = self
+            .inner
+            .tasks
+            .lock()
+            .expect("health tasks poisoned")
+            .get_mut(id)
+        {
+            task.state = state;
+        }
+    }
+
+    pub fn report(&self) -> Report {
+        let mut tasks = vec![];
+        let mut checks = vec![];
+        self.collect(&mut tasks, &mut checks);
+
+        let live = !tasks
+            .iter()
+            .any(|task| matches!(task.state, TaskState::Failed { .. }));
+        let ready = !tasks.is_empty() && live && checks.iter().all(|check| check.status.healthy);
+        Report {
+            live,
+            ready,
+            tasks,
+            checks,
+        }
+    }
+
+    fn collect(&self, tasks: &mut Vec<TaskStatus>, checks: &mut Vec<NamedCheck>) {
+        tasks.extend(
+            self.inner
+                .tasks
+                .lock()
+                .expect("health tasks poisoned")
+                .iter()
+                .cloned(),
+        );
+        checks.extend(
+            self.inner
+                .checks
+                .lock()
+                .expect("health checks poisoned")
+                .iter()
+                .map(|(name, check)| NamedCheck {
+                    name: name.clone(),
+                    status: check(),
+                }),
+        );
+        let nested = self
+            .inner
+            .nested
+            .lock()
+            .expect("nested health poisoned")
+            .clone();
+        for health in nested {
+            health.collect(tasks, checks);
+        }
+    }
+}
+
+/// HTTP endpoint serving the health report
+pub struct HealthServer {
+    address: SocketAddr,
+    health: Health,
+}
+
+impl HealthServer {
+    pub fn new(address: SocketAddr, health: Health) -> Self {
+        Self { address, health }
+    }
+
+    pub async fn run(self, shutdown: triggered::Listener) -> anyhow::Result<()> {
+        let app = Router::new()
+            .route("/health", get(live))
+            .route("/ready", get(ready))
+            .with_state(self.health);
+        let listener = tokio::net::TcpListener::bind(self.address).await?;
+        tracing::info!("health endpoint listening on {}", self.address);
+        axum::serve(listener, app)
+            .with_graceful_shutdown(shutdown)
+            .await?;
+        Ok(())
+    }
+}
+
+impl ManagedTask for HealthServer {
+    fn start_task(
+        self: Box<Self>,
+        shutdown: triggered::Listener,
+    ) -> LocalBoxFuture<'static, anyhow::Result<()>> {
+        let handle = tokio::spawn(self.run(shutdown));
+        Box::pin(
+            handle
+                .map_err(anyhow::Error::from)
+                .and_then(|result| async move { result }),
+        )
+    }
+
+    fn name(&self) -> String {
+        "health_server".to_string()
+    }
+}
+
+async fn live(State(health): State<Health>) -> (StatusCode, Json<Report>) {
+    let report = health.report();
+    (status(report.live), Json(report))
+}
+
+async fn ready(State(health): State<Health>) -> (StatusCode, Json<Report>) {
+    let report = health.report();
+    (status(report.ready), Json(report))
+}
+
+fn status(ok: bool) -> StatusCode {
+    if ok {
+        StatusCode::OK
+    } else {
+        StatusCode::SERVICE_UNAVAILABLE
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ready_once_tasks_run_and_checks_pass() {
+        let health = Health::default();
+        assert!(!health.report().ready);
+
+        let id = health.start_task("sink".to_string(), None);
+        let report = health.report();
+        assert!(report.live);
+        assert!(report.ready);
+
+        // A task that finished cleanly does not make the service unready
+        let finished = health.start_task("migrations".to_string(), None);
+        health.set_state(finished, TaskState::Stopped);
+        assert!(health.report().ready);
+
+        health.add_check("backlog", || CheckStatus::unhealthy("100 messages queued"));
+        let report = health.report();
+        assert!(report.live);
+        assert!(!report.ready);
+        assert_eq!("backlog", report.checks[0].name);
+
+        health.set_state(
+            id,
+            TaskState::Failed {
+                error: "disk full".to_string(),
+            },
+        );
+        assert!(!health.report().live);
+    }
+}
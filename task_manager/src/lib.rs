@@ -1,9 +1,11 @@
+pub mod health;
 mod select_all;
 
-use std::pin::pin;
+use std::{net::SocketAddr, pin::pin};
 
 use crate::select_all::select_all;
 use futures::{future::LocalBoxFuture, Future, FutureExt, StreamExt};
+use health::{Check, CheckStatus, Health, HealthServer, TaskState};
 use tokio::signal;
 
 pub trait ManagedTask {
@@ -11,10 +13,26 @@ pub trait ManagedTask {
         self: Box<Self>,
         shutdown: triggered::Listener,
     ) -> LocalBoxFuture<'static, anyhow::Result<()>>;
+
+    /// Name of the task in the health report
+    fn name(&self) -> String {
+        std::any::type_name::<Self>().to_string()
+    }
+
+    /// Check reported along with the state of the task while it runs
+    fn health_check(&self) -> Option<Check> {
+        None
+    }
+
+    /// Health of the tasks run by this task, for nested task managers
+    fn nested_health(&self) -> Option<Health> {
+        None
+    }
 }
 
 pub struct TaskManager {
     tasks: Vec<Box<dyn ManagedTask>>,
+    health: Health,
 }
 
 impl ManagedTask for TaskManager {
@@ -24,10 +42,20 @@ impl ManagedTask for TaskManager {
     ) -> LocalBoxFuture<'static, anyhow::Result<()>> {
         Box::pin(self.do_start(Box::pin(shutdown)))
     }
+
+    fn name(&self) -> String {
+        "task_manager".to_string()
+    }
+
+    fn nested_health(&self) -> Option<Health> {
+        Some(self.health.clone())
+    }
 }
 
 pub struct TaskManagerBuilder {
     tasks: Vec<Box<dyn ManagedTask>>,
+    health: Health,
+    health_endpoint: Option<SocketAddr>,
 }
 
 struct StoppableLocalFuture {
@@ -67,17 +95,36 @@ impl Default for TaskManager {
 
 impl TaskManager {
     pub fn new() -> Self {
-        Self { tasks: Vec::new() }
+        Self {
+            tasks: Vec::new(),
+            health: Health::default(),
+        }
     }
 
     pub fn builder() -> TaskManagerBuilder {
-        TaskManagerBuilder { tasks: Vec::new() }
+        TaskManagerBuilder {
+            tasks: Vec::new(),
+            health: Health::default(),
+            health_endpoint: None,
+        }
     }
 
     pub fn add(&mut self, task: impl ManagedTask + 'static) {
         self.tasks.push(Box::new(task));
     }
 
+    /// Health of the tasks of this manager, to add checks to
+    pub fn health(&self) -> &Health {
+        &self.health
+    }
+
+    /// Serve the health of the tasks on `address`, if set
+    pub fn serve_health(&mut self, address: Option<SocketAddr>) {
+        if let Some(address) = address {
+            self.add(HealthServer::new(address, self.health.clone()));
+        }
+    }
+
     pub async fn start(self) -> anyhow::Result<()> {
         let mut sigterm = signal::unix::signal(signal::unix::SignalKind::terminate())?;
         let shutdown = Box::pin(
@@ -91,7 +138,7 @@ impl TaskManager {
     }
 
     async fn do_start(self, mut shutdown: LocalBoxFuture<'static, ()>) -> anyhow::Result<()> {
-        let mut futures = start_futures(self.tasks);
+        let mut futures = start_futures(self.tasks, &self.health);
 
         loop {
             if futures.is_empty() {
@@ -126,19 +173,53 @@ impl TaskManagerBuilder {
         self
     }
 
+    /// Add a check to the health of the tasks
+    pub fn health_check<F>(self, name: impl Into<String>, check: F) -> Self
+    where
+        F: Fn() -> CheckStatus + Send + Sync + 'static,
+    {
+        self.health.add_check(name, check);
+        self
+    }
+
+    /// Serve the health of the tasks on `address`, if set
+    pub fn health_endpoint(mut self, address: Option<SocketAddr>) -> Self {
+        self.health_endpoint = address;
+        self
+    }
+
     pub fn build(self) -> TaskManager {
-        TaskManager { tasks: self.tasks }
+        let mut task_manager = TaskManager {
+            tasks: self.tasks,
+            health: self.health,
+        };
+        task_manager.serve_health(self.health_endpoint);
+        task_manager
     }
 }
 
-fn start_futures(tasks: Vec<Box<dyn ManagedTask>>) -> Vec<StoppableLocalFuture> {
+fn start_futures(tasks: Vec<Box<dyn ManagedTask>>, health: &Health) -> Vec<StoppableLocalFuture> {
     tasks
         .into_iter()
         .map(|task| {
             let (trigger, listener) = triggered::trigger();
+            let id = health.start_task(task.name(), task.health_check());
+            if let Some(nested) = task.nested_health() {
+                health.add_nested(nested);
+            }
+            let health = health.clone();
+            let future = task.start_task(listener).inspect(move |result| {
+                let state = match result {
+                    Ok(()) => TaskState::Stopped,
+                    Err(err) => TaskState::Failed {
+                        error: err.to_string(),
+                    },
+                };
+                health.set_state(id, state);
+            });
             StoppableLocalFuture {
                 shutdown_trigger: trigger,
-                future: task.start_task(listener),
+                future: Box::pin(future),
             }
         })
         .collect()
@@ -360,4 +441,43 @@ mod tests {
         assert_eq!(Some("task-1"), receiver.recv().await);
         assert!(result.is_err());
     }
+
+    #[tokio::test]
+    async fn tracks_task_states_in_health() {
+        let (sender, mut receiver) = mpsc::channel(5);
+
+        let task_manager = TaskManager::builder()
+            .add_task(TestTask {
+                name: "1",
+                delay: 1000,
+                result: Ok(()),
+                sender: sender.clone(),
+            })
+            .add_task(TestTask {
+                name: "2",
+                delay: 50,
+                result: Err(anyhow!("error")),
+                sender: sender.clone(),
+            })
+            .health_check("check", || CheckStatus::healthy("ok"))
+            .build();
+        let health = task_manager.health().clone();
+
+        let result = task_manager.start().await;
+        assert!(result.is_err());
+        assert_eq!(Some("2"), receiver.recv().await);
+        assert_eq!(Some("1"), receiver.recv().await);
+
+        let report = health.report();
+        assert!(!report.live);
+        assert!(!report.ready);
+        assert_eq!(TaskState::Stopped, report.tasks[0].state);
+        assert_eq!(
+            TaskState::Failed {
+                error: "error".to_string()
+            },
+            report.tasks[1].state
+        );
+        assert!(report.checks[0].status.healthy);
+    }
 }